@@ -0,0 +1,305 @@
+// Items that take more than their key to remove. The rules live in a table
+// beside the shards, under a lock of its own that is only ever taken with the
+// key's shard already locked, and each rule holds a weak pointer to the slot
+// it was made for. A rule only counts while that slot is the one stored under
+// its key, so a removal that doesn't look at the table, like drain, can't
+// leave a rule behind for the next item added under the same key, and the
+// table can be tidied lazily. A count of the rules lets vaults with none skip
+// the lock altogether.
+//
+// The second key of a dual item, and the read key of an item with roles,
+// isn't stored in the shards at all, only here, as standing in for the item
+// under its first key; the rule of the first names it in turn, so that it
+// only counts while the two agree. An alias is kept here the same way, but
+// an item can have any number of them, so each alias holds a weak pointer to
+// the item's slot instead, and only counts while that slot is the one stored
+// under the item's key. Removing the item leaves its aliases dangling.
+// A reserved key has a record of its own until it is fulfilled or let go of,
+// which keeps every other way of adding under it out; clearing the vault
+// leaves it, as the reservation outlives the items.
+
+use crate::{Slot, VaultKey, WeakSlot};
+use crate::lock::Lock;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "passphrases")]
+use sha2::{Digest, Sha256};
+
+/// The ways getting at an item that needs more than its key can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AccessError {
+    /// There is no item with the key, or the passphrase doesn't match. Which of the two isn't told, so that a wrong passphrase gives away nothing about the key.
+    Denied,
+    /// The item is locked with a passphrase, and can only be removed with `Vault::remove_locked`.
+    PassphraseRequired,
+    /// The item was added with `Vault::add_dual`, and can only be removed with both of its keys, by `Vault::remove_dual`.
+    BothKeysRequired,
+    /// The key is the `ReadKey` of an item added with `Vault::add_with_roles`, which can look at the item but not change or remove it.
+    ReadOnly,
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccessError::Denied => write!(f, "the key or the passphrase is wrong"),
+            AccessError::PassphraseRequired => write!(f, "the item is locked with a passphrase"),
+            AccessError::BothKeysRequired => write!(f, "the item can only be removed with both of its keys"),
+            AccessError::ReadOnly => write!(f, "the key can only read the item"),
+        }
+    }
+}
+
+impl Error for AccessError {}
+
+// What it takes to remove an item besides its key, or which other key may
+// read it.
+#[derive(Clone, Copy)]
+pub(crate) enum Rule {
+    #[cfg_attr(not(feature = "passphrases"), allow(dead_code))]
+    Passphrase {salt: [u8; 16], hash: [u8; 32]},
+    Dual {second: VaultKey},
+    Roles {reader: VaultKey},
+}
+
+impl Rule {
+    // The error for trying to remove the item with its key alone, if that
+    // isn't allowed.
+    pub(crate) fn refusal(&self) -> Option<AccessError> {
+        match self {
+            Rule::Passphrase {..} => Some(AccessError::PassphraseRequired),
+            Rule::Dual {..} => Some(AccessError::BothKeysRequired),
+            Rule::Roles {..} => None,
+        }
+    }
+
+    // The key that stands in for the item's own, if there is one, and the
+    // error for trying to remove the item with it.
+    fn stand_in(&self) -> Option<(VaultKey, AccessError)> {
+        match self {
+            Rule::Passphrase {..} => None,
+            Rule::Dual {second} => Some((*second, AccessError::BothKeysRequired)),
+            Rule::Roles {reader} => Some((*reader, AccessError::ReadOnly)),
+        }
+    }
+}
+
+#[cfg(feature = "passphrases")]
+impl Rule {
+    pub(crate) fn passphrase(passphrase: &[u8]) -> Rule {
+        let salt = crate::keygen::random_bits().to_be_bytes();
+        Rule::Passphrase {salt, hash: digest(&salt, passphrase)}
+    }
+
+    pub(crate) fn admits(&self, passphrase: &[u8]) -> bool {
+        match self {
+            Rule::Passphrase {salt, hash} => same(hash, &digest(salt, passphrase)),
+            Rule::Dual {..} => false,
+            Rule::Roles {..} => true,
+        }
+    }
+
+    // Does the same work as checking a passphrase against a rule, for a key
+    // with no item, so that how long a refusal takes doesn't tell the two
+    // apart.
+    pub(crate) fn refuse(passphrase: &[u8]) {
+        std::hint::black_box(Rule::Passphrase {salt: [0; 16], hash: [0; 32]}.admits(passphrase));
+    }
+}
+
+// A single round of SHA-256 over the salt and the passphrase. That is enough
+// to keep the passphrase out of memory, but it isn't a slow password hash,
+// so it does little against guessing a weak passphrase from a stolen hash.
+#[cfg(feature = "passphrases")]
+fn digest(salt: &[u8; 16], passphrase: &[u8]) -> [u8; 32] {
+    Sha256::new().chain_update(salt).chain_update(passphrase).finalize().into()
+}
+
+// Looks at every byte whatever the first difference, so that how long the
+// comparison takes says nothing about where the hashes differ.
+#[cfg(feature = "passphrases")]
+fn same(left: &[u8; 32], right: &[u8; 32]) -> bool {
+    let difference = left.iter().zip(right).fold(0, |difference, (left, right)| difference | (left ^ right));
+    std::hint::black_box(difference) == 0
+}
+
+enum Record<T> {
+    // The rule for the item stored under the key, in the slot.
+    Guarded {slot: WeakSlot<T>, rule: Rule},
+    // The key stands in for the item stored under the first.
+    StandIn {first: VaultKey, refusal: AccessError},
+    // The key is an alias of the item stored under the first, in the slot.
+    Alias {first: VaultKey, slot: WeakSlot<T>},
+    // The key is reserved, and has no item yet.
+    Reserved,
+}
+
+pub(crate) struct Access<T> {
+    rules: Mutex<HashMap<VaultKey, Record<T>>>,
+    count: AtomicUsize,
+}
+
+impl<T> Access<T> {
+    pub(crate) fn new() -> Access<T> {
+        Access {rules: Mutex::new(HashMap::new()), count: AtomicUsize::new(0)}
+    }
+
+    // Called with the key's shard locked, like the rest, except where noted.
+    pub(crate) fn protect(&self, key: VaultKey, slot: &Slot<T>, rule: Rule) {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((stand_in, refusal)) = rule.stand_in() {
+            rules.insert(stand_in, Record::StandIn {first: key, refusal});
+        }
+        rules.insert(key, Record::Guarded {slot: Arc::downgrade(slot), rule});
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    // Whether the key stands in for an item's own, or once did, is an alias
+    // that may still be in use, or is reserved: whether anything but its
+    // reservation is kept from adding an item under it.
+    pub(crate) fn is_stand_in(&self, key: &VaultKey) -> bool {
+        self.stands_in_for(key).is_some() || self.alias_of(key).is_some() || self.is_reserved(key)
+    }
+
+    // Called with the key's shard locked, and no item under it.
+    pub(crate) fn reserve(&self, key: VaultKey) {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        rules.insert(key, Record::Reserved);
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    fn is_reserved(&self, key: &VaultKey) -> bool {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+        matches!(self.rules.lock().unwrap_or_else(PoisonError::into_inner).get(key), Some(Record::Reserved))
+    }
+
+    // Drops the reservation of the key, as it is fulfilled or let go of.
+    pub(crate) fn unreserve(&self, key: &VaultKey) {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(Record::Reserved) = rules.get(key) {
+            rules.remove(key);
+        }
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    pub(crate) fn alias(&self, alias: VaultKey, first: VaultKey, slot: &Slot<T>) {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        rules.insert(alias, Record::Alias {first, slot: Arc::downgrade(slot)});
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    // The key of the item the alias is for, and a pointer to its slot. Needs
+    // no lock; the caller checks with the first key's shard locked that the
+    // slot is still the one stored under it. An alias whose item is gone for
+    // good is dropped.
+    pub(crate) fn alias_of(&self, key: &VaultKey) -> Option<(VaultKey, *const Lock<Option<T>>)> {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        match rules.get(key)? {
+            Record::Alias {first, slot} if slot.strong_count() > 0 => return Some((*first, slot.as_ptr())),
+            Record::Alias {..} => {}
+            Record::Guarded {..} | Record::StandIn {..} | Record::Reserved => return None,
+        }
+        rules.remove(key);
+        self.count.store(rules.len(), Ordering::Relaxed);
+        None
+    }
+
+    // Drops the alias, if the key is one.
+    pub(crate) fn unlink(&self, key: &VaultKey) {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(Record::Alias {..}) = rules.get(key) {
+            rules.remove(key);
+        }
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    // The key a stand-in key stands in for, and the error for trying to
+    // remove the item with it. Needs no lock; the caller checks with the
+    // first key's shard locked that the two still name each other.
+    pub(crate) fn stands_in_for(&self, key: &VaultKey) -> Option<(VaultKey, AccessError)> {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        match self.rules.lock().unwrap_or_else(PoisonError::into_inner).get(key)? {
+            Record::StandIn {first, refusal} => Some((*first, *refusal)),
+            Record::Guarded {..} | Record::Alias {..} | Record::Reserved => None,
+        }
+    }
+
+    // The rule for the item in the slot stored under the key, if it has one.
+    // A rule left over from an earlier item under the key is dropped.
+    pub(crate) fn rule(&self, key: &VaultKey, slot: &Slot<T>) -> Option<Rule> {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        match rules.get(key)? {
+            Record::Guarded {slot: guarded, rule} if guarded.as_ptr() == Arc::as_ptr(slot) => return Some(*rule),
+            Record::Guarded {..} => {}
+            // An item added under a stand-in key or an alias after all; the
+            // record is only looked at while the key has no item.
+            Record::StandIn {..} | Record::Alias {..} | Record::Reserved => return None,
+        }
+        rules.remove(key);
+        self.count.store(rules.len(), Ordering::Relaxed);
+        None
+    }
+
+    // Whether the item in the slot can be removed with the key alone, or
+    // why not.
+    pub(crate) fn refusal(&self, key: &VaultKey, slot: &Slot<T>) -> Option<AccessError> {
+        self.rule(key, slot)?.refusal()
+    }
+
+    // Drops the key's rule, and the key that stands in for it, as its item
+    // is removed.
+    pub(crate) fn release(&self, key: &VaultKey) {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(Record::Guarded {rule, ..}) = rules.get(key) {
+            if let Some((stand_in, _)) = rule.stand_in() {
+                rules.remove(&stand_in);
+            }
+            rules.remove(key);
+        }
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    // Moves the rules of items that are moving from one key to another, all
+    // at once so that the moves may swap keys around. Stand-in keys and aliases
+    // stay as they are, and stand in for their items' new keys.
+    pub(crate) fn rename<I>(&self, moves: I)
+            where I: IntoIterator<Item = (VaultKey, VaultKey)> {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        let moves: HashMap<VaultKey, VaultKey> = moves.into_iter().collect();
+        let moved: Vec<(VaultKey, Record<T>)> = moves.iter()
+            .filter_map(|(old, new)| rules.remove(old).map(|record| (*new, record)))
+            .collect();
+        rules.extend(moved);
+        for record in rules.values_mut() {
+            if let Record::StandIn {first, ..} | Record::Alias {first, ..} = record {
+                *first = moves.get(first).copied().unwrap_or(*first);
+            }
+        }
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+
+    // Called with every shard locked, as the vault is emptied.
+    pub(crate) fn release_all(&self) {
+        let mut rules = self.rules.lock().unwrap_or_else(PoisonError::into_inner);
+        rules.retain(|_, record| matches!(record, Record::Reserved));
+        self.count.store(rules.len(), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,355 @@
+// A vault for async code. Its lock is tokio's, so a task waiting for the
+// vault yields to the executor instead of blocking its thread. Operations are
+// synchronous once the lock is held and never hold it across an await.
+//
+// Tasks waiting for a key to be filled each hold a registration for it,
+// which shares one Notify among the key's waiters and unregisters itself on
+// drop, so a cancelled wait leaves nothing behind.
+//
+// Changes are announced on a broadcast channel while the change's lock is
+// still held, so subscribers see them in the order they were made.
+
+use crate::VaultKey;
+use std::collections::{hash_map, HashMap};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::sync::{broadcast, Notify, RwLock};
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+// How many events a subscriber can fall behind by before it loses the oldest.
+const EVENT_CAPACITY: usize = 256;
+
+/// A change to an `AsyncVault`, as delivered by `AsyncVault::subscribe`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VaultEvent {
+    /// An item was added under the key.
+    Added(VaultKey),
+    /// The item under the key was removed.
+    Removed(VaultKey),
+    /// The item under the key was updated.
+    Updated(VaultKey),
+    /// Every item was removed.
+    Cleared,
+    /// The subscriber fell too far behind, and this many of the oldest events were dropped before it could see them.
+    Lagged(u64),
+}
+
+/// A collection that hides its contents behind unique keys, like `Vault`, for use from async code. Every method awaits the vault's lock rather than blocking the thread on it. Closures passed in run with the lock held, so they are synchronous and should be quick. Keys are the same `VaultKey`s that `Vault` uses.
+pub struct AsyncVault<T> {
+    items: RwLock<HashMap<VaultKey, T>>,
+    // Keys being waited for, with how many tasks wait on each. Only ever
+    // locked briefly, never across an await.
+    waiters: Mutex<HashMap<VaultKey, Waiters>>,
+    events: broadcast::Sender<VaultEvent>,
+}
+
+struct Waiters {
+    notify: Arc<Notify>,
+    count: usize,
+}
+
+struct Registration<'a> {
+    waiters: &'a Mutex<HashMap<VaultKey, Waiters>>,
+    key: VaultKey,
+    notify: Arc<Notify>,
+}
+
+impl<'a> Drop for Registration<'a> {
+    fn drop(&mut self) {
+        let mut waiters = lock_waiters(self.waiters);
+        if let hash_map::Entry::Occupied(mut entry) = waiters.entry(self.key) {
+            entry.get_mut().count -= 1;
+            if entry.get().count == 0 {
+                entry.remove();
+            }
+        }
+    }
+}
+
+fn lock_waiters(waiters: &Mutex<HashMap<VaultKey, Waiters>>) -> MutexGuard<'_, HashMap<VaultKey, Waiters>> {
+    waiters.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T> AsyncVault<T> {
+    /// Creates a new, empty AsyncVault instance.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::AsyncVault;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn new() -> AsyncVault<T> {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        AsyncVault {items: RwLock::new(HashMap::new()), waiters: Mutex::new(HashMap::new()), events}
+    }
+
+    /// Adds an object to the vault and returns a key.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::AsyncVault;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// 
+    /// let key = vault.add(1).await;
+    /// assert_eq!(true, vault.has_item(&key).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn add(&self, to_add: T) -> VaultKey {
+        let key = VaultKey::new();
+        let mut items = self.items.write().await;
+        items.insert(key, to_add);
+        self.announce(VaultEvent::Added(key));
+        key
+    }
+
+    /// Removes and returns the stored object with a matching key, if it exists, otherwise returns None.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::AsyncVault;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let key = vault.add(1).await;
+    /// 
+    /// assert_eq!(Some(1), vault.remove(&key).await);
+    /// assert_eq!(None, vault.remove(&key).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn remove(&self, key: &VaultKey) -> Option<T> {
+        let mut items = self.items.write().await;
+        let removed = items.remove(key)?;
+        self.announce(VaultEvent::Removed(*key));
+        Some(removed)
+    }
+
+    /// Returns true if there exists an item in the vault with the provided key, otherwise returns false.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{AsyncVault, VaultKey};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let key = vault.add(1).await;
+    /// 
+    /// assert_eq!(true, vault.has_item(&key).await);
+    /// assert_eq!(false, vault.has_item(&VaultKey::new()).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn has_item(&self, key: &VaultKey) -> bool {
+        self.items.read().await.contains_key(key)
+    }
+
+    /// Adds an item to the vault with the specified key. If the key already is in use, the item is not added and this returns false. If the key is not already in use, the item is added and returns true.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{AsyncVault, VaultKey};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// assert_eq!(true, vault.add_with_key(1, &key).await);
+    /// assert_eq!(false, vault.add_with_key(2, &key).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn add_with_key(&self, to_add: T, key: &VaultKey) -> bool {
+        let added = match self.items.write().await.entry(*key) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(to_add);
+                self.announce(VaultEvent::Added(*key));
+                true
+            }
+        };
+        // Only keys chosen in advance can have waiters, so add, which makes
+        // a fresh key, needn't look.
+        if added {
+            if let Some(waiters) = lock_waiters(&self.waiters).get(key) {
+                waiters.notify.notify_waiters();
+            }
+        }
+        added
+    }
+
+    /// Waits for an item to be added under the provided key, then removes and returns it. If there is one already, it is returned straight away. Dropping the future before it is ready stops the wait and leaves the vault as it was. If several tasks wait on the same key, each item added goes to one of them.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::Arc;
+    /// # use bank_vault::{AsyncVault, VaultKey};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Arc::new(AsyncVault::<&str>::new());
+    /// let request = VaultKey::new();
+    /// 
+    /// let responder = {
+    ///     let vault = Arc::clone(&vault);
+    ///     tokio::spawn(async move { vault.add_with_key("response", &request).await })
+    /// };
+    /// assert_eq!("response", vault.wait_for_item(&request).await);
+    /// # responder.await?;
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn wait_for_item(&self, key: &VaultKey) -> T {
+        let registration = self.register(key);
+        loop {
+            // Enabled before looking at the vault, so an item added after the
+            // look still wakes this task.
+            let notified = registration.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            if let Some(item) = self.remove(key).await {
+                return item;
+            }
+            notified.await;
+        }
+    }
+
+    /// Waits for an item to be added under the provided key, like `wait_for_item`, but gives up and returns None once the timeout runs out.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use bank_vault::{AsyncVault, VaultKey};
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let key = vault.add(1).await;
+    /// 
+    /// assert_eq!(Some(1), vault.wait_for_item_timeout(&key, Duration::from_secs(1)).await);
+    /// assert_eq!(None, vault.wait_for_item_timeout(&key, Duration::from_millis(10)).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn wait_for_item_timeout(&self, key: &VaultKey, timeout: Duration) -> Option<T> {
+        tokio::time::timeout(timeout, self.wait_for_item(key)).await.ok()
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it. Returns false if an item with the key is not found, otherwise returns true. The operation runs with the vault locked, so it can't await. The item is changed in place, so if the operation panics the item stays in the vault as the operation left it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::AsyncVault;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let key = vault.add(1).await;
+    /// 
+    /// assert_eq!(true, vault.update_item(&key, |i| *i += 1).await);
+    /// assert_eq!(Some(2), vault.remove(&key).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn update_item<F>(&self, key: &VaultKey, operation: F) -> bool
+            where F: FnOnce(&mut T) {
+        let mut items = self.items.write().await;
+        match items.get_mut(key) {
+            Some(item) => {
+                operation(item);
+                self.announce(VaultEvent::Updated(*key));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Clears the contents of the vault.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::AsyncVault;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let key = vault.add(1).await;
+    /// 
+    /// vault.clear().await;
+    /// assert_eq!(false, vault.has_item(&key).await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub async fn clear(&self) {
+        let mut items = self.items.write().await;
+        items.clear();
+        self.announce(VaultEvent::Cleared);
+    }
+
+    /// Returns a stream of the changes made to the vault from now on. Changes never wait for subscribers: one that falls more than 256 events behind loses the oldest of them, and is told how many with a `VaultEvent::Lagged` in their place. Dropping the stream unsubscribes it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{AsyncVault, VaultEvent};
+    /// # use tokio_stream::StreamExt;
+    /// # #[tokio::main(flavor = "current_thread")]
+    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = AsyncVault::<i32>::new();
+    /// let mut events = Box::pin(vault.subscribe());
+    /// 
+    /// let key = vault.add(1).await;
+    /// vault.remove(&key).await;
+    /// assert_eq!(Some(VaultEvent::Added(key)), events.next().await);
+    /// assert_eq!(Some(VaultEvent::Removed(key)), events.next().await);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn subscribe(&self) -> impl Stream<Item = VaultEvent> {
+        BroadcastStream::new(self.events.subscribe()).map(|event| match event {
+            Ok(event) => event,
+            Err(BroadcastStreamRecvError::Lagged(missed)) => VaultEvent::Lagged(missed),
+        })
+    }
+
+    // Sending fails only when nobody is subscribed, which is fine.
+    fn announce(&self, event: VaultEvent) {
+        let _ = self.events.send(event);
+    }
+
+    fn register(&self, key: &VaultKey) -> Registration<'_> {
+        let mut waiters = lock_waiters(&self.waiters);
+        let entry = waiters.entry(*key).or_insert_with(|| Waiters {notify: Arc::new(Notify::new()), count: 0});
+        entry.count += 1;
+        Registration {waiters: &self.waiters, key: *key, notify: Arc::clone(&entry.notify)}
+    }
+
+    #[cfg(test)]
+    pub(crate) fn waiting_keys(&self) -> usize {
+        lock_waiters(&self.waiters).len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn subscribers(&self) -> usize {
+        self.events.receiver_count()
+    }
+}
+
+impl<T> Default for AsyncVault<T> {
+    fn default() -> AsyncVault<T> {
+        AsyncVault::new()
+    }
+}
@@ -0,0 +1,144 @@
+// Vault::builder, which sets up a vault from any mix of the options the
+// with_ constructors each give on their own; those are made by it. A cap is
+// kept as a finished Limit, the last of max_items and max_weight winning,
+// and the eviction policy put on it once the builder is done, as either cap
+// may come after it.
+
+use crate::{shard, EvictionPolicy, KeyGenerator, MasterKey, RandomKeyGenerator, Vault, VaultKey};
+use crate::access::Access;
+use crate::limit::{Limit, OnEvict};
+use crate::lock::Lock;
+use crate::names::Names;
+use crate::watch::Watches;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize};
+
+/// Sets up a new vault, made by `Vault::builder`. Each option is the same as the `Vault` constructor named after it, such as `with_shards` for `shards`, and any of them can be combined; those left out are as for `Vault::new`.
+/// # Example
+/// 
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::{SequentialKeyGenerator, Vault, VaultError, VaultKey};
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let vault = Vault::<i32>::builder()
+///     .shards(4)
+///     .capacity(100)
+///     .max_items(2)
+///     .key_generator(SequentialKeyGenerator::new())
+///     .build();
+/// 
+/// assert_eq!(VaultKey::from_u128(1), vault.add(1));
+/// assert_eq!(VaultKey::from_u128(2), vault.add(2));
+/// assert_eq!(Err(VaultError::Full), vault.add_checked(3));
+/// #     Ok(())
+/// # }
+/// ```
+pub struct VaultBuilder<T> {
+    shards: usize,
+    capacity: usize,
+    fair: bool,
+    limit: Option<Limit<T>>,
+    eviction: Option<(Box<dyn EvictionPolicy<T>>, OnEvict<T>)>,
+    keys: Box<dyn KeyGenerator + Send + Sync>,
+}
+
+impl<T> VaultBuilder<T> {
+    /// Creates a builder with every option as for `Vault::new`.
+    pub fn new() -> VaultBuilder<T> {
+        VaultBuilder {shards: shard::default_count(), capacity: 0, fair: false, limit: None, eviction: None, keys: Box::new(RandomKeyGenerator)}
+    }
+
+    /// Splits the vault's items across the given number of shards, as for `Vault::with_shards`. A count of zero is treated as one.
+    pub fn shards(self, shards: usize) -> VaultBuilder<T> {
+        VaultBuilder {shards: shards.max(1), ..self}
+    }
+
+    /// Makes room for at least the given number of items up front, spread evenly across the shards, as for `Vault::with_capacity`.
+    pub fn capacity(self, capacity: usize) -> VaultBuilder<T> {
+        VaultBuilder {capacity, ..self}
+    }
+
+    /// Hands out the vault's locks in the order they are asked for, as for `Vault::new_fair`.
+    pub fn fair(self) -> VaultBuilder<T> {
+        VaultBuilder {fair: true, ..self}
+    }
+
+    /// Caps the number of items the vault holds, as for `Vault::with_max_items`. This replaces a cap set by `max_weight`.
+    pub fn max_items(self, max_items: usize) -> VaultBuilder<T> {
+        VaultBuilder {limit: Some(Limit::new(max_items)), ..self}
+    }
+
+    /// Caps the total weight of the vault's items, as for `Vault::with_max_weight`. This replaces a cap set by `max_items`.
+    pub fn max_weight<W>(self, max_weight: usize, weigher: W) -> VaultBuilder<T>
+            where W: Fn(&T) -> usize + Send + Sync + 'static {
+        VaultBuilder {limit: Some(Limit::weighted(max_weight, weigher)), ..self}
+    }
+
+    /// Makes space in the full vault by evicting the items the policy chooses, as for `Vault::with_eviction`, or `Vault::with_weighted_eviction` along with `max_weight`. It takes a cap, from `max_items` or `max_weight`, to evict down to.
+    pub fn eviction<F>(self, policy: Box<dyn EvictionPolicy<T>>, on_evict: F) -> VaultBuilder<T>
+            where F: Fn(VaultKey, T) + Send + Sync + 'static {
+        VaultBuilder {eviction: Some((policy, Box::new(on_evict))), ..self}
+    }
+
+    /// Draws the keys for new items from the generator, as for `Vault::with_key_generator`.
+    pub fn key_generator<G>(self, keys: G) -> VaultBuilder<T>
+            where G: KeyGenerator + Send + Sync + 'static {
+        VaultBuilder {keys: Box::new(keys), ..self}
+    }
+
+    /// Creates the vault. Panics if `eviction` was set without `max_items` or `max_weight`.
+    pub fn build(self) -> Vault<T> {
+        self.build_with(None)
+    }
+
+    /// Creates the vault along with its master key, as for `Vault::with_master_key`. Panics like `build`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let (vault, master) = Vault::<i32>::builder().max_items(10).build_with_master_key();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Ok(Some(1)), vault.master_remove(&master, &key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn build_with_master_key(self) -> (Vault<T>, MasterKey) {
+        let master = MasterKey::new();
+        (self.build_with(Some(master.nonce())), master)
+    }
+
+    // The options that don't look at the items, for a vault of another type
+    // made with this one's, like a SpillVault's vault of entries. A cap on
+    // weight or an eviction policy would look at the items, so neither can
+    // be carried over.
+    #[cfg(feature = "spill")]
+    pub(crate) fn retyped<U>(self) -> VaultBuilder<U> {
+        let weighed = self.limit.as_ref().is_some_and(|limit| limit.max_weight().is_some());
+        assert!(!weighed && self.eviction.is_none(), "a vault that spills can't have max_weight or eviction set");
+        let limit = self.limit.and_then(|limit| limit.max_items()).map(Limit::new);
+        VaultBuilder {shards: self.shards, capacity: self.capacity, fair: self.fair, limit, eviction: None, keys: self.keys}
+    }
+
+    fn build_with(self, master: Option<u128>) -> Vault<T> {
+        let per_shard = self.capacity.div_ceil(self.shards);
+        let shards = (0..self.shards).map(|_| {
+            let items = HashMap::with_capacity(per_shard);
+            if self.fair { Lock::fair(items) } else { Lock::new(items) }
+        }).collect();
+        let limit = match (self.limit, self.eviction) {
+            (Some(limit), Some((policy, on_evict))) => Some(limit.evicting(policy, on_evict)),
+            (None, Some(_)) => panic!("a vault can only evict with max_items or max_weight set"),
+            (limit, None) => limit,
+        };
+        Vault {shards, count: AtomicUsize::new(0), watches: Watches::new(), keys: self.keys, generation: AtomicU64::new(0), master, access: Access::new(), names: Names::new(), limit}
+    }
+}
+
+impl<T> Default for VaultBuilder<T> {
+    fn default() -> VaultBuilder<T> {
+        VaultBuilder::new()
+    }
+}
@@ -0,0 +1,63 @@
+// The keys of the compact-keys feature: 64-bit numbers, made without the uuid
+// crate. A new key is the next value of a counter shared by the whole
+// process, scrambled together with a seed picked at random the first time a
+// key is made. The scramble is a bijection, so a process never makes the same
+// key twice, not until it has made 2^64 of them; but keys made by different
+// processes are only as unlikely to collide as any two random 64-bit numbers,
+// which is why compact keys are unique within a process and no further.
+
+use crate::keygen::{mix, STEP};
+use crate::Invalid;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+static SEED: OnceLock<u64> = OnceLock::new();
+
+pub(crate) fn new_key() -> u64 {
+    let seed = *SEED.get_or_init(random_seed);
+    loop {
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let key = mix(seed.wrapping_add(count.wrapping_mul(STEP)));
+        // Zero is VaultKey::zero(), which a new key never is.
+        if key != 0 {
+            return key;
+        }
+    }
+}
+
+// The standard library seeds its hashers from the operating system's random
+// numbers, which saves depending on a crate for them.
+fn random_seed() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_nanos()));
+    hasher.finish()
+}
+
+// Parses the simple, hyphenated and urn forms of a UUID, as the uuid crate
+// does, to the number they spell.
+pub(crate) fn parse(text: &str) -> Result<u128, Invalid> {
+    let text = text.strip_prefix("urn:uuid:").unwrap_or(text);
+    let hyphenated = text.len() == 36;
+    let mut value = 0;
+    let mut digits = 0;
+    for (index, character) in text.char_indices() {
+        if hyphenated && [8, 13, 18, 23].contains(&index) {
+            if character != '-' {
+                return Err(Invalid::Format);
+            }
+            continue;
+        }
+        let digit = character.to_digit(16).ok_or(Invalid::Format)?;
+        value = value << 4 | u128::from(digit);
+        digits += 1;
+    }
+    if digits == 32 {
+        Ok(value)
+    } else {
+        Err(Invalid::Format)
+    }
+}
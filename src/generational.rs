@@ -0,0 +1,143 @@
+// Keys that go stale when their vault is cleared. A vault's generation moves
+// on by one, with every shard locked, whenever the vault is emptied, and a
+// GenerationalKey carries the generation it was handed out in. Lookups check
+// it with the key's shard locked, so a key from before a clear never finds an
+// item added after it, even one added under the same VaultKey, and clearing
+// makes every outstanding key stale at once, at the cost of one increment.
+
+use crate::{Vault, VaultKey};
+
+/// A `VaultKey` together with the generation of the vault it was handed out in, as returned by `GenerationalVault::add`. Once the vault is cleared the key is stale, and the vault treats it as missing, whatever is later added under the same `VaultKey`.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct GenerationalKey {
+    key: VaultKey,
+    generation: u64,
+}
+
+impl GenerationalKey {
+    /// Returns the key without its generation, for use with a plain `Vault`. The untyped key doesn't go stale.
+    pub fn key(&self) -> VaultKey {
+        self.key
+    }
+
+    /// Returns the generation of the vault the key was handed out in.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// A `Vault` that hands out `GenerationalKey`s, which stop resolving once the vault is cleared. Without it, a key kept from before a `clear` finds whatever item is later added under the same `VaultKey`, as can happen with keys made from ids used elsewhere; with it, old keys are simply missing. The vault behind it is available through `as_untyped`; clearing that clears this one too, and makes its keys stale in the same way.
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::GenerationalVault;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let vault = GenerationalVault::new();
+/// let old = vault.add("before");
+/// 
+/// vault.clear();
+/// let new = vault.add_with_key("after", &old.key()).unwrap();
+/// 
+/// assert_eq!(None, vault.get_clone(&old));
+/// assert_eq!(Some("after"), vault.get_clone(&new));
+/// #     Ok(())
+/// # }
+/// ```
+pub struct GenerationalVault<T> {
+    vault: Vault<T>,
+}
+
+impl<T> GenerationalVault<T> {
+    /// Creates a new, empty GenerationalVault instance.
+    pub fn new() -> GenerationalVault<T> {
+        GenerationalVault {vault: Vault::new()}
+    }
+
+    /// Returns the vault behind this one.
+    pub fn as_untyped(&self) -> &Vault<T> {
+        &self.vault
+    }
+
+    /// Consumes this vault and returns the vault behind it.
+    pub fn into_untyped(self) -> Vault<T> {
+        self.vault
+    }
+
+    /// Adds an object to the vault and returns its key, in the vault's current generation.
+    pub fn add(&self, to_add: T) -> GenerationalKey {
+        let (key, generation) = self.vault.add_in_generation(to_add);
+        GenerationalKey {key, generation}
+    }
+
+    /// Adds an object to the vault under the provided key, like `Vault::add_with_key`, and returns the key in the vault's current generation. Returns None, without adding it, if the key is already in use.
+    pub fn add_with_key(&self, to_add: T, key: &VaultKey) -> Option<GenerationalKey> {
+        let generation = self.vault.add_with_key_in_generation(to_add, key)?;
+        Some(GenerationalKey {key: *key, generation})
+    }
+
+    /// Removes and returns the stored object with a matching key, like `Vault::remove`. Returns None if the key is stale.
+    pub fn remove(&self, key: &GenerationalKey) -> Option<T> {
+        self.vault.remove_in_generation(&key.key, Some(key.generation))
+    }
+
+    /// Returns whether there exists an item in the vault with the provided key, like `Vault::has_item`. Returns false if the key is stale.
+    pub fn has_item(&self, key: &GenerationalKey) -> bool {
+        self.vault.has_item_in_generation(&key.key, Some(key.generation))
+    }
+
+    /// Returns a clone of the stored object with a matching key, like `Vault::get_clone`. Returns None if the key is stale.
+    pub fn get_clone(&self, key: &GenerationalKey) -> Option<T>
+            where T: Clone {
+        self.with_item(key, T::clone)
+    }
+
+    /// Applies the operation to a reference to the stored object with a matching key, like `Vault::with_item`. Returns None without calling the operation if the key is stale.
+    pub fn with_item<R, F>(&self, key: &GenerationalKey, operation: F) -> Option<R>
+            where F: FnOnce(&T) -> R {
+        self.vault.with_item_in_generation(&key.key, Some(key.generation), "GenerationalVault::with_item", operation)
+    }
+
+    /// Applies the operation to a mutable reference to the stored object with a matching key, like `Vault::with_item_mut`. Returns None without calling the operation if the key is stale.
+    pub fn with_item_mut<R, F>(&self, key: &GenerationalKey, operation: F) -> Option<R>
+            where F: FnOnce(&mut T) -> R {
+        self.vault.modify(&key.key, Some(key.generation), "GenerationalVault::with_item_mut", operation)
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it, like `Vault::update_item`. Returns false without calling the operation if the key is stale.
+    pub fn update_item<F>(&self, key: &GenerationalKey, operation: F) -> bool
+            where F: FnOnce(&mut T) {
+        self.vault.update_item_in_generation(&key.key, Some(key.generation), "GenerationalVault::update_item", operation)
+    }
+
+    /// Removes every item from the vault and makes every key handed out so far stale.
+    pub fn clear(&self) {
+        self.vault.clear()
+    }
+
+    /// Returns the vault's current generation, the one new keys are handed out in.
+    pub fn generation(&self) -> u64 {
+        self.vault.generation()
+    }
+
+    /// Returns the number of items in the vault.
+    pub fn len(&self) -> usize {
+        self.vault.len()
+    }
+
+    /// Returns whether the vault holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.vault.is_empty()
+    }
+}
+
+impl<T> Default for GenerationalVault<T> {
+    fn default() -> GenerationalVault<T> {
+        GenerationalVault::new()
+    }
+}
+
+impl<T> From<Vault<T>> for GenerationalVault<T> {
+    fn from(vault: Vault<T>) -> GenerationalVault<T> {
+        GenerationalVault {vault}
+    }
+}
@@ -0,0 +1,53 @@
+// Serde support for VaultKey, in the same shape as the uuid crate's: the
+// hyphenated string in human-readable formats such as JSON, and the 16 bytes
+// of the UUID in binary formats such as bincode.
+
+use crate::VaultKey;
+use serde::de::{self, Deserializer, Visitor};
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+
+impl Serialize for VaultKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where S: Serializer {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_bytes(&self.to_bytes())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VaultKey {
+    fn deserialize<D>(deserializer: D) -> Result<VaultKey, D::Error>
+            where D: Deserializer<'de> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(KeyVisitor)
+        } else {
+            deserializer.deserialize_bytes(KeyVisitor)
+        }
+    }
+}
+
+struct KeyVisitor;
+
+impl<'de> Visitor<'de> for KeyVisitor {
+    type Value = VaultKey;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a vault key, as a UUID string or 16 bytes")
+    }
+
+    fn visit_str<E>(self, text: &str) -> Result<VaultKey, E>
+            where E: de::Error {
+        text.parse().map_err(E::custom)
+    }
+
+    fn visit_bytes<E>(self, bytes: &[u8]) -> Result<VaultKey, E>
+            where E: de::Error {
+        if bytes.len() != 16 {
+            return Err(E::invalid_length(bytes.len(), &"16 bytes"));
+        }
+        VaultKey::try_from_slice(bytes).map_err(E::custom)
+    }
+}
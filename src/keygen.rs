@@ -0,0 +1,131 @@
+// Where a vault's new keys come from. Every key a vault makes up for itself,
+// in add and the like, is drawn from its KeyGenerator, which by default gives
+// random v4 keys; the others here give predictable keys for tests and
+// simulations.
+
+use crate::VaultKey;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// How many keys in a row may turn out to be in use before the generator is
+// taken to be broken.
+const ATTEMPTS: usize = 1000;
+
+/// A source of new keys for a vault, set with `Vault::with_key_generator`. Keys should be unique, but a generator that repeats itself can't make a vault lose items: a key already in use is skipped and the next one drawn, the same way `add_with_key` refuses to overwrite an item. A generator that gives a long run of keys that are all in use makes adding panic.
+pub trait KeyGenerator {
+    /// Returns the next key.
+    fn next_key(&self) -> VaultKey;
+}
+
+/// Generates random keys with `VaultKey::new`. This is the generator vaults use unless given another one.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RandomKeyGenerator;
+
+impl KeyGenerator for RandomKeyGenerator {
+    fn next_key(&self) -> VaultKey {
+        VaultKey::new()
+    }
+}
+
+/// Generates the keys `VaultKey::from_u128(1)`, `VaultKey::from_u128(2)` and so on, in order.
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::{KeyGenerator, SequentialKeyGenerator, VaultKey};
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let keys = SequentialKeyGenerator::new();
+/// 
+/// assert_eq!(VaultKey::from_u128(1), keys.next_key());
+/// assert_eq!(VaultKey::from_u128(2), keys.next_key());
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct SequentialKeyGenerator {
+    last: AtomicU64,
+}
+
+impl SequentialKeyGenerator {
+    /// Creates a generator whose first key is `VaultKey::from_u128(1)`.
+    pub fn new() -> SequentialKeyGenerator {
+        SequentialKeyGenerator::default()
+    }
+}
+
+impl KeyGenerator for SequentialKeyGenerator {
+    fn next_key(&self) -> VaultKey {
+        VaultKey::from_u128(u128::from(self.last.fetch_add(1, Ordering::Relaxed)) + 1)
+    }
+}
+
+/// Generates keys that look random but follow from a seed, so that generators with the same seed give the same keys in the same order, in any process on any platform. With UUID keys, the default, the keys are version 4 UUIDs like those from `VaultKey::new`.
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::{KeyGenerator, SeededKeyGenerator};
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let first = SeededKeyGenerator::new(7);
+/// let second = SeededKeyGenerator::new(7);
+/// 
+/// assert_eq!(first.next_key(), second.next_key());
+/// assert_ne!(first.next_key(), SeededKeyGenerator::new(8).next_key());
+/// #     Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct SeededKeyGenerator {
+    state: AtomicU64,
+}
+
+impl SeededKeyGenerator {
+    /// Creates a generator for the seed.
+    pub fn new(seed: u64) -> SeededKeyGenerator {
+        SeededKeyGenerator {state: AtomicU64::new(seed)}
+    }
+}
+
+// SplitMix64: the state steps by a fixed odd amount, and each step's value
+// is scrambled into the output.
+pub(crate) const STEP: u64 = 0x9e37_79b9_7f4a_7c15;
+
+pub(crate) fn mix(mut bits: u64) -> u64 {
+    bits = (bits ^ (bits >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    bits = (bits ^ (bits >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    bits ^ (bits >> 31)
+}
+
+impl KeyGenerator for SeededKeyGenerator {
+    fn next_key(&self) -> VaultKey {
+        // Both steps are taken at once, so keys drawn from several threads
+        // are still whole draws from the sequence.
+        let state = self.state.fetch_add(STEP.wrapping_mul(2), Ordering::Relaxed);
+        let high = mix(state.wrapping_add(STEP));
+        let low = mix(state.wrapping_add(STEP.wrapping_mul(2)));
+        let bits = u128::from(high) << 64 | u128::from(low);
+        // Version 4 and the RFC variant, as in a random UUID.
+        let bits = bits & !(0xf << 76) | 0x4 << 76;
+        let bits = bits & !(0x3 << 62) | 0x2 << 62;
+        VaultKey::from_u128(bits)
+    }
+}
+
+// Draws keys from the generator until place takes one, which it does if the
+// key is free, and returns what place gave back.
+pub(crate) fn fresh_key<R, F>(keys: &dyn KeyGenerator, mut place: F) -> R
+        where F: FnMut(VaultKey) -> Option<R> {
+    for _ in 0..ATTEMPTS {
+        if let Some(placed) = place(keys.next_key()) {
+            return placed;
+        }
+    }
+    panic!("the vault's key generator gave {} keys in a row that were already in use", ATTEMPTS)
+}
+
+// 128 bits that can't be guessed, for master keys and salts. The standard
+// library seeds each RandomState's hasher from the operating system's random
+// numbers.
+pub(crate) fn random_bits() -> u128 {
+    let half = || RandomState::new().build_hasher().finish();
+    u128::from(half()) << 64 | u128::from(half())
+}
@@ -1,14 +1,115 @@
-#![feature(option_unwrap_none)]
+#[cfg(not(any(feature = "uuid-keys", feature = "compact-keys")))]
+compile_error!("bank_vault needs a kind of key: turn on either the uuid-keys feature, which is on by default, or compact-keys");
+
+mod access;
+#[cfg(feature = "async")]
+mod async_vault;
+mod builder;
+#[cfg(feature = "compact-keys")]
+mod compact;
+mod generational;
+#[cfg(feature = "serde")]
+mod key_serde;
+mod keygen;
+mod limit;
+mod lock;
+mod master;
+mod names;
+mod reentry;
+mod reservation;
+mod roles;
+mod shard;
+#[cfg(feature = "signed-tokens")]
+mod signed;
+#[cfg(feature = "spill")]
+mod spill;
+mod token;
+mod typed;
+mod watch;
+
+#[cfg(not(feature = "compact-keys"))]
 use uuid::Uuid;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::iter::{self, FromIterator};
+use std::ops::{Add, Deref, DerefMut, Sub};
+use std::str::FromStr;
+use std::collections::hash_map;
+use std::error::Error;
+use std::vec;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(not(feature = "compact-keys"))]
 use std::sync::Mutex;
+use std::sync::{Arc, PoisonError, TryLockError, TryLockResult};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(any(not(feature = "compact-keys"), feature = "signed-tokens"))]
+use std::time::SystemTime;
+#[cfg(not(feature = "compact-keys"))]
+use std::time::UNIX_EPOCH;
+use access::{Access, Rule};
+use limit::{Evicted, Limit, Room, Victims};
+use lock::{Lock, ReadGuard, WriteGuard};
+use names::Names;
+use reentry::Scope;
+use shard::Shards;
+use watch::Watches;
+
+pub use access::AccessError;
+pub use builder::VaultBuilder;
+pub use generational::{GenerationalKey, GenerationalVault};
+pub use limit::{EvictionPolicy, FifoPolicy, LruPolicy};
+pub use keygen::{KeyGenerator, RandomKeyGenerator, SeededKeyGenerator, SequentialKeyGenerator};
+pub use master::MasterKey;
+pub use reservation::Reservation;
+pub use roles::{OwnerKey, ReadKey};
+pub use typed::{TypedKey, TypedVault};
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+#[cfg(feature = "async")]
+pub use async_vault::{AsyncVault, VaultEvent};
+#[cfg(feature = "signed-tokens")]
+pub use signed::TokenError;
+#[cfg(feature = "spill")]
+pub use spill::{Spillable, SpillVault};
 
-#[derive(Clone, Copy, Hash, PartialEq, Eq, Debug)]
+/// The key an item is kept under in a vault. Keys are ordered by the bytes of their UUIDs, compared in turn, which is not the order they were created in.
+/// 
+/// With the `compact-keys` feature, a key is a 64-bit number instead of a UUID, and the crate doesn't depend on `uuid`. New compact keys are unique within the process that made them, but keys made by different processes may collide, so they shouldn't be stored or sent anywhere that mixes them. Compact keys still show and parse as UUIDs, whose first 64 bits are zero, and convert to and from 128-bit numbers, bytes and tokens the same way; conversions from a wider value keep only its low 64 bits, or fail if they can fail. The methods that only make sense for UUIDs, `new_v7`, `from_name` and the like, are left out.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct VaultKey {
+    #[cfg(not(feature = "compact-keys"))]
     key: Uuid,
+    #[cfg(feature = "compact-keys")]
+    key: u64,
 }
 
 impl VaultKey {
+    /// The lowest key, with every byte zero; the same as `VaultKey::zero()`.
+    pub const MIN: VaultKey = VaultKey::from_u128(0);
+
+    /// The highest key, with every byte 0xff.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::collections::BTreeSet;
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let keys = (0..10).map(|_| VaultKey::new()).collect::<BTreeSet<_>>();
+    /// 
+    /// assert_eq!(10, keys.range(VaultKey::MIN..=VaultKey::MAX).count());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub const MAX: VaultKey = VaultKey::from_u128(u128::MAX);
+
+    /// A namespace for `from_name`, for keys that needn't agree with name-based UUIDs made elsewhere. Its value, `00677de8-ccbf-4a92-b663-092bb471a048`, never changes.
+    #[cfg(not(feature = "compact-keys"))]
+    pub const NAMESPACE: VaultKey = VaultKey {key: Uuid::from_u128(0x00677de8_ccbf_4a92_b663_092bb471a048)};
+
     /// Creates a new unique VaultKey
     /// # Example
     /// ```rust
@@ -22,10 +123,100 @@ impl VaultKey {
     /// #     Ok(())
     /// # }
     /// ```
+    #[cfg(not(feature = "compact-keys"))]
     pub fn new() -> VaultKey {
         VaultKey {key: Uuid::new_v4()}
     }
 
+    /// Creates a new VaultKey, unique within this process (see the `compact-keys` feature)
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key_1 = VaultKey::new();
+    /// let key_2 = VaultKey::new();
+    /// 
+    /// assert_ne!(key_1, key_2);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "compact-keys")]
+    pub fn new() -> VaultKey {
+        VaultKey {key: compact::new_key()}
+    }
+
+    /// Creates a new unique VaultKey from a version 7 UUID, which begins with the time it was created. Keys created this way sort in the order they were created, including keys created in the same millisecond by the same process, and `created_at` returns their time.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key_1 = VaultKey::new_v7();
+    /// let key_2 = VaultKey::new_v7();
+    /// 
+    /// assert_eq!(true, key_1 < key_2);
+    /// assert_eq!(true, key_1.created_at().is_some());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "compact-keys"))]
+    pub fn new_v7() -> VaultKey {
+        let (millis, counter) = v7_stamp();
+        // The random bits of a v4 UUID, which already carry the variant,
+        // under the timestamp and counter.
+        let mut bytes = *Uuid::new_v4().as_bytes();
+        bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+        bytes[6] = 0x70 | (counter >> 8) as u8;
+        bytes[7] = counter as u8;
+        VaultKey::from_bytes(bytes)
+    }
+
+    /// Creates the VaultKey for a name within a namespace, from a version 5 (SHA-1 name-based) UUID. The same namespace and name always give the same key, in any process on any platform, while a name gives a different key in each namespace. Use `VaultKey::NAMESPACE`, or any key of your own as the namespace.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add_with_key(1, &VaultKey::from_name(&VaultKey::NAMESPACE, b"user:42:settings"));
+    /// 
+    /// let key = VaultKey::from_name(&VaultKey::NAMESPACE, b"user:42:settings");
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "compact-keys"))]
+    pub fn from_name(namespace: &VaultKey, name: &[u8]) -> VaultKey {
+        VaultKey {key: Uuid::new_v5(&namespace.key, name)}
+    }
+
+    /// Returns the time a key made by `new_v7`, or from any other version 7 UUID, was created, to the millisecond. Returns None for other keys, which don't carry a time.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::{Duration, SystemTime};
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::new_v7();
+    /// let age = SystemTime::now().duration_since(key.created_at().unwrap())?;
+    /// 
+    /// assert_eq!(true, age < Duration::from_secs(1));
+    /// assert_eq!(None, VaultKey::new().created_at());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(not(feature = "compact-keys"))]
+    pub fn created_at(&self) -> Option<SystemTime> {
+        let bytes = self.key.as_bytes();
+        if bytes[6] >> 4 != 7 || bytes[8] >> 6 != 0b10 {
+            return None;
+        }
+        let mut millis = [0; 8];
+        millis[2..].copy_from_slice(&bytes[..6]);
+        Some(UNIX_EPOCH + Duration::from_millis(u64::from_be_bytes(millis)))
+    }
+
     /// Creates a default, zero-valued VaultKey
     /// # Example
     /// ```rust
@@ -40,269 +231,9859 @@ impl VaultKey {
     /// # }
     /// ```
     pub fn zero() -> VaultKey {
-        VaultKey {key: Uuid::nil()}
+        VaultKey::MIN
     }
-}
-
-pub struct Vault<T> {
-    items: Mutex<HashMap<VaultKey, T>>
-}
 
-impl<T> Vault<T> {
-    /// Creates a new, empty Vault instance.
+    /// Creates a VaultKey from an existing UUID, so that items can be kept under ids used elsewhere, such as in a database. Keys made from the same UUID are equal.
     /// # Example
-    /// 
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::Vault;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # use uuid::Uuid;
     /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8")?;
     /// let vault = Vault::<i32>::new();
+    /// vault.add_with_key(1, &VaultKey::from_uuid(id));
+    /// 
+    /// assert_eq!(Some(1), vault.get_clone(&VaultKey::from_uuid(id)));
     /// #     Ok(())
     /// # }
     /// ```
-    pub fn new() -> Vault<T>{
-        let map = HashMap::new();
-        let mutex = Mutex::from(map);
-        Vault {items: mutex}
+    #[cfg(not(feature = "compact-keys"))]
+    pub fn from_uuid(uuid: Uuid) -> VaultKey {
+        VaultKey {key: uuid}
     }
 
-    /// Adds an object to the vault and returns a key.
+    /// Returns the UUID behind the key.
     /// # Example
-    /// 
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::Vault;
+    /// # use bank_vault::VaultKey;
+    /// # use uuid::Uuid;
     /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let vault = Vault::<i32>::new();
+    /// let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8")?;
+    /// let key = VaultKey::from_uuid(id);
     /// 
-    /// let key = vault.add(1);
+    /// assert_eq!(id, key.as_uuid());
     /// #     Ok(())
     /// # }
     /// ```
-    pub fn add(&self, to_add: T) -> VaultKey {
-        let mut unlocked = self.items.try_lock().unwrap();
-        let key = VaultKey::new();
-        unlocked.insert(key, to_add);
-        key
+    #[cfg(not(feature = "compact-keys"))]
+    pub fn as_uuid(&self) -> Uuid {
+        self.key
     }
 
-    /// Removes and returns the stored object with a matching key, if it exists, otherwise returns None.
+    /// Creates a VaultKey from a 128-bit number, like `Uuid::from_u128`. This is a `const fn`, so well-known keys can be declared as constants. Compact keys keep only the low 64 bits of the number.
     /// # Example
-    /// 
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::Vault;
+    /// # use bank_vault::{Vault, VaultKey};
     /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let vault = Vault::<i32>::new();
-    /// let key = vault.add(1);
+    /// const SETTINGS: VaultKey = VaultKey::from_u128(1);
+    /// let vault = Vault::<&str>::new();
+    /// vault.add_with_key("dark mode", &SETTINGS);
     /// 
-    /// let item = vault.remove(&key);
-    /// assert_eq!(Some(1), item);
+    /// assert_eq!(Some("dark mode"), vault.get_clone(&SETTINGS));
     /// #     Ok(())
     /// # }
     /// ```
-    pub fn remove(&self, key: &VaultKey) -> Option<T>{
-        self.items.try_lock().unwrap().remove(key)
+    #[cfg(not(feature = "compact-keys"))]
+    pub const fn from_u128(value: u128) -> VaultKey {
+        VaultKey {key: Uuid::from_u128(value)}
     }
 
-    /// Returns true if there exists an item in the vault with the provided key, otherwise returns false.
+    #[cfg(feature = "compact-keys")]
+    pub const fn from_u128(value: u128) -> VaultKey {
+        VaultKey {key: value as u64}
+    }
+
+    /// Returns the key as a 128-bit number, like `Uuid::as_u128`.
     /// # Example
-    /// 
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::Vault;
+    /// # use bank_vault::VaultKey;
     /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let vault = Vault::<i32>::new();
-    /// let key = vault.add(1);
+    /// let key = VaultKey::from_u128(42);
     /// 
-    /// let has_item = vault.has_item(&key);
-    /// assert_eq!(true, has_item);
+    /// assert_eq!(42, key.as_u128());
     /// #     Ok(())
     /// # }
-    /// ```    
-    pub fn has_item(&self, key: &VaultKey) -> bool {
-        self.items.try_lock().unwrap().contains_key(key)
+    /// ```
+    #[cfg(not(feature = "compact-keys"))]
+    pub const fn as_u128(&self) -> u128 {
+        u128::from_be_bytes(*self.key.as_bytes())
     }
 
-    /// Adds an item to the vault with the specified key. If the key already is in use, the item is not added and this returns false. If the key is not already in use, the item is added and returns true.
+    #[cfg(feature = "compact-keys")]
+    pub const fn as_u128(&self) -> u128 {
+        self.key as u128
+    }
+
+    // Like from_u128, but fails for a number wider than a compact key rather
+    // than cut it short.
+    fn try_from_u128(value: u128) -> Result<VaultKey, Invalid> {
+        let key = VaultKey::from_u128(value);
+        if key.as_u128() == value {
+            Ok(key)
+        } else {
+            Err(Invalid::Width)
+        }
+    }
+
+    /// Returns the key as a 22-character token of URL-safe base64 without padding, a shorter form than the UUID for use in URLs. `from_token` parses it back.
     /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::from_u128(1);
     /// 
+    /// assert_eq!("AAAAAAAAAAAAAAAAAAAAAQ", key.to_token());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn to_token(&self) -> String {
+        token::encode(self.as_u128())
+    }
+
+    /// Parses a key from a token made by `to_token`. Padding, other lengths and characters outside the URL-safe base64 alphabet are rejected, except that a key in any of the UUID forms accepted by `parse` is also accepted, to ease moving from UUIDs to tokens.
+    /// # Example
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::{Vault, VaultKey};
+    /// # use bank_vault::VaultKey;
     /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let vault = Vault::<i32>::new();
     /// let key = VaultKey::new();
     /// 
-    /// let is_true = vault.add_with_key(1, &key);
-    /// assert_eq!(true, is_true);
-    /// let is_false = vault.add_with_key(2, &key);
-    /// assert_eq!(false, is_false);
+    /// assert_eq!(key, VaultKey::from_token(&key.to_token())?);
+    /// assert_eq!(key, VaultKey::from_token(&key.to_string())?);
+    /// assert_eq!(true, VaultKey::from_token("not a token").is_err());
     /// #     Ok(())
     /// # }
     /// ```
-    pub fn add_with_key(&self, to_add: T, key: &VaultKey) -> bool {
-        self.items.try_lock().unwrap().insert(*key, to_add).is_none()
+    pub fn from_token(text: &str) -> Result<VaultKey, VaultKeyParseError> {
+        if KEY_LENGTHS.contains(&text.len()) {
+            return text.parse();
+        }
+        token::decode(text)
+            .and_then(VaultKey::try_from_u128)
+            .map_err(|invalid| VaultKeyParseError {invalid})
     }
 
-    /// Updates an item in the vault with the specified key by applying the operation to it. Returns false if an item with the key is not found, otherwise returns true.
+    /// Returns a token for the key that is signed with the secret and expires at the given time, for handing keys to clients that mustn't be able to forge or alter them. `verify_token` with the same secret gives the key back until then. The token holds the key, the expiry and an HMAC-SHA256 of both, in URL-safe base64 without padding; it isn't encrypted, so anyone holding it can read the key from it. Expiry is kept to the millisecond.
     /// # Example
-    /// 
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::{Vault, VaultKey};
+    /// # use std::time::{Duration, SystemTime};
+    /// # use bank_vault::{TokenError, VaultKey};
     /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let vault = Vault::<i32>::new();
-    /// let key = vault.add(1);
+    /// let key = VaultKey::new();
+    /// let token = key.to_signed_token(b"secret", SystemTime::now() + Duration::from_secs(60));
     /// 
-    /// let double_me = |i:i32| i * 2;
+    /// assert_eq!(Ok(key), VaultKey::verify_token(&token, b"secret"));
+    /// assert_eq!(Err(TokenError::BadSignature), VaultKey::verify_token(&token, b"another secret"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "signed-tokens")]
+    pub fn to_signed_token(&self, secret: &[u8], expires_at: SystemTime) -> String {
+        signed::sign(self, secret, expires_at)
+    }
+
+    /// Returns the key in a token made by `to_signed_token` with the same secret. Returns `TokenError::Expired` if the token has expired, `TokenError::BadSignature` if it was signed with another secret or changed in any way since, and `TokenError::Malformed` if it isn't a signed token at all. The signature is compared in constant time.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::{Duration, SystemTime};
+    /// # use bank_vault::{TokenError, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::new();
+    /// let expired = key.to_signed_token(b"secret", SystemTime::now() - Duration::from_secs(1));
     /// 
-    /// let updated = vault.update_item(&key, double_me);
-    /// assert_eq!(true, updated);
+    /// assert_eq!(Err(TokenError::Expired), VaultKey::verify_token(&expired, b"secret"));
+    /// assert_eq!(Err(TokenError::Malformed), VaultKey::verify_token(&key.to_token(), b"secret"));
     /// #     Ok(())
     /// # }
     /// ```
-    pub fn update_item<F>(&self, key: &VaultKey, mut operation: F) -> bool
-            where F: FnMut(T) -> T {
-        self.remove(key).map(|i| {
-            let updated = operation(i);
-            self.add_with_key(updated, key)
-        }).is_some()
+    #[cfg(feature = "signed-tokens")]
+    pub fn verify_token(token: &str, secret: &[u8]) -> Result<VaultKey, TokenError> {
+        signed::verify(token, secret)
     }
 
-    /// Clears the contents of the vault.
+    /// Returns the 16 bytes of the key's UUID, in the same order as `Uuid::as_bytes`.
     /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::new();
+    /// let bytes = key.to_bytes();
     /// 
+    /// assert_eq!(key, VaultKey::from_bytes(bytes));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.as_u128().to_be_bytes()
+    }
+
+    /// Creates a VaultKey from the 16 bytes of a UUID, in the same order as `Uuid::from_bytes`. Compact keys keep only the last 8 bytes.
+    /// # Example
     /// ```rust
     /// # use std::error::Error;
-    /// # use bank_vault::{Vault, VaultKey};
+    /// # use bank_vault::VaultKey;
     /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let vault = Vault::<i32>::new();
-    /// let key = vault.add(1);
+    /// let key = VaultKey::from_bytes([0; 16]);
     /// 
-    /// vault.clear();
+    /// assert_eq!(VaultKey::zero(), key);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn from_bytes(bytes: [u8; 16]) -> VaultKey {
+        VaultKey::from_u128(u128::from_be_bytes(bytes))
+    }
+
+    /// Creates a VaultKey from a slice holding the 16 bytes of a UUID, like `from_bytes`. Returns an error if the slice isn't exactly 16 bytes long, or, for compact keys, if any of the first 8 bytes isn't zero.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::VaultKey;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::new();
+    /// let buffer = key.to_bytes().to_vec();
     /// 
-    /// let has_item = vault.has_item(&key);
-    /// assert_eq!(false, has_item);
+    /// assert_eq!(key, VaultKey::try_from_slice(&buffer)?);
+    /// assert_eq!(true, VaultKey::try_from_slice(&buffer[..8]).is_err());
     /// #     Ok(())
     /// # }
     /// ```
-    pub fn clear(&self) {
-        self.items.try_lock().unwrap().clear()
+    pub fn try_from_slice(bytes: &[u8]) -> Result<VaultKey, VaultKeyParseError> {
+        <[u8; 16]>::try_from(bytes)
+            .map_err(|_| Invalid::ByteLength(bytes.len()))
+            .and_then(|bytes| VaultKey::try_from_u128(u128::from_be_bytes(bytes)))
+            .map_err(|invalid| VaultKeyParseError {invalid})
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn add_has_item() {
-        let vault = Vault::new();
-        let to_add = "stuff";
-        let key = vault.add(to_add);
-        let has_item = vault.has_item(&key);
-        assert_eq!(true, has_item);
+impl Default for VaultKey {
+    fn default() -> VaultKey {
+        VaultKey::zero()
     }
+}
 
-    #[test]
-    fn has_item_wrong_key() {
-        let vault = Vault::new();
-        let to_add = "garbage";
-        vault.add(to_add);
-        let wrong_key = VaultKey::new();
-        let has_item = vault.has_item(&wrong_key);
-        assert_eq!(false, has_item);
-    }
+// The time and counter for the next v7 key. The counter orders keys made in
+// the same millisecond; when it runs out, or the clock goes back, the time
+// is moved on past the last key's instead, so keys from this process never
+// go out of order.
+#[cfg(not(feature = "compact-keys"))]
+fn v7_stamp() -> (u64, u16) {
+    static LAST: Mutex<(u64, u16)> = Mutex::new((0, 0));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis() as u64);
+    let mut last = LAST.lock().unwrap_or_else(PoisonError::into_inner);
+    *last = match *last {
+        (millis, counter) if now <= millis && counter < 0xfff => (millis, counter + 1),
+        (millis, _) if now <= millis => (millis + 1, 0),
+        _ => (now, 0),
+    };
+    *last
+}
 
-    #[test]
-    fn add_duplicates_unique_keys() {
-        let vault = Vault::new();
-        let to_add = 1.0;
-        let key_1 = vault.add(to_add);
-        let key_2 = vault.add(to_add);
-        assert_ne!(key_1, key_2);
+/// Shows the key as a hyphenated UUID, such as `67e55044-10b1-426f-9247-bb680e5fe0c8`.
+impl fmt::Display for VaultKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bits = self.as_u128();
+        write!(f, "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}", bits >> 96, bits >> 80 & 0xffff, bits >> 64 & 0xffff, bits >> 48 & 0xffff, bits & 0xffff_ffff_ffff)
     }
+}
 
-    #[test]
-    fn add_with_key_has_item() {
-        let vault = Vault::new();
-        let to_add = 1.0;
-        let key = VaultKey::new();
-        let added = vault.add_with_key(to_add, &key);
-        let has_item = vault.has_item(&key);
-        assert_eq!(true, added);
-        assert_eq!(true, has_item);
+/// Parses a key from a UUID in hyphenated, simple or urn form, as shown by `Display` or the `uuid` crate.
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::VaultKey;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let key = VaultKey::new();
+/// 
+/// assert_eq!(key, key.to_string().parse::<VaultKey>()?);
+/// assert_eq!(true, "not a key".parse::<VaultKey>().is_err());
+/// #     Ok(())
+/// # }
+/// ```
+impl FromStr for VaultKey {
+    type Err = VaultKeyParseError;
+
+    fn from_str(text: &str) -> Result<VaultKey, VaultKeyParseError> {
+        if !KEY_LENGTHS.contains(&text.len()) {
+            return Err(VaultKeyParseError {invalid: Invalid::Length(text.len())});
+        }
+        VaultKey::parse_uuid(text).map_err(|invalid| VaultKeyParseError {invalid})
     }
+}
 
-    #[test]
-    fn add_with_key_duplicate_fails() {
-        let vault = Vault::new();
-        let to_add = 1.0;
-        let key = VaultKey::new();
-        let added_1 = vault.add_with_key(to_add, &key);
-        let added_2 = vault.add_with_key(to_add, &key);
-        assert_eq!(true, added_1);
-        assert_eq!(false, added_2);
+impl VaultKey {
+    #[cfg(not(feature = "compact-keys"))]
+    fn parse_uuid(text: &str) -> Result<VaultKey, Invalid> {
+        Uuid::parse_str(text)
+            .map(VaultKey::from_uuid)
+            .map_err(|_| Invalid::Format)
     }
 
-    #[test]
-    fn add_remove() {
-        let vault = Vault::new();
-        let to_add = 1;
-        let key = vault.add(to_add);
-        let retrieved = vault.remove(&key).unwrap();
-        assert_eq!(to_add, retrieved);
+    #[cfg(feature = "compact-keys")]
+    fn parse_uuid(text: &str) -> Result<VaultKey, Invalid> {
+        compact::parse(text).and_then(VaultKey::try_from_u128)
     }
+}
 
-    #[test]
-    fn remove_before_add() {
-        let vault = Vault::<i32>::new();
-        let key = VaultKey::new();
-        let retrieved = vault.remove(&key);
-        assert_eq!(None, retrieved);
+// The lengths of the simple, hyphenated and urn forms.
+const KEY_LENGTHS: [usize; 3] = [32, 36, 45];
+
+#[cfg(not(feature = "compact-keys"))]
+impl From<Uuid> for VaultKey {
+    fn from(uuid: Uuid) -> VaultKey {
+        VaultKey::from_uuid(uuid)
     }
+}
 
-    #[test]
-    fn add_update() {
-        let vault = Vault::new();
-        let to_add = 1.0;
-        let key = vault.add(to_add);
-        let double = |i:f64| i * 2.0;
-        let expected = 2.0;
-        let updated = vault.update_item(&key, double);
-        let retrieved = vault.remove(&key).unwrap();
-        assert_eq!(true, updated);
-        assert_eq!(expected, retrieved);
+#[cfg(not(feature = "compact-keys"))]
+impl From<VaultKey> for Uuid {
+    fn from(key: VaultKey) -> Uuid {
+        key.as_uuid()
     }
+}
 
-    #[test]
-    fn add_remove_twice() {
+// Each item sits in a slot with its own lock, so that a slow operation on
+// one item holds only that item's lock and not its shard's. A slot stored in
+// a shard is only empty while its item is checked out by an entry.
+type Slot<T> = Arc<Lock<Option<T>>>;
+// What the side tables beside the shards hold on to, to tell whether an
+// item they know of is still the one stored under its key.
+type WeakSlot<T> = std::sync::Weak<Lock<Option<T>>>;
+type Items<T> = HashMap<VaultKey, Slot<T>>;
+type ItemsReadGuard<'a, T> = ReadGuard<'a, Items<T>>;
+type ItemsLock<T> = Lock<Items<T>>;
+
+fn new_slot<T>(item: T) -> Slot<T> {
+    Arc::new(Lock::new(Some(item)))
+}
+
+// A new slot for the item under the key, with its load booked against the
+// vault's cap. Called with the key's shard locked, as the slot goes into it.
+fn store<T>(limit: Option<&Limit<T>>, key: &VaultKey, item: T, load: usize) -> Slot<T> {
+    let slot = new_slot(item);
+    if let Some(limit) = limit {
+        limit.book(key, &slot, load);
+    }
+    slot
+}
+
+// Weighs the item in the locked slot again, after it was changed in place.
+fn reweigh<T>(limit: Option<&Limit<T>>, key: &VaultKey, slot: &Slot<T>, item: &Option<T>) {
+    if let (Some(limit), Some(item)) = (limit, item) {
+        limit.rebook(key, slot, item);
+    }
+}
+
+fn read_slot<T>(slot: &Slot<T>) -> ReadGuard<'_, Option<T>> {
+    slot.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write_slot<T>(slot: &Slot<T>) -> WriteGuard<'_, Option<T>> {
+    slot.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+// Turns the outcome of a timed lock into the guard or a timeout. Like the
+// blocking methods, timed ones carry on past a poisoned lock.
+fn within<G>(result: TryLockResult<G>) -> Result<G, VaultError> {
+    match result {
+        Ok(guard) => Ok(guard),
+        Err(TryLockError::Poisoned(poisoned)) => Ok(poisoned.into_inner()),
+        Err(TryLockError::WouldBlock) => Err(VaultError::Timeout),
+    }
+}
+
+// The slot found under a key, if it is the one the key's item must be in:
+// any slot, unless the key is an alias, which only finds the slot of the
+// item it was made for.
+fn resolved<T>(slot: Option<&Slot<T>>, aliased: Option<*const Lock<Option<T>>>) -> Option<&Slot<T>> {
+    slot.filter(|slot| aliased.is_none_or(|aliased| Arc::as_ptr(slot) == aliased))
+}
+
+// Takes the item out of a slot that has been dropped from its shard, first
+// waiting for any operation still running on it to finish.
+fn take_slot<T>(slot: Slot<T>) -> Option<T> {
+    write_slot(&slot).take()
+}
+
+// Sole access to one shard of a vault's items. The vault's count covers all
+// of its shards, and each guard moves it by however much its shard grew or
+// shrank as the guard is released. That keeps the count right however an
+// operation changes the shard, even if a panic interrupts it part way.
+struct ItemsGuard<'a, T> {
+    items: WriteGuard<'a, Items<T>>,
+    count: &'a AtomicUsize,
+    len_before: usize,
+    watches: &'a Watches,
+}
+
+impl<'a, T> Deref for ItemsGuard<'a, T> {
+    type Target = Items<T>;
+
+    fn deref(&self) -> &Items<T> {
+        &self.items
+    }
+}
+
+impl<'a, T> DerefMut for ItemsGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Items<T> {
+        &mut self.items
+    }
+}
+
+impl<'a, T> Drop for ItemsGuard<'a, T> {
+    fn drop(&mut self) {
+        let len = self.items.len();
+        if len > self.len_before {
+            self.count.fetch_add(len - self.len_before, Ordering::Relaxed);
+        } else if len < self.len_before {
+            self.count.fetch_sub(self.len_before - len, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A collection that hides its contents behind unique keys. Every method takes `&self` and is safe to call from many threads at once; calls that need the vault at the same time wait for each other rather than failing.
+/// 
+/// Calls that only read the vault, such as `has_item`, `with_item` and `keys`, share it and run in parallel; calls that change it wait for sole use. How a waiting writer is treated against a steady stream of readers depends on the lock, unless the vault is made with `new_fair`. With the `parking_lot` feature the lock is fair, so neither readers nor writers are starved. Otherwise the standard library's lock is used and the policy is the platform's: on some platforms new readers queue behind a waiting writer, on others a busy enough stream of readers can hold writers off indefinitely. Because readers share items across threads, `Vault<T>` is `Send` and `Sync` only when `T` is both `Send` and `Sync`.
+/// 
+/// The items are split across shards by key, each shard with its own lock (see `with_shards`). Calls on a single key lock only that key's shard, so calls on keys in different shards never wait for each other. Calls on several keys or on the whole vault lock every shard, always in the same order, and so still see and change the vault in a single step.
+pub struct Vault<T> {
+    shards: Vec<ItemsLock<T>>,
+    count: AtomicUsize,
+    watches: Watches,
+    keys: Box<dyn KeyGenerator + Send + Sync>,
+    generation: AtomicU64,
+    master: Option<u128>,
+    access: Access<T>,
+    names: Names<T>,
+    limit: Option<Limit<T>>,
+}
+
+impl<T> Vault<T> {
+    /// Creates a new, empty Vault instance.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn new() -> Vault<T>{
+        Vault::builder().build()
+    }
+
+    /// Returns a `VaultBuilder`, for setting up a new vault with any mix of the options the other constructors give one at a time, such as a shard count, a cap, an eviction policy and a key generator.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{LruPolicy, Vault};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::builder()
+    ///     .shards(2)
+    ///     .fair()
+    ///     .max_items(1)
+    ///     .eviction(Box::new(LruPolicy::new()), |_, _| {})
+    ///     .build();
+    /// let first = vault.add(1);
+    /// 
+    /// vault.add(2);
+    /// assert_eq!(false, vault.has_item(&first));
+    /// assert_eq!(1, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn builder() -> VaultBuilder<T> {
+        VaultBuilder::new()
+    }
+
+    /// Creates a new, empty Vault instance that splits its items across the given number of shards, each with its own lock. Operations on keys in different shards don't wait for each other, while operations on the whole vault lock every shard. `new` uses one shard per CPU; a count of zero is treated as one.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_shards(4);
+    /// 
+    /// let key = vault.add(1);
+    /// assert_eq!(true, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_shards(shards: usize) -> Vault<T> {
+        Vault::builder().shards(shards).build()
+    }
+
+    /// Creates a new, empty Vault instance with room for at least the given number of items before it has to grow, for vaults whose size is known up front. The room is spread evenly across the shards, and since items are spread across them by key, a shard can still grow a little before the vault holds that many items. In every other way the vault is the same as one made by `new`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_capacity(1000);
+    /// 
+    /// assert!(vault.capacity() >= 1000);
+    /// assert_eq!(0, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Vault<T> {
+        Vault::builder().capacity(capacity).build()
+    }
+
+    /// Creates a new, empty Vault instance that holds at most the given number of items. Once it is full, the methods that add items refuse to: the ones that return a `Result`, such as `add_checked`, `add_with_key_checked` and `try_add`, return `VaultError::Full`, the ones that return a bool return false, and the rest, such as `add` and `add_many`, panic. Adding checks for space and adds in a single step, so threads adding at once can't take the vault over the cap, but adds to different shards wait for each other.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_max_items(2);
+    /// vault.add_many(vec![1, 2]);
+    /// 
+    /// assert_eq!(Err(VaultError::Full), vault.add_checked(3));
+    /// assert_eq!(false, vault.add_with_key(3, &VaultKey::new()));
+    /// assert_eq!(2, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_max_items(max_items: usize) -> Vault<T> {
+        Vault::builder().max_items(max_items).build()
+    }
+
+    /// Creates a new, empty Vault instance that holds at most the given number of items, like `with_max_items`, but that makes space for a new item once it is full by evicting one chosen by the policy, such as `LruPolicy` or `FifoPolicy`, and passes each evicted item to the callback along with its key. The callback is called once the vault is free again, so it may use the vault. Items that only some of their keys can remove, like those added with `add_locked` or `add_dual`, aren't evicted. `try_add` and `add_timeout`, which would have to wait on other keys to evict, and `lock` and `atomically`, return an error or refuse as for `with_max_items` instead.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::{Arc, Mutex};
+    /// # use bank_vault::{LruPolicy, Vault};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let evicted = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = Arc::clone(&evicted);
+    /// let vault = Vault::with_eviction(2, Box::new(LruPolicy::new()), move |_, item| sink.lock().unwrap().push(item));
+    /// let first = vault.add(1);
+    /// vault.add(2);
+    /// 
+    /// assert_eq!(Some(1), vault.get_clone(&first));
+    /// vault.add(3);
+    /// assert_eq!(vec![2], *evicted.lock().unwrap());
+    /// assert_eq!(true, vault.has_item(&first));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_eviction<F>(max_items: usize, policy: Box<dyn EvictionPolicy<T>>, on_evict: F) -> Vault<T>
+            where F: Fn(VaultKey, T) + Send + Sync + 'static {
+        Vault::builder().max_items(max_items).eviction(policy, on_evict).build()
+    }
+
+    /// Creates a new, empty Vault instance whose items may weigh at most the given total, each weighing what the weigher says, such as its size in bytes. Adding an item that would take the vault over its budget is refused as adding to a full vault is for `with_max_items`. An item changed in place is weighed again, and is kept if it grows past the budget; the vault then refuses new items until enough weight is removed. The weigher is called with the vault locked, so it must not use the vault.
+    /// 
+    /// `get_or_insert_with`, `update_or_insert` and `entry` only make their item once the vault is locked, and are refused once the vault is over its budget, or if the item turns out not to fit, in which case it is dropped.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::with_max_weight(10, |item: &String| item.len());
+    /// let key = vault.add(String::from("abcd"));
+    /// vault.add(String::from("efgh"));
+    /// 
+    /// assert_eq!(Err(VaultError::Full), vault.add_checked(String::from("ijk")));
+    /// vault.update_item(&key, |item| item.truncate(1));
+    /// assert_eq!(5, vault.weight());
+    /// assert_eq!(true, vault.add_checked(String::from("ijk")).is_ok());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_max_weight<W>(max_weight: usize, weigher: W) -> Vault<T>
+            where W: Fn(&T) -> usize + Send + Sync + 'static {
+        Vault::builder().max_weight(max_weight, weigher).build()
+    }
+
+    /// Creates a new, empty Vault instance whose items may weigh at most the given total, like `with_max_weight`, but that makes space for a new item by evicting items chosen by the policy, as many as it takes, like `with_eviction`. A vault taken over its budget by an item growing in place evicts down to the budget when the next item is added. An item heavier than the whole budget is refused without evicting anything.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::{Arc, Mutex};
+    /// # use bank_vault::{LruPolicy, Vault};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let evicted = Arc::new(Mutex::new(Vec::new()));
+    /// let sink = Arc::clone(&evicted);
+    /// let vault = Vault::with_weighted_eviction(10, |item: &Vec<u8>| item.len(), Box::new(LruPolicy::new()), move |_, item: Vec<u8>| sink.lock().unwrap().push(item.len()));
+    /// vault.add(vec![0; 4]);
+    /// vault.add(vec![0; 4]);
+    /// 
+    /// vault.add(vec![0; 8]);
+    /// assert_eq!(vec![4, 4], *evicted.lock().unwrap());
+    /// assert_eq!(8, vault.weight());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_weighted_eviction<W, F>(max_weight: usize, weigher: W, policy: Box<dyn EvictionPolicy<T>>, on_evict: F) -> Vault<T>
+            where W: Fn(&T) -> usize + Send + Sync + 'static, F: Fn(VaultKey, T) + Send + Sync + 'static {
+        Vault::builder().max_weight(max_weight, weigher).eviction(policy, on_evict).build()
+    }
+
+    /// Creates a new, empty Vault instance whose locks are handed out in the order they are asked for. Under heavy contention no thread waits for long while others are let in ahead of it, at the cost of slower locking. The `try_` and timeout methods can still take a lock out of turn.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new_fair();
+    /// 
+    /// let key = vault.add(1);
+    /// assert_eq!(true, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn new_fair() -> Vault<T> {
+        Vault::builder().fair().build()
+    }
+
+    /// Creates a new, empty Vault instance that draws the keys for new items, in `add` and the like, from the generator instead of making random ones. A generated key that is already in use is skipped, so an item is never overwritten; if the generator gives a long run of keys that are all in use, adding panics.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{SequentialKeyGenerator, Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_key_generator(SequentialKeyGenerator::new());
+    /// 
+    /// assert_eq!(VaultKey::from_u128(1), vault.add(1));
+    /// assert_eq!(VaultKey::from_u128(2), vault.add(2));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_key_generator<G>(keys: G) -> Vault<T>
+            where G: KeyGenerator + Send + Sync + 'static {
+        Vault::builder().key_generator(keys).build()
+    }
+
+    /// Creates a new, empty Vault instance along with its master key, which lets its holder remove or look at any item with `master_remove`, `master_inspect` and `master_drain`. Everything else about the vault works the same as for one made by `new`. The master key only opens this vault.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let (vault, master) = Vault::<i32>::with_master_key();
+    /// let (other, _) = Vault::<i32>::with_master_key();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Err(VaultError::WrongMasterKey), other.master_drain(&master));
+    /// assert_eq!(Ok(Some(1)), vault.master_remove(&master, &key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_master_key() -> (Vault<T>, MasterKey) {
+        Vault::builder().build_with_master_key()
+    }
+
+    /// Creates a new Vault instance holding the provided items under their keys. If a key appears more than once, the last item with that key is kept.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::new();
+    /// 
+    /// let vault = Vault::from_entries(vec![(key, 1), (key, 2)]);
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// assert_eq!(1, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn from_entries<I>(entries: I) -> Vault<T>
+            where I: IntoIterator<Item = (VaultKey, T)> {
+        let vault = Vault::new();
+        vault.extend_entries(entries);
+        vault
+    }
+
+    /// Creates a new Vault instance holding the provided objects, and returns it along with the objects' keys, in the same order as the objects.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let (vault, keys) = Vault::collect_with_keys(vec!["a", "b"]);
+    /// 
+    /// assert_eq!(Some("b"), vault.get_clone(&keys[1]));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn collect_with_keys<I>(to_add: I) -> (Vault<T>, Vec<VaultKey>)
+            where I: IntoIterator<Item = T> {
+        let vault = Vault::new();
+        let keys = vault.add_many(to_add);
+        (vault, keys)
+    }
+
+    /// Adds an object to the vault and returns a key. Panics if the vault was made with `with_max_items` and is full; `add_checked` returns an error instead.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// let key = vault.add(1);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add(&self, to_add: T) -> VaultKey {
+        self.add_in_generation(to_add).0
+    }
+
+    /// Adds an object to the vault and returns its key, like `add`, or returns `VaultError::Full`, dropping the object, if the vault was made with `with_max_items` and is full.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_max_items(1);
+    /// 
+    /// let key = vault.add_checked(1)?;
+    /// assert_eq!(Err(VaultError::Full), vault.add_checked(2));
+    /// assert_eq!(Some(1), vault.remove(&key));
+    /// assert_eq!(true, vault.add_checked(2).is_ok());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_checked(&self, to_add: T) -> Result<VaultKey, VaultError> {
+        self.add_checked_in_generation(to_add).map(|(key, _)| key)
+    }
+
+    // Adds the item like add, and returns the generation it was added in
+    // along with its key.
+    pub(crate) fn add_in_generation(&self, to_add: T) -> (VaultKey, u64) {
+        self.add_checked_in_generation(to_add).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    fn add_checked_in_generation(&self, to_add: T) -> Result<(VaultKey, u64), VaultError> {
+        let load = self.load(&to_add);
+        let mut to_add = Some(to_add);
+        let (key, generation) = keygen::fresh_key(&*self.keys, |key| {
+            let room = self.room(&key, load);
+            let mut unlocked = self.write_shard(&key);
+            match unlocked.entry(key) {
+                hash_map::Entry::Occupied(_) => None,
+                hash_map::Entry::Vacant(_) if !room.admit(&key, load) => Some(Err(VaultError::Full)),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.store(&key, to_add.take().expect("item is added once"), load));
+                    Some(Ok((key, self.generation())))
+                }
+            }
+        })?;
+        self.watches.changed(&key);
+        Ok((key, generation))
+    }
+
+    /// Adds every object to the vault at once and returns their keys, in the same order as the objects. Other threads see either none or all of the objects. Panics, adding none of them, if the vault was made with `with_max_items` and hasn't space for them all; `add_many_checked` returns an error instead.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// let keys = vault.add_many(vec![1, 2, 3]);
+    /// assert_eq!(3, keys.len());
+    /// assert_eq!(Some(2), vault.get_clone(&keys[1]));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_many<I>(&self, to_add: I) -> Vec<VaultKey>
+            where I: IntoIterator<Item = T> {
+        self.add_many_checked(to_add).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Adds every object to the vault at once and returns their keys, like `add_many`, or returns `VaultError::Full`, adding none of them, if the vault was made with `with_max_items` and hasn't space for them all.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_max_items(3);
+    /// 
+    /// assert_eq!(Err(VaultError::Full), vault.add_many_checked(vec![1, 2, 3, 4]));
+    /// assert_eq!(0, vault.len());
+    /// assert_eq!(3, vault.add_many_checked(vec![1, 2, 3])?.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_many_checked<I>(&self, to_add: I) -> Result<Vec<VaultKey>, VaultError>
+            where I: IntoIterator<Item = T> {
+        let to_add: Vec<(usize, T)> = to_add.into_iter().map(|item| (self.load(&item), item)).collect();
+        let adding = to_add.iter().map(|(load, _)| load).sum();
+        let mut keys = Vec::with_capacity(to_add.len());
+        let mut evicted = Evicted::new(self.limit.as_ref(), &self.watches);
+        let mut unlocked = self.write_all();
+        self.make_room(&mut unlocked, adding, &mut evicted);
+        if !limit::fits(self.limit.as_ref(), unlocked.len(), adding) {
+            return Err(VaultError::Full);
+        }
+        unlocked.reserve(to_add.len());
+        for (load, item) in to_add {
+            let key = keygen::fresh_key(&*self.keys, |key| Some(key).filter(|key| !unlocked.contains_key(key)));
+            unlocked.insert(key, self.store(&key, item, load));
+            self.inserted(&key);
+            keys.push(key);
+        }
+        drop(unlocked);
+        for key in &keys {
+            self.watches.changed(key);
+        }
+        Ok(keys)
+    }
+
+    /// Adds every object to the vault at once and returns their keys, in the same order as the objects. This behaves the same as `add_many`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// let keys = vault.extend_values((1..4).map(|i| i * 10));
+    /// assert_eq!(Some(30), vault.get_clone(&keys[2]));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn extend_values<I>(&self, to_add: I) -> Vec<VaultKey>
+            where I: IntoIterator<Item = T> {
+        self.add_many(to_add)
+    }
+
+    /// Adds every item to the vault under its key, at once. If a key is already in use, the stored item is replaced, matching `HashMap::extend`, and loses its rules and name as with `replace`. An entry under a key that `replace` refuses, such as that of an item added with `add_locked` or `add_dual`, a second key, the untyped form of a `ReadKey`, an alias or a reserved key, is dropped without being added.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// vault.extend_entries(vec![(key, 2), (VaultKey::new(), 3)]);
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// assert_eq!(2, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn extend_entries<I>(&self, entries: I)
+            where I: IntoIterator<Item = (VaultKey, T)> {
+        let entries: Vec<(VaultKey, T)> = entries.into_iter().collect();
+        let mut evicted = Evicted::new(self.limit.as_ref(), &self.watches);
+        let mut unlocked = self.write_all();
+        let entries: Vec<(VaultKey, T)> = entries.into_iter().filter(|(key, _)| match unlocked.get(key) {
+            Some(slot) => self.access.refusal(key, slot).is_none(),
+            None => !self.access.is_stand_in(key),
+        }).collect();
+        let loads = entries.iter().map(|(_, item)| self.load(item)).collect::<Vec<_>>();
+        if let Some(limit) = &self.limit {
+            // Of entries under the same key the last is the one kept.
+            let last = entries.iter().zip(&loads).map(|((key, _), load)| (*key, *load)).collect::<HashMap<_, _>>();
+            let replacing = last.keys().map(|key| limit.load_of(key, unlocked.contains_key(key))).sum();
+            let incoming = last.values().sum();
+            self.make_room(&mut unlocked, usize::saturating_sub(incoming, replacing), &mut evicted);
+            if !limit.fits_changed(unlocked.len(), replacing, incoming) {
+                panic!("{}", VaultError::Full);
+            }
+        }
+        unlocked.reserve(entries.len());
+        let keys = entries.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        for ((key, item), load) in entries.into_iter().zip(loads) {
+            if unlocked.contains_key(&key) {
+                self.forget(&key);
+            }
+            let slot = self.store(&key, item, load);
+            unlocked.insert(key, slot);
+            self.inserted(&key);
+        }
+        drop(unlocked);
+        for key in &keys {
+            self.watches.changed(key);
+        }
+    }
+
+    /// Removes and returns the stored object with a matching key, if it exists, otherwise returns None. If another thread is updating the item, this waits for the update to finish and returns the updated item. Panics with `AccessError::PassphraseRequired` if the item was added with `add_locked`, leaving it in the vault; `remove_locked` removes it, and `try_remove` returns the error instead. An item added with `add_dual` isn't removed, and None is returned. An alias made with `add_alias` removes the item it is for, leaving every other alias of it dangling.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let item = vault.remove(&key);
+    /// assert_eq!(Some(1), item);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove(&self, key: &VaultKey) -> Option<T>{
+        self.remove_in_generation(key, None)
+    }
+
+    // Removes the key's item like remove, if the generation stands.
+    pub(crate) fn remove_in_generation(&self, key: &VaultKey, generation: Option<u64>) -> Option<T> {
+        let slot = {
+            let mut unlocked = self.write_shard(key);
+            if !self.stands(generation) {
+                return None;
+            }
+            let slot = match unlocked.get(key) {
+                Some(slot) => slot,
+                None => {
+                    drop(unlocked);
+                    return self.remove_aliased(key);
+                }
+            };
+            if let Some(refusal) = self.access.refusal(key, slot) {
+                drop(unlocked);
+                if refusal == AccessError::PassphraseRequired {
+                    panic!("{}", VaultError::Access(refusal));
+                }
+                return None;
+            }
+            self.forget(key);
+            unlocked.remove(key)?
+        };
+        self.watches.changed(key);
+        take_slot(slot)
+    }
+
+    // Removes the item the key is an alias of, like remove.
+    fn remove_aliased(&self, alias: &VaultKey) -> Option<T> {
+        let (first, aliased) = self.access.alias_of(alias)?;
+        let slot = {
+            let mut unlocked = self.write_shard(&first);
+            let slot = unlocked.get(&first).filter(|slot| Arc::as_ptr(slot) == aliased)?;
+            if self.access.refusal(&first, slot).is_some() {
+                return None;
+            }
+            self.forget(&first);
+            unlocked.remove(&first)?
+        };
+        self.watches.changed(&first);
+        take_slot(slot)
+    }
+
+    /// Removes and returns the stored objects with matching keys, all at once, in the same order as the keys. Each slot holds None if its key has no item, including a key repeated later in the slice after its item was already removed, or if its item can't be removed with its key alone, like with `remove`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key_1 = vault.add(1);
+    /// let key_2 = vault.add(2);
+    /// 
+    /// let items = vault.remove_many(&[key_2, VaultKey::new(), key_1]);
+    /// assert_eq!(vec![Some(2), None, Some(1)], items);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove_many(&self, keys: &[VaultKey]) -> Vec<Option<T>> {
+        let removed: Vec<Option<(VaultKey, Slot<T>)>> = {
+            let mut unlocked = self.write_all();
+            keys.iter().map(|key| {
+                let (key, aliased) = self.resolve(key);
+                match resolved(unlocked.get(&key), aliased) {
+                    None => None,
+                    Some(slot) if self.access.refusal(&key, slot).is_some() => None,
+                    Some(_) => {
+                        self.forget(&key);
+                        unlocked.remove(&key).map(|slot| (key, slot))
+                    }
+                }
+            }).collect()
+        };
+        for (key, _) in removed.iter().flatten() {
+            self.watches.changed(key);
+        }
+        removed.into_iter().map(|removed| removed.and_then(|(_, slot)| take_slot(slot))).collect()
+    }
+
+    /// Returns true if there exists an item in the vault with the provided key, otherwise returns false. Either key of an item added with `add_dual` finds it, as does the `ReadKey` of one added with `add_with_roles`, in its untyped form.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let has_item = vault.has_item(&key);
+    /// assert_eq!(true, has_item);
+    /// #     Ok(())
+    /// # }
+    /// ```    
+    pub fn has_item(&self, key: &VaultKey) -> bool {
+        self.has_item_in_generation(key, None)
+    }
+
+    pub(crate) fn has_item_in_generation(&self, key: &VaultKey, generation: Option<u64>) -> bool {
+        // Waiting on a shard never fails.
+        self.find_item(key, generation, |key| Ok(self.read_shard(key))).unwrap_or(false)
+    }
+
+    // Whether the key has an item, stands in for one or is an alias of one,
+    // locking each shard it looks in with the given lock.
+    fn find_item<'a, L>(&'a self, key: &VaultKey, generation: Option<u64>, read_shard: L) -> Result<bool, VaultError>
+            where L: Fn(&VaultKey) -> Result<ItemsReadGuard<'a, T>, VaultError> {
+        {
+            let unlocked = read_shard(key)?;
+            if !self.stands(generation) {
+                return Ok(false);
+            }
+            if unlocked.contains_key(key) {
+                self.used(key);
+                return Ok(true);
+            }
+        }
+        if let Some((first, _)) = self.access.stands_in_for(key) {
+            return Ok(self.stand_in_slot(&*read_shard(&first)?, &first, key).is_some());
+        }
+        let (first, aliased) = match self.access.alias_of(key) {
+            Some(alias) => alias,
+            None => return Ok(false),
+        };
+        let found = resolved(read_shard(&first)?.get(&first), Some(aliased)).is_some();
+        if found {
+            self.used(&first);
+        }
+        Ok(found)
+    }
+
+    /// Returns a clone of the stored object with a matching key, if it exists, otherwise returns None. The item is left in the vault. The untyped form of a `ReadKey` finds its item, as with `has_item`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let item = vault.get_clone(&key);
+    /// assert_eq!(Some(1), item);
+    /// assert_eq!(true, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn get_clone(&self, key: &VaultKey) -> Option<T>
+            where T: Clone {
+        self.with_item(key, T::clone)
+    }
+
+    /// Returns clones of the stored objects with matching keys, in the same order as the keys, with None for keys that have no item. All of the clones are taken at once, so they are consistent with each other as of the moment of the call, but may be out of date by the time they are used.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let items = vault.get_many(&[key, VaultKey::new(), key]);
+    /// assert_eq!(vec![Some(1), None, Some(1)], items);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn get_many(&self, keys: &[VaultKey]) -> Vec<Option<T>>
+            where T: Clone {
+        let unlocked = self.read_all();
+        keys.iter()
+            .map(|key| {
+                let (key, aliased) = self.resolve(key);
+                resolved(unlocked.get(&key), aliased).and_then(|slot| read_slot(slot).clone())
+            })
+            .collect()
+    }
+
+    /// Applies the operation to a reference to the stored object with a matching key and returns its result, if the key exists, otherwise returns None without calling the operation. The item is left in the vault. Only the item itself is locked while the operation runs, so other keys can be used in the meantime.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<String>::new();
+    /// let key = vault.add(String::from("hello"));
+    /// 
+    /// let length = vault.with_item(&key, |s| s.len());
+    /// assert_eq!(Some(5), length);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_item<R, F>(&self, key: &VaultKey, operation: F) -> Option<R>
+            where F: FnOnce(&T) -> R {
+        self.with_item_in_generation(key, None, "Vault::with_item", operation)
+    }
+
+    // Runs the operation on a reference to the key's item like with_item, if
+    // the generation stands, on behalf of the named public method.
+    pub(crate) fn with_item_in_generation<R, F>(&self, key: &VaultKey, generation: Option<u64>, name: &'static str, operation: F) -> Option<R>
+            where F: FnOnce(&T) -> R {
+        loop {
+            let (key, slot) = self.find_readable_slot(key, generation)?;
+            let item = read_slot(&slot);
+            if let Some(item) = item.as_ref() {
+                let _held = self.hold(Scope::Item(key), name);
+                return Some(operation(item));
+            }
+            drop(item);
+            thread::yield_now();
+        }
+    }
+
+    /// Applies the operation to a mutable reference to the stored object with a matching key and returns its result, if the key exists, otherwise returns None without calling the operation. The item is modified in place. Only the item itself is locked while the operation runs, so other keys can be used in the meantime.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<Vec<i32>>::new();
+    /// let key = vault.add(vec![1, 2]);
+    /// 
+    /// let new_length = vault.with_item_mut(&key, |v| {
+    ///     v.push(3);
+    ///     v.len()
+    /// });
+    /// assert_eq!(Some(3), new_length);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_item_mut<R, F>(&self, key: &VaultKey, operation: F) -> Option<R>
+            where F: FnOnce(&mut T) -> R {
+        self.modify(key, None, "Vault::with_item_mut", operation)
+    }
+
+    /// Applies the operation to mutable references to the stored objects with matching keys, all at once, and returns its result. The references are in the same order as the keys, with None for keys that have no item. A key given more than once is only looked up the first time, and is None after that, as is any later key that finds the same item, like an alias made with `add_alias` and its item's key. No other thread can see or change these items until the operation returns, but other keys can still be read and updated.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let from = vault.add(10);
+    /// let to = vault.add(0);
+    /// 
+    /// let moved = vault.with_items(&[from, to, VaultKey::new()], |items| {
+    ///     match items {
+    ///         [Some(from), Some(to), None] => {
+    ///             **from -= 4;
+    ///             **to += 4;
+    ///             true
+    ///         }
+    ///         _ => false,
+    ///     }
+    /// });
+    /// assert_eq!(true, moved);
+    /// assert_eq!(Some(6), vault.get_clone(&from));
+    /// assert_eq!(Some(4), vault.get_clone(&to));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_items<R, F>(&self, keys: &[VaultKey], operation: F) -> R
+            where F: FnOnce(&mut [Option<&mut T>]) -> R {
+        let unlocked = self.read_all();
+        let _held = self.hold(Scope::Vault, "Vault::with_items");
+        // Slots are locked in the order of the keys they are stored under, so
+        // that two calls with overlapping keys can't deadlock; the sort is
+        // stable, so of keys finding the same item the first one given that
+        // finds it is the one locked.
+        let resolved_keys = keys.iter().map(|key| self.resolve(key)).collect::<Vec<_>>();
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&index| resolved_keys[index].0);
+        let mut locked = keys.iter().map(|_| None).collect::<Vec<_>>();
+        let mut previous = None;
+        for index in order {
+            let (key, aliased) = &resolved_keys[index];
+            if previous != Some(key) {
+                locked[index] = resolved(unlocked.get(key), *aliased).map(write_slot);
+                if locked[index].is_some() {
+                    previous = Some(key);
+                }
+            }
+        }
+        let mut items = locked.iter_mut()
+            .map(|item| item.as_mut().and_then(|item| item.as_mut()))
+            .collect::<Vec<_>>();
+        let result = operation(&mut items);
+        drop(items);
+        for (key, item) in keys.iter().zip(&locked) {
+            if let (Some(item), Some(slot)) = (item, unlocked.get(key)) {
+                self.reweigh(key, slot, item);
+            }
+        }
+        drop(locked);
+        for key in keys {
+            self.watches.changed(key);
+        }
+        result
+    }
+
+    /// Adds an item to the vault with the specified key. If the key already is in use, the item is not added and this returns false. If the key is not already in use, the item is added and returns true.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// let is_true = vault.add_with_key(1, &key);
+    /// assert_eq!(true, is_true);
+    /// let is_false = vault.add_with_key(2, &key);
+    /// assert_eq!(false, is_false);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_with_key(&self, to_add: T, key: &VaultKey) -> bool {
+        self.add_with_key_in_generation(to_add, key).is_some()
+    }
+
+    /// Adds an object to the vault with the specified key, like `add_with_key`, but tells why it wasn't added: `VaultError::KeyInUse` if the key is already in use, or held back by `reserve_key` or as another item's second key, and `VaultError::Full` if the vault was made with `with_max_items` and is full. The object is dropped if it isn't added.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_max_items(1);
+    /// let key = VaultKey::new();
+    /// 
+    /// vault.add_with_key_checked(1, &key)?;
+    /// assert_eq!(Err(VaultError::KeyInUse), vault.add_with_key_checked(2, &key));
+    /// assert_eq!(Err(VaultError::Full), vault.add_with_key_checked(2, &VaultKey::new()));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_with_key_checked(&self, to_add: T, key: &VaultKey) -> Result<(), VaultError> {
+        self.add_with_key_checked_in_generation(to_add, key).map(|_| ())
+    }
+
+    // Adds the item like add_with_key, and returns the generation it was
+    // added in, or None if it wasn't.
+    pub(crate) fn add_with_key_in_generation(&self, to_add: T, key: &VaultKey) -> Option<u64> {
+        self.add_with_key_checked_in_generation(to_add, key).ok()
+    }
+
+    fn add_with_key_checked_in_generation(&self, to_add: T, key: &VaultKey) -> Result<u64, VaultError> {
+        let load = self.load(&to_add);
+        let generation = {
+            let room = self.room(key, load);
+            let mut unlocked = self.write_shard(key);
+            match unlocked.entry(*key) {
+                hash_map::Entry::Occupied(_) => return Err(VaultError::KeyInUse),
+                hash_map::Entry::Vacant(_) if self.access.is_stand_in(key) => return Err(VaultError::KeyInUse),
+                hash_map::Entry::Vacant(_) if !room.admit(key, load) => return Err(VaultError::Full),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.store(key, to_add, load));
+                    self.generation()
+                }
+            }
+        };
+        self.watches.changed(key);
+        Ok(generation)
+    }
+
+    /// Adds an item to the vault with the specified key, replacing any item already stored under that key. Returns the replaced item, if there was one, otherwise returns None. Nothing is replaced, and the new item is dropped without being added, if the stored item can't be removed with the key alone, like with `remove`, or if the key is the untyped form of a `ReadKey` or the second key of an item added with `add_dual`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// assert_eq!(None, vault.replace(&key, 1));
+    /// assert_eq!(Some(1), vault.replace(&key, 2));
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn replace(&self, key: &VaultKey, to_add: T) -> Option<T> {
+        let load = self.load(&to_add);
+        let replaced = {
+            let room = self.room(key, load);
+            let mut unlocked = self.write_shard(key);
+            let refused = match unlocked.get(key) {
+                Some(slot) => self.access.refusal(key, slot).is_some(),
+                None => self.access.is_stand_in(key),
+            };
+            if refused {
+                return None;
+            }
+            if !unlocked.contains_key(key) && !room.admit(key, load) {
+                panic!("{}", VaultError::Full);
+            }
+            // The item keeps its place in the eviction order, as a use.
+            self.access.release(key);
+            self.names.release(key);
+            self.used(key);
+            let slot = self.store(key, to_add, load);
+            unlocked.insert(*key, slot)
+        };
+        self.watches.changed(key);
+        take_slot(replaced?)
+    }
+
+    /// Adds the item produced by the initializer to the vault with the specified key, if the key is not already in use. The check and the insert happen atomically, and the initializer is only called when the key is not in use and the vault isn't full. Returns true if the item was added, otherwise returns false.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// let inserted = vault.get_or_insert_with(&key, || 1);
+    /// assert_eq!(true, inserted);
+    /// let inserted = vault.get_or_insert_with(&key, || 2);
+    /// assert_eq!(false, inserted);
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn get_or_insert_with<F>(&self, key: &VaultKey, init: F) -> bool
+            where F: FnOnce() -> T {
+        let room = self.room(key, self.least_load());
+        let inserted = match self.write_shard(key).entry(*key) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(_) if self.access.is_stand_in(key) || !room.fits(self.least_load()) => false,
+            hash_map::Entry::Vacant(entry) => {
+                let _held = self.hold(self.shard_scope(key), "Vault::get_or_insert_with");
+                let item = init();
+                let load = self.load(&item);
+                if room.admit(key, load) {
+                    entry.insert(self.store(key, item, load));
+                    true
+                } else {
+                    false
+                }
+            }
+        };
+        drop(room);
+        if inserted {
+            self.watches.changed(key);
+        }
+        inserted
+    }
+
+    /// Moves the item stored under the old key to the new key. Returns false and changes nothing if there is no item with the old key or the new key is already in use, otherwise returns true.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let old_key = vault.add(1);
+    /// let new_key = VaultKey::new();
+    /// 
+    /// let moved = vault.rekey(&old_key, &new_key);
+    /// assert_eq!(true, moved);
+    /// assert_eq!(false, vault.has_item(&old_key));
+    /// assert_eq!(Some(1), vault.get_clone(&new_key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn rekey(&self, old: &VaultKey, new: &VaultKey) -> bool {
+        let mut unlocked = self.write_all();
+        if !unlocked.contains_key(old) || unlocked.contains_key(new) || self.access.is_stand_in(new) {
+            return false;
+        }
+        let item = unlocked.remove(old).expect("old key was checked");
+        unlocked.insert(*new, item);
+        self.moved(&[(*old, *new)]);
+        drop(unlocked);
+        self.watches.changed(old);
+        self.watches.changed(new);
+        true
+    }
+
+    /// Moves the item stored under the old key to a newly generated key. Returns the new key, or None and changes nothing if there is no item with the old key.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let old_key = vault.add(1);
+    /// 
+    /// let new_key = vault.rekey_fresh(&old_key).unwrap();
+    /// assert_ne!(old_key, new_key);
+    /// assert_eq!(Some(1), vault.get_clone(&new_key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn rekey_fresh(&self, old: &VaultKey) -> Option<VaultKey> {
+        let mut unlocked = self.write_all();
+        let slot = unlocked.remove(old)?;
+        let new = keygen::fresh_key(&*self.keys, |key| Some(key).filter(|key| key != old && !unlocked.contains_key(key) && !self.access.is_stand_in(key)));
+        unlocked.insert(new, slot);
+        self.moved(&[(*old, new)]);
+        drop(unlocked);
+        self.watches.changed(old);
+        Some(new)
+    }
+
+    /// Moves every item in the vault to a fresh key, all at once, and returns a map from each old key to the new key of its item. No old key is used again, so afterward none of them finds an item. The whole vault is locked while the keys are rotated.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let old = vault.add(1);
+    /// 
+    /// let rotated = vault.rotate_all_keys();
+    /// assert_eq!(false, vault.has_item(&old));
+    /// assert_eq!(Some(1), vault.get_clone(&rotated[&old]));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn rotate_all_keys(&self) -> HashMap<VaultKey, VaultKey> {
+        let mut unlocked = self.write_all();
+        // Every new key is drawn before any item is moved, so that a key
+        // generator that panics leaves the vault as it was, and unpoisoned.
+        let drawn = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut rotated = HashMap::with_capacity(unlocked.len());
+            let mut new_keys = HashSet::with_capacity(unlocked.len());
+            for old in unlocked.keys() {
+                let new = keygen::fresh_key(&*self.keys, |key| Some(key).filter(|key| !unlocked.contains_key(key) && !new_keys.contains(key) && !self.access.is_stand_in(key)));
+                new_keys.insert(new);
+                rotated.insert(*old, new);
+            }
+            rotated
+        }));
+        let rotated = match drawn {
+            Ok(rotated) => rotated,
+            Err(payload) => {
+                drop(unlocked);
+                panic::resume_unwind(payload)
+            }
+        };
+        let slots = unlocked.drain().collect::<Vec<_>>();
+        for (old, slot) in slots {
+            unlocked.insert(rotated[&old], slot);
+        }
+        self.moved(&rotated.iter().map(|(old, new)| (*old, *new)).collect::<Vec<_>>());
+        drop(unlocked);
+        self.watches.changed_all();
+        rotated
+    }
+
+    /// Exchanges the items stored under the two keys. Returns false and changes nothing unless both keys have an item, otherwise returns true. Neither key is ever observed without an item.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<&str>::new();
+    /// let active = vault.add("blue");
+    /// let standby = vault.add("green");
+    /// 
+    /// vault.swap(&active, &standby);
+    /// assert_eq!(Some("green"), vault.get_clone(&active));
+    /// assert_eq!(Some("blue"), vault.get_clone(&standby));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn swap(&self, a: &VaultKey, b: &VaultKey) -> bool {
+        let mut unlocked = self.write_all();
+        if !unlocked.contains_key(a) || !unlocked.contains_key(b) {
+            return false;
+        }
+        if a != b {
+            let item_a = unlocked.remove(a).expect("key a was checked");
+            let item_b = unlocked.insert(*b, item_a).expect("key b was checked");
+            unlocked.insert(*a, item_b);
+            self.moved(&[(*a, *b), (*b, *a)]);
+            drop(unlocked);
+            self.watches.changed(a);
+            self.watches.changed(b);
+        }
+        true
+    }
+
+    /// Locks the whole vault and returns a guard for running several operations as a single step; no other thread can use the vault until the guard is dropped. The guard can't be sent to another thread. Calling any method of this vault, including `lock`, on the thread holding the guard will deadlock, or panic in debug builds, so use only the guard's own methods until it is dropped.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<&str>::new();
+    /// let primary = vault.add("primary");
+    /// let backup = VaultKey::new();
+    /// 
+    /// {
+    ///     let mut guard = vault.lock();
+    ///     if guard.has_item(&primary) {
+    ///         guard.add_with_key("backup", &backup);
+    ///         guard.remove(&primary);
+    ///     }
+    /// }
+    /// assert_eq!(false, vault.has_item(&primary));
+    /// assert_eq!(true, vault.has_item(&backup));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn lock(&self) -> VaultGuard<'_, T> {
+        let items = self.write_all();
+        VaultGuard {items, watches: &self.watches, keys: &*self.keys, generation: &self.generation, access: &self.access, names: &self.names, limit: self.limit.as_ref(), _held: self.hold(Scope::Vault, "Vault::lock")}
+    }
+
+    /// Runs the transaction on the whole vault and returns its result. Changes made through the transaction are held back until it returns, and are then made all at once; if it panics, none of them are made. Reads through the transaction see its own changes so far. The vault is locked for as long as the transaction runs, so, as with `lock`, using the vault itself from inside the transaction will deadlock.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let from = vault.add(10);
+    /// let to = vault.add(0);
+    /// 
+    /// vault.atomically(|txn| {
+    ///     txn.update_item(&from, |i| *i -= 4);
+    ///     txn.update_item(&to, |i| *i += 4);
+    /// });
+    /// assert_eq!(Some(6), vault.get_clone(&from));
+    /// assert_eq!(Some(4), vault.get_clone(&to));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn atomically<R, F>(&self, transaction: F) -> R
+            where T: Clone, F: FnOnce(&mut VaultTxn<'_, T>) -> R {
+        self.transact("Vault::atomically", transaction, |_| true)
+    }
+
+    /// Runs the transaction on the whole vault, like `atomically`, except that if the transaction returns an error none of its changes are made.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let from = vault.add(3);
+    /// let to = vault.add(0);
+    /// 
+    /// let result = vault.try_atomically(|txn| {
+    ///     txn.update_item(&to, |i| *i += 4);
+    ///     match txn.get_clone(&from) {
+    ///         Some(balance) if balance >= 4 => Ok(txn.update_item(&from, |i| *i -= 4)),
+    ///         _ => Err("insufficient funds"),
+    ///     }
+    /// });
+    /// assert_eq!(Err("insufficient funds"), result);
+    /// assert_eq!(Some(3), vault.get_clone(&from));
+    /// assert_eq!(Some(0), vault.get_clone(&to));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_atomically<R, E, F>(&self, transaction: F) -> Result<R, E>
+            where T: Clone, F: FnOnce(&mut VaultTxn<'_, T>) -> Result<R, E> {
+        self.transact("Vault::try_atomically", transaction, Result::is_ok)
+    }
+
+    /// Returns the entry for the specified key, which can be used to inspect, insert, modify, or remove the item in a single step. The part of the vault holding the key stays locked until the entry is dropped, so calling any other method on this vault while holding an entry may deadlock.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// vault.entry(&key).and_modify(|i| *i += 1).or_insert(1);
+    /// vault.entry(&key).and_modify(|i| *i += 1).or_insert(1);
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn entry(&self, key: &VaultKey) -> Entry<'_, T> {
+        let room = self.room(key, self.least_load());
+        let items = self.write_shard(key);
+        let key = *key;
+        match items.get(&key).and_then(|slot| write_slot(slot).take()) {
+            Some(item) => Entry::Occupied(OccupiedEntry {items, key, item: Some(item), room}),
+            None => Entry::Vacant(VacantEntry {items, key, room}),
+        }
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it. Returns false if an item with the key is not found, otherwise returns true.
+    /// 
+    /// Only the item itself is locked while the operation runs, so a slow operation doesn't hold up other threads using other keys. Other calls on the same key wait for the update: a `remove` of the key waits and returns the updated item, and an update that finds its item already removed returns false. Calls that look at every item, such as `values` or `retain`, also wait for it, and hold up the rest of the vault while they do. So the operation itself may use other keys, but making either kind of call on this vault from inside it deadlocks; debug builds panic instead, naming `update_item`.
+    /// 
+    /// The item is changed in place, so if the operation panics the item stays in the vault as the operation left it, which is unchanged if it panicked before changing anything. The panic is passed on to the caller without leaving the vault poisoned.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let double_me = |i: &mut i32| *i *= 2;
+    /// 
+    /// let updated = vault.update_item(&key, double_me);
+    /// assert_eq!(true, updated);
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_item<F>(&self, key: &VaultKey, operation: F) -> bool
+            where F: FnOnce(&mut T) {
+        self.update_item_in_generation(key, None, "Vault::update_item", operation)
+    }
+
+    // Updates the key's item like update_item, if the generation stands, on
+    // behalf of the named public method.
+    pub(crate) fn update_item_in_generation<F>(&self, key: &VaultKey, generation: Option<u64>, name: &'static str, operation: F) -> bool
+            where F: FnOnce(&mut T) {
+        let outcome = match self.with_slot(key, generation, |_, item| {
+            let _held = self.hold(Scope::Item(*key), name);
+            Vault::update_locked(item, operation)
+        }) {
+            Some(outcome) => outcome,
+            None => return false,
+        };
+        self.watches.changed(key);
+        outcome.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        true
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it in place, and returns the operation's result. Returns None without calling the operation if an item with the key is not found. This behaves the same as `with_item_mut`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(100);
+    /// 
+    /// let balance = vault.update_item_map(&key, |i| {
+    ///     *i -= 30;
+    ///     *i
+    /// });
+    /// assert_eq!(Some(70), balance);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_item_map<R, F>(&self, key: &VaultKey, operation: F) -> Option<R>
+            where F: FnOnce(&mut T) -> R {
+        self.modify(key, None, "Vault::update_item_map", operation)
+    }
+
+    /// Updates an item in the vault with the specified key by replacing it with the operation's result, if the operation succeeds. The operation only borrows the item, so if it returns an error the original item is left in the vault unchanged and the error is returned. Returns Ok(false) if an item with the key is not found, otherwise returns Ok(true) after a successful update.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<u8>::new();
+    /// let key = vault.add(200);
+    /// 
+    /// let add_100 = |i: &u8| i.checked_add(100).ok_or("overflow");
+    /// 
+    /// let updated = vault.try_update_item(&key, add_100);
+    /// assert_eq!(Err("overflow"), updated);
+    /// assert_eq!(Some(200), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_update_item<E, F>(&self, key: &VaultKey, operation: F) -> Result<bool, E>
+            where F: FnOnce(&T) -> Result<T, E> {
+        let updated = self.with_slot(key, None, |_, item| {
+            let _held = self.hold(Scope::Item(*key), "Vault::try_update_item");
+            let item = item.as_mut().expect("locked slot has an item");
+            *item = operation(item)?;
+            Ok(true)
+        }).unwrap_or(Ok(false));
+        if let Ok(true) = updated {
+            self.watches.changed(key);
+        }
+        updated
+    }
+
+    /// Updates the item in the vault with the specified key by applying the update operation to it, or adds the item produced by the insert operation if the key is not in use. Only one of the two operations is called, and the whole step happens atomically. Returns which of the two happened. A key that stands in for another's, like the second key of a dual item, the read key of an item added with `add_with_roles`, or a reserved key, is refused as by `replace`: no item is added under it, and Refused is returned. So is an item that can't be removed with its key alone, like with `remove`, as the update operation takes the item out of the vault; it is left as it is, and `update_item` can still change it in place. If the update operation panics, the item it was given is lost, and the key is left without one.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{UpsertResult, Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// let result = vault.update_or_insert(&key, |i| i + 1, || 1);
+    /// assert_eq!(UpsertResult::Inserted, result);
+    /// let result = vault.update_or_insert(&key, |i| i + 1, || 1);
+    /// assert_eq!(UpsertResult::Updated, result);
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_or_insert<F, G>(&self, key: &VaultKey, update: F, insert: G) -> UpsertResult
+            where F: FnOnce(T) -> T, G: FnOnce() -> T {
+        let (first, aliased) = self.resolve(key);
+        let room = self.room(&first, self.least_load());
+        let mut unlocked = self.write_shard(&first);
+        let _held = self.hold(self.shard_scope(&first), "Vault::update_or_insert");
+        let existing = resolved(unlocked.get(&first), aliased).filter(|slot| read_slot(slot).is_some()).cloned();
+        let (result, outcome) = match existing {
+            Some(slot) if self.access.refusal(&first, &slot).is_some() => return UpsertResult::Refused,
+            Some(slot) => (UpsertResult::Updated, self.replace_in(&mut unlocked, &first, &slot, |item| Some(update(item)))),
+            None if self.access.is_stand_in(key) => return UpsertResult::Refused,
+            None if !room.fits(self.least_load()) => panic!("{}", VaultError::Full),
+            None => {
+                let item = insert();
+                let load = self.load(&item);
+                if !room.admit(key, load) {
+                    panic!("{}", VaultError::Full);
+                }
+                unlocked.insert(*key, self.store(key, item, load));
+                (UpsertResult::Inserted, Ok(()))
+            }
+        };
+        drop(unlocked);
+        drop(room);
+        self.watches.changed(&first);
+        outcome.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        result
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it. If the operation returns Some, the new item is stored under the key; if it returns None, the item is removed from the vault. Returns which of these happened, or that an item with the key was not found. An item that can't be removed with its key alone, like with `remove`, is left as it is without calling the operation, and NotFound is returned. If the operation panics, the item it was given is lost, as if it had returned None.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{UpdateFilterResult, Vault};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<u32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let retry = |i: u32| i.checked_sub(1).filter(|i| *i > 0);
+    /// 
+    /// let result = vault.update_filter(&key, retry);
+    /// assert_eq!(UpdateFilterResult::Removed, result);
+    /// assert_eq!(false, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_filter<F>(&self, key: &VaultKey, operation: F) -> UpdateFilterResult
+            where F: FnOnce(T) -> Option<T> {
+        let (first, aliased) = self.resolve(key);
+        let mut unlocked = self.write_shard(&first);
+        let _held = self.hold(self.shard_scope(&first), "Vault::update_filter");
+        let slot = match resolved(unlocked.get(&first), aliased).filter(|slot| read_slot(slot).is_some()) {
+            Some(slot) if self.access.refusal(&first, slot).is_some() => return UpdateFilterResult::NotFound,
+            Some(slot) => Arc::clone(slot),
+            None => return UpdateFilterResult::NotFound,
+        };
+        let outcome = self.replace_in(&mut unlocked, &first, &slot, operation);
+        let result = if unlocked.contains_key(&first) {
+            UpdateFilterResult::Updated
+        } else {
+            UpdateFilterResult::Removed
+        };
+        drop(unlocked);
+        self.watches.changed(&first);
+        outcome.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        result
+    }
+
+    /// Applies the operation to every item in the vault in place, and returns the number of items updated. The vault stays locked for the whole pass.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// vault.add(2);
+    /// 
+    /// let updated = vault.update_all(|_, i| *i *= 10);
+    /// assert_eq!(2, updated);
+    /// assert_eq!(Some(10), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_all<F>(&self, mut operation: F) -> usize
+            where F: FnMut(&VaultKey, &mut T) {
+        let unlocked = self.write_all();
+        let _held = self.hold(Scope::Vault, "Vault::update_all");
+        let mut updated = 0;
+        for (key, slot) in unlocked.iter() {
+            let mut item = write_slot(slot);
+            if let Some(item) = item.as_mut() {
+                operation(key, item);
+                updated += 1;
+            }
+            self.reweigh(key, slot, &item);
+        }
+        drop(unlocked);
+        self.watches.changed_all();
+        updated
+    }
+
+    /// Applies the operation in place to every item with one of the specified keys, all at once, and returns the number of items updated. Keys with no item are skipped, and a key repeated in the slice is updated once per occurrence.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<bool>::new();
+    /// let key_1 = vault.add(false);
+    /// let key_2 = vault.add(false);
+    /// 
+    /// let updated = vault.update_many(&[key_1, VaultKey::new(), key_2], |_, expired| *expired = true);
+    /// assert_eq!(2, updated);
+    /// assert_eq!(Some(true), vault.get_clone(&key_2));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_many<F>(&self, keys: &[VaultKey], mut operation: F) -> usize
+            where F: FnMut(&VaultKey, &mut T) {
+        let unlocked = self.write_all();
+        let _held = self.hold(Scope::Vault, "Vault::update_many");
+        let mut updated = 0;
+        for key in keys {
+            let (first, aliased) = self.resolve(key);
+            if let Some(slot) = resolved(unlocked.get(&first), aliased) {
+                let mut item = write_slot(slot);
+                if let Some(item) = item.as_mut() {
+                    operation(key, item);
+                    updated += 1;
+                    self.watches.changed(&first);
+                }
+                self.reweigh(&first, slot, &item);
+            }
+        }
+        updated
+    }
+
+    /// Clears the contents of the vault.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// vault.clear();
+    /// 
+    /// let has_item = vault.has_item(&key);
+    /// assert_eq!(false, has_item);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn clear(&self) {
+        let mut unlocked = self.write_all();
+        self.next_generation(&unlocked);
+        unlocked.clear();
+        drop(unlocked);
+        self.watches.changed_all();
+    }
+
+    /// Adds an object to the vault and returns a key, like `add`, but never blocks or panics. Returns an error if the vault is in use by another thread or a thread panicked while changing it, or `VaultError::Full` if it was made with `with_max_items` and is full.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// let key = vault.try_add(1)?;
+    /// assert_eq!(true, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_add(&self, to_add: T) -> Result<VaultKey, VaultError> {
+        let load = self.load(&to_add);
+        let mut to_add = Some(to_add);
+        let key = keygen::fresh_key(&*self.keys, |key| {
+            let room = match Room::try_lock(self.limit.as_ref(), &self.count, &self.watches) {
+                Ok(room) => room,
+                Err(error) => return Some(Err(error)),
+            };
+            let mut unlocked = match self.try_write_shard(&key) {
+                Ok(unlocked) => unlocked,
+                Err(error) => return Some(Err(error)),
+            };
+            match unlocked.entry(key) {
+                hash_map::Entry::Occupied(_) => None,
+                hash_map::Entry::Vacant(_) if !room.admit(&key, load) => Some(Err(VaultError::Full)),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.store(&key, to_add.take().expect("item is added once"), load));
+                    Some(Ok(key))
+                }
+            }
+        })?;
+        self.watches.changed(&key);
+        Ok(key)
+    }
+
+    /// Removes and returns the stored object with a matching key, like `remove`, but never blocks or panics. Returns `VaultError::KeyNotFound` if there is no item with the key, `VaultError::Access` if the item can't be removed with the key alone, or an error if the vault is in use by another thread or a thread panicked while changing it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Ok(1), vault.try_remove(&key));
+    /// assert_eq!(Err(VaultError::KeyNotFound), vault.try_remove(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_remove(&self, key: &VaultKey) -> Result<T, VaultError> {
+        let (first, aliased) = self.resolve(key);
+        let mut unlocked = self.try_write_shard(&first)?;
+        let slot = resolved(unlocked.get(&first), aliased).cloned().ok_or_else(|| match self.access.stands_in_for(key) {
+            Some((_, refusal)) => VaultError::Access(refusal),
+            None => VaultError::KeyNotFound,
+        })?;
+        if let Some(refusal) = self.access.refusal(&first, &slot) {
+            return Err(VaultError::Access(refusal));
+        }
+        let item = slot.try_write()?.take().ok_or(VaultError::KeyNotFound)?;
+        self.forget(&first);
+        unlocked.remove(&first);
+        self.watches.changed(&first);
+        Ok(item)
+    }
+
+    /// Returns whether there exists an item in the vault with the provided key, like `has_item`, but never blocks or panics. Returns an error if the vault is in use by another thread or a thread panicked while changing it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Ok(true), vault.try_has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_has_item(&self, key: &VaultKey) -> Result<bool, VaultError> {
+        self.find_item(key, None, |key| self.try_read_shard(key))
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it, like `update_item`, but never blocks or panics on the vault's lock. Returns `VaultError::KeyNotFound` if there is no item with the key, or an error if the vault is in use by another thread or a thread panicked while changing it. For operations that can themselves fail, see `try_update_item`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Ok(()), vault.try_update(&key, |i| *i += 1));
+    /// assert_eq!(Err(VaultError::KeyNotFound), vault.try_update(&VaultKey::new(), |i| *i += 1));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_update<F>(&self, key: &VaultKey, operation: F) -> Result<(), VaultError>
+            where F: FnOnce(&mut T) {
+        let (key, aliased) = self.resolve(key);
+        let slot = resolved(self.try_read_shard(&key)?.get(&key), aliased).cloned().ok_or(VaultError::KeyNotFound)?;
+        let outcome = {
+            let mut item = slot.try_write()?;
+            if item.is_none() {
+                return Err(VaultError::WouldBlock);
+            }
+            let _held = self.hold(Scope::Item(key), "Vault::try_update");
+            let outcome = Vault::update_locked(&mut item, operation);
+            self.reweigh(&key, &slot, &item);
+            outcome
+        };
+        self.watches.changed(&key);
+        outcome.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        Ok(())
+    }
+
+    /// Clears the contents of the vault, like `clear`, but never blocks or panics. Returns an error if the vault is in use by another thread or a thread panicked while changing it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// 
+    /// vault.try_clear()?;
+    /// assert_eq!(true, vault.is_empty());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn try_clear(&self) -> Result<(), VaultError> {
+        let mut unlocked = self.try_write_all()?;
+        self.next_generation(&unlocked);
+        unlocked.clear();
+        drop(unlocked);
+        self.watches.changed_all();
+        Ok(())
+    }
+
+    /// Adds an object to the vault and returns a key, like `add`, but waits no longer than the timeout for the vault to be free. Returns `VaultError::Timeout`, without adding the object, if it isn't, and `VaultError::Full` if the vault was made with `with_max_items` and is full.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// let key = vault.add_timeout(1, Duration::from_millis(10))?;
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_timeout(&self, to_add: T, timeout: Duration) -> Result<VaultKey, VaultError> {
+        let start = Instant::now();
+        let load = self.load(&to_add);
+        let mut to_add = Some(to_add);
+        let key = keygen::fresh_key(&*self.keys, |key| {
+            let room = match Room::lock_for(self.limit.as_ref(), &self.count, &self.watches, timeout.saturating_sub(start.elapsed())) {
+                Ok(room) => room,
+                Err(error) => return Some(Err(error)),
+            };
+            let items = match within(self.shard(&key).try_write_for(timeout.saturating_sub(start.elapsed()))) {
+                Ok(items) => items,
+                Err(error) => return Some(Err(error)),
+            };
+            match self.guard(items).entry(key) {
+                hash_map::Entry::Occupied(_) => None,
+                hash_map::Entry::Vacant(_) if !room.admit(&key, load) => Some(Err(VaultError::Full)),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.store(&key, to_add.take().expect("item is added once"), load));
+                    Some(Ok(key))
+                }
+            }
+        })?;
+        self.watches.changed(&key);
+        Ok(key)
+    }
+
+    /// Removes and returns the stored object with a matching key, like `remove`, but waits no longer than the timeout for the vault and the item to be free. Returns `VaultError::Timeout`, leaving the item in the vault, if they aren't, and `VaultError::Access` if the item can't be removed with its key alone.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Some(1), vault.remove_timeout(&key, Duration::from_millis(10))?);
+    /// assert_eq!(None, vault.remove_timeout(&key, Duration::from_millis(10))?);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove_timeout(&self, key: &VaultKey, timeout: Duration) -> Result<Option<T>, VaultError> {
+        let start = Instant::now();
+        let (key, aliased) = self.resolve(key);
+        let mut unlocked = self.guard(within(self.shard(&key).try_write_for(timeout))?);
+        let slot = match resolved(unlocked.get(&key), aliased) {
+            Some(slot) => Arc::clone(slot),
+            None => return Ok(None),
+        };
+        if let Some(refusal) = self.access.refusal(&key, &slot) {
+            return Err(VaultError::Access(refusal));
+        }
+        let item = within(slot.try_write_for(timeout.saturating_sub(start.elapsed())))?.take();
+        self.forget(&key);
+        unlocked.remove(&key);
+        self.watches.changed(&key);
+        Ok(item)
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it, like `update_item`, but waits no longer than the timeout for the item to be free. Returns `VaultError::Timeout`, without calling the operation, if it isn't; otherwise returns whether an item with the key was found.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(true, vault.update_timeout(&key, |i| *i += 1, Duration::from_millis(10))?);
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_timeout<F>(&self, key: &VaultKey, operation: F, timeout: Duration) -> Result<bool, VaultError>
+            where F: FnOnce(&mut T) {
+        let start = Instant::now();
+        let (key, aliased) = self.resolve(key);
+        loop {
+            let left = timeout.saturating_sub(start.elapsed());
+            let slot = match resolved(within(self.shard(&key).try_read_for(left))?.get(&key), aliased) {
+                Some(slot) => Arc::clone(slot),
+                None => return Ok(false),
+            };
+            let outcome = {
+                let mut item = within(slot.try_write_for(timeout.saturating_sub(start.elapsed())))?;
+                if item.is_none() {
+                    drop(item);
+                    thread::yield_now();
+                    continue;
+                }
+                let _held = self.hold(Scope::Item(key), "Vault::update_timeout");
+                let outcome = Vault::update_locked(&mut item, operation);
+                self.reweigh(&key, &slot, &item);
+                outcome
+            };
+            self.watches.changed(&key);
+            outcome.unwrap_or_else(|payload| panic::resume_unwind(payload));
+            return Ok(true);
+        }
+    }
+
+    /// Removes and returns the stored object with a matching key, waiting for one to be added if there isn't one yet. Gives up and returns None once the timeout, if any, runs out. If the item is added and taken by another thread before this one gets to it, this carries on waiting.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Arc::new(Vault::<&str>::new());
+    /// let key = VaultKey::new();
+    /// 
+    /// let producer = {
+    ///     let vault = Arc::clone(&vault);
+    ///     thread::spawn(move || vault.add_with_key("result", &key))
+    /// };
+    /// assert_eq!(Some("result"), vault.take_blocking(&key, None));
+    /// assert_eq!(None, vault.take_blocking(&key, Some(Duration::from_millis(10))));
+    /// # producer.join().unwrap();
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn take_blocking(&self, key: &VaultKey, timeout: Option<Duration>) -> Option<T> {
+        self.watches.wait(key, timeout, || self.remove(key))
+    }
+
+    /// Waits until there is an item with the provided key, leaving it in the vault, and returns true. Returns true straight away if the item is already there, or false if the timeout runs out first.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(true, vault.wait_for_item(&key, Duration::from_secs(1)));
+    /// assert_eq!(false, vault.wait_for_item(&VaultKey::new(), Duration::from_millis(10)));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn wait_for_item(&self, key: &VaultKey, timeout: Duration) -> bool {
+        self.watches.wait(key, Some(timeout), || self.has_item(key).then_some(())).is_some()
+    }
+
+    /// Waits until the item with the provided key is added, updated, replaced or removed, and returns what the key holds afterward. If it changes more than once before this thread wakes, the latest item is returned. Any write to the item counts as a change, even one that leaves it equal to what it was. Returns `WatchResult::TimedOut` if the timeout runs out first.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::Arc;
+    /// # use std::thread;
+    /// # use std::time::Duration;
+    /// # use bank_vault::{Vault, WatchResult};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Arc::new(Vault::<i32>::new());
+    /// let key = vault.add(1);
+    /// 
+    /// let watcher = {
+    ///     let vault = Arc::clone(&vault);
+    ///     thread::spawn(move || vault.watch_item(&key, Duration::from_secs(10)))
+    /// };
+    /// thread::sleep(Duration::from_millis(10));
+    /// vault.update_item(&key, |i| *i += 1);
+    /// assert_eq!(WatchResult::Changed(2), watcher.join().unwrap());
+    /// assert_eq!(WatchResult::TimedOut, vault.watch_item(&key, Duration::from_millis(10)));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn watch_item(&self, key: &VaultKey, timeout: Duration) -> WatchResult<T>
+            where T: Clone {
+        let mut started = false;
+        let changed = self.watches.wait(key, Some(timeout), || {
+            if !started {
+                started = true;
+                return None;
+            }
+            Some(self.get_clone(key).map_or(WatchResult::Removed, WatchResult::Changed))
+        });
+        changed.unwrap_or(WatchResult::TimedOut)
+    }
+
+    /// Exchanges the entire contents of this vault with the contents of the other vault, in a single step. Keys that resolved in one vault resolve to the same items in the other afterward. Both vaults are locked in a fixed order, so concurrent swaps in opposite directions can't deadlock.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let blue = Vault::<&str>::new();
+    /// let green = Vault::<&str>::new();
+    /// let key = green.add("next config");
+    /// 
+    /// blue.swap_contents(&green);
+    /// assert_eq!(Some("next config"), blue.get_clone(&key));
+    /// assert_eq!(false, green.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn swap_contents(&self, other: &Vault<T>) {
+        if std::ptr::eq(self, other) {
+            return;
+        }
+        let (mut mine, mut theirs) = self.lock_pair(other);
+        let (my_keys, their_keys) = (mine.keys().copied().collect::<Vec<_>>(), theirs.keys().copied().collect::<Vec<_>>());
+        if mine.shard_count() == theirs.shard_count() {
+            for (mine, theirs) in mine.shards_mut().zip(theirs.shards_mut()) {
+                std::mem::swap(mine, theirs);
+            }
+        } else {
+            let my_items: Vec<(VaultKey, Slot<T>)> = mine.drain().collect();
+            let their_items: Vec<(VaultKey, Slot<T>)> = theirs.drain().collect();
+            mine.extend(their_items);
+            theirs.extend(my_items);
+        }
+        self.relist(&mine, &my_keys);
+        other.relist(&theirs, &their_keys);
+        drop((mine, theirs));
+        self.watches.changed_all();
+        other.watches.changed_all();
+    }
+
+    /// Moves the item with the specified key from this vault into the destination vault, keeping the same key, in a single step. Returns false and changes nothing if the key has no item in this vault or is already in use in the destination, if the item can't be removed with its key alone, like with `remove`, or if the destination is full, otherwise returns true. Both vaults are locked in a fixed order, so concurrent transfers in opposite directions can't deadlock.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let pending = Vault::<&str>::new();
+    /// let active = Vault::<&str>::new();
+    /// let key = pending.add("job");
+    /// 
+    /// let moved = pending.transfer(&key, &active);
+    /// assert_eq!(true, moved);
+    /// assert_eq!(false, pending.has_item(&key));
+    /// assert_eq!(true, active.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn transfer(&self, key: &VaultKey, dest: &Vault<T>) -> bool {
+        if std::ptr::eq(self, dest) {
+            return false;
+        }
+        let room = dest.room(key, dest.least_load());
+        let (mut mine, mut theirs) = self.lock_shard_pair(dest, key);
+        let load = match mine.get(key) {
+            Some(slot) if self.access.refusal(key, slot).is_some() => return false,
+            Some(slot) => read_slot(slot).as_ref().map_or(0, |item| dest.load(item)),
+            None => return false,
+        };
+        if theirs.contains_key(key) || !room.admit(key, load) {
+            return false;
+        }
+        match mine.remove(key) {
+            Some(slot) => {
+                self.forget(key);
+                if let Some(limit) = &dest.limit {
+                    limit.book(key, &slot, load);
+                }
+                theirs.insert(*key, slot);
+                drop((mine, theirs, room));
+                self.watches.changed(key);
+                dest.watches.changed(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves every item from the other vault into this vault, keeping their keys, and consumes the other vault. If a key is already in use in this vault, the item already here is kept, the other vault's item is dropped, and the key is included in the returned list of skipped keys; so are the keys of the items left over once this vault is full, if it was made with `with_max_items`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let main = Vault::<i32>::new();
+    /// let worker = Vault::<i32>::new();
+    /// let key = worker.add(1);
+    /// 
+    /// let skipped = main.absorb(worker);
+    /// assert_eq!(true, skipped.is_empty());
+    /// assert_eq!(Some(1), main.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn absorb(&self, other: Vault<T>) -> Vec<VaultKey> {
+        let theirs = other.into_inner();
+        let mut evicted = Evicted::new(self.limit.as_ref(), &self.watches);
+        let mut unlocked = self.write_all();
+        unlocked.reserve(theirs.len());
+        let mut skipped = Vec::new();
+        let mut added = Vec::new();
+        for (key, item) in theirs {
+            if unlocked.contains_key(&key) {
+                skipped.push(key);
+                continue;
+            }
+            let load = self.load(&item);
+            self.make_room(&mut unlocked, load, &mut evicted);
+            if !limit::fits(self.limit.as_ref(), unlocked.len(), load) {
+                skipped.push(key);
+                continue;
+            }
+            let slot = self.store(&key, item, load);
+            unlocked.insert(key, slot);
+            self.inserted(&key);
+            added.push(key);
+        }
+        drop(unlocked);
+        for key in &added {
+            self.watches.changed(key);
+        }
+        skipped
+    }
+
+    /// Compares this vault with the other vault and returns the keys found only in this vault, the keys found only in the other vault, and the keys found in both but with unequal items. Both vaults are locked in a fixed order for the whole comparison, so the result reflects a single moment in time.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let left = Vault::<i32>::new();
+    /// let right = Vault::<i32>::new();
+    /// let key = left.add(1);
+    /// right.add_with_key(2, &key);
+    /// let added = right.add(3);
+    /// 
+    /// let diff = left.diff(&right);
+    /// assert_eq!(true, diff.only_left.is_empty());
+    /// assert_eq!(vec![added], diff.only_right);
+    /// assert_eq!(vec![key], diff.changed);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn diff(&self, other: &Vault<T>) -> VaultDiff
+            where T: PartialEq {
+        let mut diff = VaultDiff::default();
+        if std::ptr::eq(self, other) {
+            return diff;
+        }
+        let (mine, theirs) = self.lock_pair(other);
+        for (key, slot) in mine.iter() {
+            match theirs.get(key) {
+                Some(other_slot) if *read_slot(other_slot) != *read_slot(slot) => diff.changed.push(*key),
+                Some(_) => {}
+                None => diff.only_left.push(*key),
+            }
+        }
+        diff.only_right.extend(theirs.keys().filter(|key| !mine.contains_key(key)));
+        diff
+    }
+
+    /// Clears the contents of the vault, handing each removed item and its key to the disposal operation. The vault is emptied and unlocked before the disposal operation is first called, so a slow disposal doesn't block other threads, and the vault is empty even if the disposal operation panics.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<&str>::new();
+    /// vault.add("temp file");
+    /// 
+    /// let mut disposed = Vec::new();
+    /// vault.clear_with(|_, item| disposed.push(item));
+    /// assert_eq!(vec!["temp file"], disposed);
+    /// assert_eq!(true, vault.is_empty());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn clear_with<F>(&self, mut dispose: F)
+            where F: FnMut(VaultKey, T) {
+        for (key, item) in self.drain() {
+            dispose(key, item);
+        }
+    }
+
+    /// Consumes the vault and returns its items in a map, keyed by their vault keys, without copying them. The items are returned even if a thread panicked while using the vault.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let key = VaultKey::new();
+    /// let vault = Vault::from_entries(vec![(key, 1)]);
+    /// 
+    /// let map = vault.into_inner();
+    /// assert_eq!(Some(&1), map.get(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn into_inner(self) -> HashMap<VaultKey, T> {
+        let mut items = HashMap::with_capacity(self.len());
+        for shard in self.shards {
+            let shard = shard.into_inner().unwrap_or_else(PoisonError::into_inner);
+            items.extend(shard.into_iter().filter_map(|(key, slot)| take_slot(slot).map(|item| (key, item))));
+        }
+        items
+    }
+
+    /// Returns whether a thread panicked while changing the vault. The vault keeps working after a panic, but the try_ methods report `VaultError::Poisoned` until `clear_poison` is called. With the `parking_lot` feature the vault is never poisoned.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// assert_eq!(false, vault.is_poisoned());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn is_poisoned(&self) -> bool {
+        self.shards.iter().any(|shard| shard.is_poisoned())
+            || self.read_all().values().any(|slot| slot.is_poisoned())
+    }
+
+    /// Marks the vault as no longer poisoned, so that the try_ methods stop reporting `VaultError::Poisoned`. Blocks until the vault is free.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::panic::{self, AssertUnwindSafe};
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+    ///     vault.with_item_mut(&key, |_| panic!("oops"));
+    /// }));
+    /// 
+    /// vault.clear_poison();
+    /// assert_eq!(false, vault.is_poisoned());
+    /// assert_eq!(Ok(true), vault.try_has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn clear_poison(&self) {
+        let unlocked = self.write_all();
+        for slot in unlocked.values() {
+            slot.clear_poison();
+        }
+        for shard in &self.shards {
+            shard.clear_poison();
+        }
+    }
+
+    /// Returns the number of items in the vault. This reads an atomic counter and never waits on the vault's lock, so it is cheap to poll while other threads are modifying the vault. The counter covers every shard and each change moves it as the change's lock is released, so while a call on several shards is releasing them it may briefly reflect only part of that call's changes.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// vault.add(2);
+    /// 
+    /// assert_eq!(2, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the most items the vault may hold, if it was made with `with_max_items`, otherwise returns None.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// assert_eq!(Some(10), Vault::<i32>::with_max_items(10).max_items());
+    /// assert_eq!(None, Vault::<i32>::new().max_items());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn max_items(&self) -> Option<usize> {
+        self.limit.as_ref().and_then(Limit::max_items)
+    }
+
+    /// Returns the most the vault's items may weigh, if it was made with `with_max_weight` or `with_weighted_eviction`, otherwise returns None.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// assert_eq!(Some(100), Vault::with_max_weight(100, |item: &String| item.len()).max_weight());
+    /// assert_eq!(None, Vault::<String>::new().max_weight());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn max_weight(&self) -> Option<usize> {
+        self.limit.as_ref().and_then(Limit::max_weight)
+    }
+
+    /// Returns the total weight of the items in the vault, if it was made with `with_max_weight` or `with_weighted_eviction`, otherwise returns 0. Like `len`, it may be out of date by the time it is used.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::with_max_weight(100, |item: &String| item.len());
+    /// vault.add(String::from("abc"));
+    /// 
+    /// assert_eq!(3, vault.weight());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn weight(&self) -> usize {
+        self.limit.as_ref().and_then(Limit::weight).unwrap_or(0)
+    }
+
+    /// Returns the number of items the vault has room for without growing, counting every shard. Items are spread across the shards by key, so a shard may grow before the vault holds this many.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_capacity(100);
+    /// vault.add(1);
+    /// 
+    /// assert!(vault.capacity() >= 100);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.read_all().capacity()
+    }
+
+    /// Makes room for at least the given number of items more, so that a bulk load like `add_many` doesn't have to grow the vault as it goes. The room is spread evenly across the shards, the same as for `with_capacity`; the items already in the vault stay where they are.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(0);
+    /// vault.reserve(1000);
+    /// 
+    /// assert!(vault.capacity() >= 1001);
+    /// assert_eq!(Some(0), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn reserve(&self, additional: usize) {
+        let mut unlocked = self.write_all();
+        let per_shard = additional.div_ceil(unlocked.shard_count());
+        for shard in unlocked.shards_mut() {
+            shard.reserve(per_shard);
+        }
+    }
+
+    /// Gives back the room the vault has beyond what its items need, for vaults that have shrunk a long way. Every key stays valid.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_capacity(1000);
+    /// let key = vault.add(1);
+    /// vault.shrink_to_fit();
+    /// 
+    /// assert!(vault.capacity() < 1000);
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn shrink_to_fit(&self) {
+        for shard in self.write_all().shards_mut() {
+            shard.shrink_to_fit();
+        }
+    }
+
+    /// Gives back room the vault has beyond what its items need, keeping room for at least the given number of items, spread evenly across the shards the same as for `with_capacity`. Every key stays valid.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::with_capacity(1000);
+    /// vault.shrink_to(100);
+    /// 
+    /// assert!(vault.capacity() >= 100);
+    /// assert!(vault.capacity() < 1000);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn shrink_to(&self, min_capacity: usize) {
+        let mut unlocked = self.write_all();
+        let per_shard = min_capacity.div_ceil(unlocked.shard_count());
+        for shard in unlocked.shards_mut() {
+            shard.shrink_to(per_shard);
+        }
+    }
+
+    /// Returns true if the vault holds no items, otherwise returns false. Like `len`, this never waits on the vault's lock.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// assert_eq!(true, vault.is_empty());
+    /// 
+    /// vault.add(1);
+    /// assert_eq!(false, vault.is_empty());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the vault's generation, which starts at zero and goes up by one every time the vault is emptied by `clear`, `try_clear`, `clear_with`, `drain` or `VaultGuard::clear`. A `GenerationalVault` uses it to tell keys handed out before the last clear from those handed out since.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// assert_eq!(0, vault.generation());
+    /// 
+    /// vault.clear();
+    /// assert_eq!(1, vault.generation());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
+    /// Removes every item from the vault and returns them along with their keys, in no particular order. Items added by other threads are either returned here or remain in the vault afterward.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let drained = vault.drain();
+    /// assert_eq!(vec![(key, 1)], drained);
+    /// assert_eq!(true, vault.is_empty());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn drain(&self) -> Vec<(VaultKey, T)> {
+        let drained: Vec<(VaultKey, Slot<T>)> = {
+            let mut unlocked = self.write_all();
+            self.next_generation(&unlocked);
+            unlocked.drain().collect()
+        };
+        self.watches.changed_all();
+        drained.into_iter()
+            .filter_map(|(key, slot)| take_slot(slot).map(|item| (key, item)))
+            .collect()
+    }
+
+    /// Removes and returns the stored object with a matching key, like `remove`, with the vault's master key. This removes items added with `add_locked` too, without their passphrase. Returns `VaultError::WrongMasterKey`, without removing anything, if the master key isn't this vault's.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let (vault, master) = Vault::<i32>::with_master_key();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Some(1), vault.master_remove(&master, &key)?);
+    /// assert_eq!(false, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn master_remove(&self, master: &MasterKey, key: &VaultKey) -> Result<Option<T>, VaultError> {
+        self.check_master(master)?;
+        let slot = {
+            let mut unlocked = self.write_shard(key);
+            self.forget(key);
+            unlocked.remove(key)
+        };
+        match slot {
+            Some(slot) => {
+                self.watches.changed(key);
+                Ok(take_slot(slot))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Applies the operation to a reference to the stored object with a matching key and returns its result, like `with_item`, with the vault's master key. Returns `VaultError::WrongMasterKey`, without calling the operation, if the master key isn't this vault's.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let (vault, master) = Vault::<String>::with_master_key();
+    /// let key = vault.add(String::from("stuck"));
+    /// 
+    /// assert_eq!(Some(5), vault.master_inspect(&master, &key, |s| s.len())?);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn master_inspect<R, F>(&self, master: &MasterKey, key: &VaultKey, operation: F) -> Result<Option<R>, VaultError>
+            where F: FnOnce(&T) -> R {
+        self.check_master(master)?;
+        Ok(self.with_item_in_generation(key, None, "Vault::master_inspect", operation))
+    }
+
+    /// Removes every item from the vault and returns them along with their keys, like `drain`, with the vault's master key. Returns `VaultError::WrongMasterKey`, leaving the vault as it was, if the master key isn't this vault's.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let (vault, master) = Vault::<i32>::with_master_key();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(vec![(key, 1)], vault.master_drain(&master)?);
+    /// assert_eq!(true, vault.is_empty());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn master_drain(&self, master: &MasterKey) -> Result<Vec<(VaultKey, T)>, VaultError> {
+        self.check_master(master)?;
+        Ok(self.drain())
+    }
+
+    // Only takes the master key made along with this vault; a vault made
+    // without one takes none.
+    fn check_master(&self, master: &MasterKey) -> Result<(), VaultError> {
+        if self.master == Some(master.nonce()) {
+            Ok(())
+        } else {
+            Err(VaultError::WrongMasterKey)
+        }
+    }
+
+    /// Adds an object to the vault that can only be removed with its passphrase as well as its key, and returns the key. `remove` panics with `AccessError::PassphraseRequired` for it, `try_remove` and `remove_timeout` return that error, and `remove_many` and `VaultGuard::remove` leave it where it is; `remove_locked` takes it out; the lock stays with the item through `rekey`, `swap` and `rotate_all_keys`. Other methods treat it like any other item, so it can still be looked at and updated with its key alone, and whole-vault operations like `clear` and `drain` take it out. The vault keeps a salted SHA-256 hash of the passphrase rather than the passphrase itself; it is a single fast hash, not a password hash, so a passphrase that is easy to guess gives little protection.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{AccessError, Vault, VaultError};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add_locked(1, b"open sesame");
+    /// 
+    /// assert_eq!(Err(VaultError::Access(AccessError::PassphraseRequired)), vault.try_remove(&key));
+    /// assert_eq!(Ok(Some(1)), vault.remove_locked(&key, b"open sesame"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "passphrases")]
+    pub fn add_locked(&self, to_add: T, passphrase: &[u8]) -> VaultKey {
+        let rule = Rule::passphrase(passphrase);
+        let load = self.load(&to_add);
+        let mut to_add = Some(to_add);
+        let key = keygen::fresh_key(&*self.keys, |key| {
+            let room = self.room(&key, load);
+            let mut unlocked = self.write_shard(&key);
+            match unlocked.entry(key) {
+                hash_map::Entry::Occupied(_) => None,
+                hash_map::Entry::Vacant(_) if !room.admit(&key, load) => panic!("{}", VaultError::Full),
+                hash_map::Entry::Vacant(entry) => {
+                    let slot = entry.insert(self.store(&key, to_add.take().expect("item is added once"), load));
+                    self.access.protect(key, slot, rule);
+                    Some(key)
+                }
+            }
+        });
+        self.watches.changed(&key);
+        key
+    }
+
+    /// Removes and returns the stored object with a matching key, if the passphrase is the one it was added with by `add_locked`. An item added without a passphrase is removed whatever the passphrase. Returns `AccessError::Denied` if there is no item with the key or the passphrase is wrong, without telling which, as a missing item has to look the same as a wrong passphrase. None is only returned for an item that another thread is already taking out, as with `remove`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{AccessError, Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add_locked(1, b"open sesame");
+    /// 
+    /// assert_eq!(Err(AccessError::Denied), vault.remove_locked(&key, b"guess"));
+    /// assert_eq!(Err(AccessError::Denied), vault.remove_locked(&VaultKey::new(), b"open sesame"));
+    /// assert_eq!(Ok(Some(1)), vault.remove_locked(&key, b"open sesame"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "passphrases")]
+    pub fn remove_locked(&self, key: &VaultKey, passphrase: &[u8]) -> Result<Option<T>, AccessError> {
+        let slot = {
+            let mut unlocked = self.write_shard(key);
+            if !self.admits(&unlocked, key, passphrase) {
+                return Err(AccessError::Denied);
+            }
+            self.forget(key);
+            unlocked.remove(key).ok_or(AccessError::Denied)?
+        };
+        self.watches.changed(key);
+        Ok(take_slot(slot))
+    }
+
+    /// Returns whether there exists an item in the vault with the provided key that `remove_locked` would remove with the passphrase. `has_item` reports an item added with `add_locked` whatever its passphrase.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add_locked(1, b"open sesame");
+    /// 
+    /// assert_eq!(true, vault.has_item(&key));
+    /// assert_eq!(false, vault.has_item_locked(&key, b"guess"));
+    /// assert_eq!(true, vault.has_item_locked(&key, b"open sesame"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "passphrases")]
+    pub fn has_item_locked(&self, key: &VaultKey, passphrase: &[u8]) -> bool {
+        let unlocked = self.read_shard(key);
+        self.admits(&unlocked, key, passphrase)
+    }
+
+    /// Adds an object to the vault under two new keys, and returns them. Either key finds the item with `has_item`, so that each holder can check it is there, but it can only be removed with both, by `remove_dual`; `remove` and the other ways of removing an item by key leave it where it is, as with `add_locked`. Other methods take the first key only, and treat the item like any other. `rekey` and the like move the first key and keep the pairing, while the second key stays as it is and can't be rekeyed.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let (first, second) = vault.add_dual(1);
+    /// 
+    /// assert_eq!(true, vault.has_item(&second));
+    /// assert_eq!(None, vault.remove(&first));
+    /// assert_eq!(None, vault.remove(&second));
+    /// assert_eq!(Some(1), vault.remove_dual(&second, &first));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_dual(&self, to_add: T) -> (VaultKey, VaultKey) {
+        let mut evicted = Evicted::new(self.limit.as_ref(), &self.watches);
+        let (first, second) = {
+            let load = self.load(&to_add);
+            let mut unlocked = self.write_all();
+            self.make_room(&mut unlocked, load, &mut evicted);
+            if !limit::fits(self.limit.as_ref(), unlocked.len(), load) {
+                panic!("{}", VaultError::Full);
+            }
+            let free = |taken: Option<VaultKey>| keygen::fresh_key(&*self.keys, |key| {
+                Some(key).filter(|key| Some(*key) != taken && !unlocked.contains_key(key) && !self.access.is_stand_in(key))
+            });
+            let first = free(None);
+            let second = free(Some(first));
+            let slot = self.store(&first, to_add, load);
+            self.access.protect(first, &slot, Rule::Dual {second});
+            unlocked.insert(first, slot);
+            self.inserted(&first);
+            (first, second)
+        };
+        self.watches.changed(&first);
+        (first, second)
+    }
+
+    /// Removes and returns the stored object added with `add_dual` under the two keys, given in either order. Returns None, without removing anything, if the keys aren't the two keys of one item.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let (first, second) = vault.add_dual(1);
+    /// let (_, other) = vault.add_dual(2);
+    /// 
+    /// assert_eq!(None, vault.remove_dual(&first, &other));
+    /// assert_eq!(Some(1), vault.remove_dual(&first, &second));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove_dual(&self, key_1: &VaultKey, key_2: &VaultKey) -> Option<T> {
+        self.remove_pair(key_1, key_2).or_else(|| self.remove_pair(key_2, key_1))
+    }
+
+    // Removes the item under the first key if the second is its other key.
+    fn remove_pair(&self, first: &VaultKey, second: &VaultKey) -> Option<T> {
+        let slot = {
+            let mut unlocked = self.write_shard(first);
+            match self.access.rule(first, unlocked.get(first)?) {
+                Some(Rule::Dual {second: paired}) if paired == *second => {}
+                _ => return None,
+            }
+            self.forget(first);
+            unlocked.remove(first)?
+        };
+        self.watches.changed(first);
+        take_slot(slot)
+    }
+
+    /// Adds an object to the vault and returns two keys for it: an `OwnerKey`, which has every right to the item and derefs to its `VaultKey`, and a `ReadKey`, which can only look at it. The read key works with `has_item_read`, `get_clone_read` and `with_item_read`, and can't be passed to the methods that change or remove items; in its untyped form it works with `has_item`, `get_clone` and `with_item`, and is refused by the rest. `rekey` and the like move the owner key and keep the read key working.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let (owner, reader) = vault.add_with_roles(1);
+    /// 
+    /// assert_eq!(Some(1), vault.get_clone_read(&reader));
+    /// assert_eq!(false, vault.update_item(&reader.untyped(), |i| *i += 1));
+    /// assert_eq!(true, vault.update_item(&owner, |i| *i += 1));
+    /// assert_eq!(Some(2), vault.remove(&owner));
+    /// assert_eq!(false, vault.has_item_read(&reader));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_with_roles(&self, to_add: T) -> (OwnerKey, ReadKey) {
+        let mut evicted = Evicted::new(self.limit.as_ref(), &self.watches);
+        let (owner, reader) = {
+            let load = self.load(&to_add);
+            let mut unlocked = self.write_all();
+            self.make_room(&mut unlocked, load, &mut evicted);
+            if !limit::fits(self.limit.as_ref(), unlocked.len(), load) {
+                panic!("{}", VaultError::Full);
+            }
+            let free = |taken: Option<VaultKey>| keygen::fresh_key(&*self.keys, |key| {
+                Some(key).filter(|key| Some(*key) != taken && !unlocked.contains_key(key) && !self.access.is_stand_in(key))
+            });
+            let owner = free(None);
+            let reader = free(Some(owner));
+            let slot = self.store(&owner, to_add, load);
+            self.access.protect(owner, &slot, Rule::Roles {reader});
+            unlocked.insert(owner, slot);
+            self.inserted(&owner);
+            (owner, reader)
+        };
+        self.watches.changed(&owner);
+        (OwnerKey::new(owner), ReadKey::new(reader))
+    }
+
+    /// Returns true if the item the read key is for is still in the vault, like `has_item`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let (_, reader) = vault.add_with_roles(1);
+    /// 
+    /// assert_eq!(true, vault.has_item_read(&reader));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn has_item_read(&self, key: &ReadKey) -> bool {
+        self.has_item(&key.untyped())
+    }
+
+    /// Returns a clone of the item the read key is for, like `get_clone`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let (_, reader) = vault.add_with_roles(1);
+    /// 
+    /// assert_eq!(Some(1), vault.get_clone_read(&reader));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn get_clone_read(&self, key: &ReadKey) -> Option<T>
+            where T: Clone {
+        self.get_clone(&key.untyped())
+    }
+
+    /// Applies the operation to a reference to the item the read key is for and returns its result, like `with_item`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<String>::new();
+    /// let (_, reader) = vault.add_with_roles(String::from("report"));
+    /// 
+    /// assert_eq!(Some(6), vault.with_item_read(&reader, |s| s.len()));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn with_item_read<R, F>(&self, key: &ReadKey, operation: F) -> Option<R>
+            where F: FnOnce(&T) -> R {
+        self.with_item(&key.untyped(), operation)
+    }
+
+    /// Makes a new key for the item with the provided key, and returns it, or returns None if there is no item with the key. The new key is an alias: reading, updating and `remove` work with it as with the item's own key, and `has_item` finds the item with it. Removing the item with any of its keys leaves every alias of it dangling, and `remove_alias` drops one alias without touching the item or its other keys. Aliases keep working when the item is rekeyed, but aren't rekeyed themselves; methods not mentioned here take the item's own key only.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let alias = vault.add_alias(&key).unwrap();
+    /// 
+    /// assert_eq!(true, vault.update_item(&alias, |i| *i += 1));
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// assert_eq!(Some(2), vault.remove(&alias));
+    /// assert_eq!(false, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_alias(&self, existing: &VaultKey) -> Option<VaultKey> {
+        let unlocked = self.write_all();
+        let (first, slot) = match unlocked.get(existing) {
+            Some(slot) => (*existing, slot),
+            None => {
+                let (first, aliased) = self.access.alias_of(existing)?;
+                (first, unlocked.get(&first).filter(|slot| Arc::as_ptr(slot) == aliased)?)
+            }
+        };
+        let alias = keygen::fresh_key(&*self.keys, |key| Some(key).filter(|key| !unlocked.contains_key(key) && !self.access.is_stand_in(key)));
+        self.access.alias(alias, first, slot);
+        Some(alias)
+    }
+
+    /// Drops an alias made with `add_alias`, leaving the item and its other keys as they are. Returns true if the key was an alias of an item still in the vault, otherwise returns false; dangling aliases are dropped too.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let alias = vault.add_alias(&key).unwrap();
+    /// 
+    /// assert_eq!(true, vault.remove_alias(&alias));
+    /// assert_eq!(false, vault.has_item(&alias));
+    /// assert_eq!(true, vault.has_item(&key));
+    /// assert_eq!(false, vault.remove_alias(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove_alias(&self, alias: &VaultKey) -> bool {
+        let (first, aliased) = match self.access.alias_of(alias) {
+            Some(found) => found,
+            None => return false,
+        };
+        let unlocked = self.write_shard(&first);
+        self.access.unlink(alias);
+        unlocked.get(&first).is_some_and(|slot| Arc::as_ptr(slot) == aliased)
+    }
+
+    /// Adds an object to the vault under a new key, gives it the name, and returns the key. `key_for` finds the key from the name, and `remove_named` removes the item by it, for as long as the item is in the vault: removing it in any way, or clearing the vault, drops its name, and rekeying it keeps the name. Names are unique within a vault; a name already in use is taken from the item that had it, which stays in the vault under its key without a name.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add_named("retries", 3);
+    /// 
+    /// assert_eq!(Some(key), vault.key_for("retries"));
+    /// assert_eq!(Some(3), vault.remove_named("retries"));
+    /// assert_eq!(None, vault.key_for("retries"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_named(&self, name: &str, to_add: T) -> VaultKey {
+        let load = self.load(&to_add);
+        let mut to_add = Some(to_add);
+        let key = keygen::fresh_key(&*self.keys, |key| {
+            let room = self.room(&key, load);
+            let mut unlocked = self.write_shard(&key);
+            match unlocked.entry(key) {
+                hash_map::Entry::Occupied(_) => None,
+                hash_map::Entry::Vacant(_) if self.access.is_stand_in(&key) => None,
+                hash_map::Entry::Vacant(_) if !room.admit(&key, load) => panic!("{}", VaultError::Full),
+                hash_map::Entry::Vacant(entry) => {
+                    let slot = entry.insert(self.store(&key, to_add.take().expect("item is added once"), load));
+                    self.names.name(name, key, slot);
+                    Some(key)
+                }
+            }
+        });
+        self.watches.changed(&key);
+        key
+    }
+
+    /// Returns the key of the item with the name given by `add_named`, or None if no item in the vault has the name.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add_named("retries", 3);
+    /// 
+    /// assert_eq!(Some(key), vault.key_for("retries"));
+    /// vault.remove(&key);
+    /// assert_eq!(None, vault.key_for("retries"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn key_for(&self, name: &str) -> Option<VaultKey> {
+        let (key, named) = self.names.lookup(name)?;
+        let unlocked = self.read_shard(&key);
+        unlocked.get(&key).filter(|slot| Arc::as_ptr(slot) == named).map(|_| key)
+    }
+
+    /// Removes and returns the item with the name given by `add_named`, like `remove` with its key. Returns None if no item in the vault has the name.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add_named("retries", 3);
+    /// 
+    /// assert_eq!(Some(3), vault.remove_named("retries"));
+    /// assert_eq!(false, vault.has_item(&key));
+    /// assert_eq!(None, vault.remove_named("retries"));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove_named(&self, name: &str) -> Option<T> {
+        let (key, named) = self.names.lookup(name)?;
+        let slot = {
+            let mut unlocked = self.write_shard(&key);
+            let slot = unlocked.get(&key).filter(|slot| Arc::as_ptr(slot) == named)?;
+            if self.access.refusal(&key, slot).is_some() {
+                return None;
+            }
+            self.forget(&key);
+            unlocked.remove(&key)?
+        };
+        self.watches.changed(&key);
+        take_slot(slot)
+    }
+
+    /// Reserves a new key for an item that doesn't exist yet, so that the key can be handed out first, to be waited on with `take_blocking` or `watch`. Until the returned reservation is fulfilled, `add_with_key`, `get_or_insert_with` and `replace` refuse the key, and `has_item` reports no item under it. Dropping the reservation lets go of the key, as does `Reservation::cancel`; clearing the vault doesn't.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let reservation = vault.reserve_key();
+    /// let key = reservation.key();
+    /// 
+    /// assert_eq!(false, vault.add_with_key(2, &key));
+    /// assert_eq!(false, vault.has_item(&key));
+    /// reservation.fulfill(1).unwrap();
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn reserve_key(&self) -> Reservation<'_, T> {
+        let key = keygen::fresh_key(&*self.keys, |key| {
+            let unlocked = self.write_shard(&key);
+            if unlocked.contains_key(&key) || self.access.is_stand_in(&key) {
+                return None;
+            }
+            self.access.reserve(key);
+            Some(key)
+        });
+        Reservation::new(self, key)
+    }
+
+    // Adds the item under the reserved key and lets go of the reservation,
+    // or hands the item back if the key was taken anyway.
+    pub(crate) fn fulfill_reservation(&self, key: &VaultKey, to_add: T) -> Result<(), T> {
+        let load = self.load(&to_add);
+        {
+            let room = self.room(key, load);
+            let mut unlocked = self.write_shard(key);
+            self.access.unreserve(key);
+            match unlocked.entry(*key) {
+                hash_map::Entry::Occupied(_) => return Err(to_add),
+                hash_map::Entry::Vacant(_) if !room.admit(key, load) => return Err(to_add),
+                hash_map::Entry::Vacant(entry) => {
+                    entry.insert(self.store(key, to_add, load));
+                }
+            }
+        }
+        self.watches.changed(key);
+        Ok(())
+    }
+
+    pub(crate) fn cancel_reservation(&self, key: &VaultKey) {
+        let _unlocked = self.write_shard(key);
+        self.access.unreserve(key);
+    }
+
+    // The rule and slot of the item under the first key, if the other key
+    // stands in for it.
+    fn find_stand_in(&self, first: &VaultKey, stand_in: &VaultKey) -> Option<(Rule, Slot<T>)> {
+        self.stand_in_slot(&self.read_shard(first), first, stand_in)
+    }
+
+    // As for find_stand_in, with the first key's shard locked.
+    fn stand_in_slot(&self, items: &Items<T>, first: &VaultKey, stand_in: &VaultKey) -> Option<(Rule, Slot<T>)> {
+        let slot = items.get(first)?;
+        match self.access.rule(first, slot)? {
+            rule @ Rule::Dual {second: key} | rule @ Rule::Roles {reader: key} if key == *stand_in => Some((rule, Arc::clone(slot))),
+            _ => None,
+        }
+    }
+
+    // Whether the key has an item the passphrase opens, with the key's shard
+    // locked. A key with no item is checked against a made up hash, so that
+    // it takes as long to refuse as a wrong passphrase does.
+    #[cfg(feature = "passphrases")]
+    fn admits(&self, items: &Items<T>, key: &VaultKey, passphrase: &[u8]) -> bool {
+        match items.get(key) {
+            Some(slot) => self.access.rule(key, slot).is_none_or(|rule| rule.admits(passphrase)),
+            None => {
+                Rule::refuse(passphrase);
+                false
+            }
+        }
+    }
+
+    /// Keeps only the items for which the predicate returns true, removing the rest. The vault stays locked for the whole sweep.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let even = vault.add(2);
+    /// let odd = vault.add(3);
+    /// 
+    /// vault.retain(|_, i| i % 2 == 0);
+    /// assert_eq!(true, vault.has_item(&even));
+    /// assert_eq!(false, vault.has_item(&odd));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn retain<F>(&self, mut predicate: F)
+            where F: FnMut(&VaultKey, &T) -> bool {
+        let mut unlocked = self.write_all();
+        let _held = self.hold(Scope::Vault, "Vault::retain");
+        unlocked.retain(|key, slot| {
+            let keep = read_slot(slot).as_ref().is_some_and(|item| predicate(key, item));
+            if !keep {
+                self.forget_use(key);
+            }
+            keep
+        });
+        drop(unlocked);
+        self.watches.changed_all();
+    }
+
+    /// Applies the operation to every item in place and keeps only the items for which it returns true, removing the rest, in a single pass. Returns the number of items removed.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<u32>::new();
+    /// let fresh = vault.add(3);
+    /// let stale = vault.add(1);
+    /// 
+    /// let removed = vault.retain_mut(|_, ttl| {
+    ///     *ttl -= 1;
+    ///     *ttl > 0
+    /// });
+    /// assert_eq!(1, removed);
+    /// assert_eq!(Some(2), vault.get_clone(&fresh));
+    /// assert_eq!(false, vault.has_item(&stale));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn retain_mut<F>(&self, mut operation: F) -> usize
+            where F: FnMut(&VaultKey, &mut T) -> bool {
+        let mut unlocked = self.write_all();
+        let _held = self.hold(Scope::Vault, "Vault::retain_mut");
+        let before = unlocked.len();
+        unlocked.retain(|key, slot| {
+            let mut item = write_slot(slot);
+            let keep = item.as_mut().is_some_and(|item| operation(key, item));
+            if keep {
+                self.reweigh(key, slot, &item);
+            } else {
+                self.forget_use(key);
+            }
+            keep
+        });
+        let removed = before - unlocked.len();
+        drop(unlocked);
+        self.watches.changed_all();
+        removed
+    }
+
+    /// Removes every item for which the predicate returns true and returns them along with their keys, in no particular order. Items that don't match are left untouched.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let big = vault.add(100);
+    /// let small = vault.add(1);
+    /// 
+    /// let removed = vault.remove_where(|_, i| *i > 10);
+    /// assert_eq!(vec![(big, 100)], removed);
+    /// assert_eq!(true, vault.has_item(&small));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove_where<F>(&self, mut predicate: F) -> Vec<(VaultKey, T)>
+            where F: FnMut(&VaultKey, &T) -> bool {
+        let removed: Vec<(VaultKey, Slot<T>)> = {
+            let mut unlocked = self.write_all();
+            let _held = self.hold(Scope::Vault, "Vault::remove_where");
+            let matching: Vec<VaultKey> = unlocked.iter()
+                .filter(|(key, slot)| read_slot(slot).as_ref().is_some_and(|item| predicate(key, item)))
+                .map(|(key, _)| *key)
+                .collect();
+            matching.into_iter()
+                .filter_map(|key| unlocked.remove(&key).map(|slot| (key, slot)))
+                .inspect(|(key, _)| self.forget_use(key))
+                .collect()
+        };
+        for (key, _) in &removed {
+            self.watches.changed(key);
+        }
+        removed.into_iter()
+            .filter_map(|(key, slot)| take_slot(slot).map(|item| (key, item)))
+            .collect()
+    }
+
+    /// Removes every item for which the predicate returns true and returns a new vault holding them under the same keys. Items are removed in a single step, so no item is ever observed in both vaults.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let big = vault.add(100);
+    /// let small = vault.add(1);
+    /// 
+    /// let big_items = vault.split_off(|_, i| *i > 10);
+    /// assert_eq!(Some(100), big_items.get_clone(&big));
+    /// assert_eq!(false, vault.has_item(&big));
+    /// assert_eq!(true, vault.has_item(&small));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn split_off<F>(&self, predicate: F) -> Vault<T>
+            where F: FnMut(&VaultKey, &T) -> bool {
+        let removed = self.remove_where(predicate);
+        Vault::from_map(removed.into_iter().collect())
+    }
+
+    /// Removes and returns an arbitrary item along with its key, if the vault is not empty, otherwise returns None. No two calls will return the same item.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Some((key, 1)), vault.pop_any());
+    /// assert_eq!(None, vault.pop_any());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn pop_any(&self) -> Option<(VaultKey, T)> {
+        let mut unlocked = self.write_all();
+        loop {
+            let key = *unlocked.keys().next()?;
+            let slot = unlocked.remove(&key)?;
+            self.forget_use(&key);
+            self.watches.changed(&key);
+            if let Some(item) = take_slot(slot) {
+                return Some((key, item));
+            }
+        }
+    }
+
+    /// Returns a snapshot of the keys of every item in the vault, in no particular order. The vault is not locked after this returns, so the snapshot may be out of date by the time it is used.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// vault.add(2);
+    /// 
+    /// let mut total = 0;
+    /// for key in vault.keys() {
+    ///     total += vault.get_clone(&key).unwrap();
+    /// }
+    /// assert_eq!(3, total);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn keys(&self) -> Vec<VaultKey> {
+        self.read_all().keys().copied().collect()
+    }
+
+    /// Returns clones of every item in the vault, in no particular order. The clones are all taken at once.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(10);
+    /// vault.add(5);
+    /// 
+    /// let pending: i32 = vault.values().iter().sum();
+    /// assert_eq!(15, pending);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn values(&self) -> Vec<T>
+            where T: Clone {
+        self.read_all().values().filter_map(|slot| read_slot(slot).clone()).collect()
+    }
+
+    /// Removes every item from the vault and returns them without their keys, in no particular order.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// 
+    /// assert_eq!(vec![1], vault.take_values());
+    /// assert_eq!(true, vault.is_empty());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn take_values(&self) -> Vec<T> {
+        self.drain().into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// Returns an iterator over clones of every item in the vault along with their keys, in no particular order. The clones are all taken at once when this is called, so the iterator is a snapshot: changes made to the vault afterward are not reflected, and the vault is not locked while iterating.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// for (k, item) in vault.iter() {
+    ///     assert_eq!(key, k);
+    ///     assert_eq!(1, item);
+    /// }
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (VaultKey, T)>
+            where T: Clone {
+        let snapshot: Vec<(VaultKey, T)> = self.read_all().iter()
+            .filter_map(|(key, slot)| read_slot(slot).clone().map(|item| (*key, item)))
+            .collect();
+        snapshot.into_iter()
+    }
+
+    /// Calls the operation with a reference to every item in the vault and its key, in no particular order. The vault stays locked for the whole pass, so the operation must not call any method on this vault, or it will deadlock or panic.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<String>::new();
+    /// vault.add(String::from("ab"));
+    /// vault.add(String::from("cde"));
+    /// 
+    /// let mut total_length = 0;
+    /// vault.for_each(|_, s| total_length += s.len());
+    /// assert_eq!(5, total_length);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn for_each<F>(&self, mut operation: F)
+            where F: FnMut(&VaultKey, &T) {
+        let unlocked = self.read_all();
+        let _held = self.hold(Scope::Vault, "Vault::for_each");
+        for (key, slot) in unlocked.iter() {
+            if let Some(item) = read_slot(slot).as_ref() {
+                operation(key, item);
+            }
+        }
+    }
+
+    /// Returns a new vault holding the result of applying the operation to every item in this vault, under the same keys. This vault is left unchanged.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let records = Vault::<String>::new();
+    /// let key = records.add(String::from("a long record"));
+    /// 
+    /// let lengths = records.map_values(|_, record| record.len());
+    /// assert_eq!(Some(13), lengths.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn map_values<U, F>(&self, mut operation: F) -> Vault<U>
+            where F: FnMut(&VaultKey, &T) -> U {
+        let mapped = {
+            let unlocked = self.read_all();
+            let _held = self.hold(Scope::Vault, "Vault::map_values");
+            unlocked.iter()
+                .filter_map(|(key, slot)| read_slot(slot).as_ref().map(|item| (*key, operation(key, item))))
+                .collect()
+        };
+        Vault::from_map(mapped)
+    }
+
+    /// Returns a new vault holding clones of the items for which the predicate returns true, under the same keys. This vault is left unchanged.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let big = vault.add(100);
+    /// vault.add(1);
+    /// 
+    /// let big_items = vault.filter(|_, i| *i > 10);
+    /// assert_eq!(Some(100), big_items.get_clone(&big));
+    /// assert_eq!(2, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn filter<F>(&self, mut predicate: F) -> Vault<T>
+            where F: FnMut(&VaultKey, &T) -> bool, T: Clone {
+        let filtered: HashMap<VaultKey, T> = {
+            let unlocked = self.read_all();
+            let _held = self.hold(Scope::Vault, "Vault::filter");
+            unlocked.iter()
+                .filter_map(|(key, slot)| {
+                    read_slot(slot).as_ref()
+                        .filter(|item| predicate(key, item))
+                        .map(|item| (*key, item.clone()))
+                })
+                .collect()
+        };
+        Vault::from_map(filtered)
+    }
+
+    /// Returns true if any item in the vault is equal to the provided value, otherwise returns false. This compares against every item, so it takes time proportional to the size of the vault.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// 
+    /// assert_eq!(true, vault.contains_value(&1));
+    /// assert_eq!(false, vault.contains_value(&2));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn contains_value(&self, value: &T) -> bool
+            where T: PartialEq {
+        self.read_all().values().any(|slot| read_slot(slot).as_ref() == Some(value))
+    }
+
+    /// Returns the key of an item for which the predicate returns true, if there is one, otherwise returns None. If several items match, any one of their keys may be returned.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<&str>::new();
+    /// let key = vault.add("lost");
+    /// vault.add("found");
+    /// 
+    /// let recovered = vault.find_key(|item| *item == "lost");
+    /// assert_eq!(Some(key), recovered);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn find_key<F>(&self, mut predicate: F) -> Option<VaultKey>
+            where F: FnMut(&T) -> bool {
+        let unlocked = self.read_all();
+        let _held = self.hold(Scope::Vault, "Vault::find_key");
+        let found = unlocked.iter()
+            .find(|(_, slot)| read_slot(slot).as_ref().is_some_and(&mut predicate))
+            .map(|(key, _)| *key);
+        found
+    }
+
+    /// Returns the keys of every item for which the predicate returns true, in no particular order.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<(u32, &str)>::new();
+    /// let key = vault.add((42, "settings"));
+    /// vault.add((7, "settings"));
+    /// 
+    /// let keys = vault.find_keys(|(user, _)| *user == 42);
+    /// assert_eq!(vec![key], keys);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn find_keys<F>(&self, mut predicate: F) -> Vec<VaultKey>
+            where F: FnMut(&T) -> bool {
+        let unlocked = self.read_all();
+        let _held = self.hold(Scope::Vault, "Vault::find_keys");
+        let found = unlocked.iter()
+            .filter(|(_, slot)| read_slot(slot).as_ref().is_some_and(&mut predicate))
+            .map(|(key, _)| *key)
+            .collect();
+        found
+    }
+
+    /// Returns the number of items for which the predicate returns true. Every item is checked and nothing is allocated.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add(1);
+    /// vault.add(2);
+    /// vault.add(4);
+    /// 
+    /// let even = vault.count_where(|i| i % 2 == 0);
+    /// assert_eq!(2, even);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn count_where<F>(&self, mut predicate: F) -> usize
+            where F: FnMut(&T) -> bool {
+        let unlocked = self.read_all();
+        let _held = self.hold(Scope::Vault, "Vault::count_where");
+        let counted = unlocked.values()
+            .filter(|slot| read_slot(slot).as_ref().is_some_and(&mut predicate))
+            .count();
+        counted
+    }
+}
+
+impl<T> Vault<T>
+        where T: Copy + Add<Output = T> {
+    /// Adds the delta to the number stored with the specified key, and returns the new value. Returns None if an item with the key is not found.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(10);
+    /// 
+    /// assert_eq!(Some(15), vault.add_assign(&key, 5));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_assign(&self, key: &VaultKey, delta: T) -> Option<T> {
+        self.with_item_mut(key, |i| {
+            *i = *i + delta;
+            *i
+        })
+    }
+
+    /// Adds one to the number stored with the specified key, and returns the new value. Returns None if an item with the key is not found.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<u64>::new();
+    /// let key = vault.add(0);
+    /// 
+    /// vault.increment(&key);
+    /// assert_eq!(Some(2), vault.increment(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn increment(&self, key: &VaultKey) -> Option<T>
+            where T: From<u8> {
+        self.add_assign(key, T::from(1))
+    }
+}
+
+impl<T> Vault<T>
+        where T: Copy + Sub<Output = T> {
+    /// Subtracts the delta from the number stored with the specified key, and returns the new value. Returns None if an item with the key is not found.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<f64>::new();
+    /// let key = vault.add(10.0);
+    /// 
+    /// assert_eq!(Some(7.5), vault.sub_assign(&key, 2.5));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn sub_assign(&self, key: &VaultKey, delta: T) -> Option<T> {
+        self.with_item_mut(key, |i| {
+            *i = *i - delta;
+            *i
+        })
+    }
+
+    /// Subtracts one from the number stored with the specified key, and returns the new value. Returns None if an item with the key is not found.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(3);
+    /// 
+    /// assert_eq!(Some(2), vault.decrement(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn decrement(&self, key: &VaultKey) -> Option<T>
+            where T: From<u8> {
+        self.sub_assign(key, T::from(1))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<T> Vault<T>
+        where T: Send + Sync {
+    /// Calls the operation with a reference to every item in the vault and its key, in parallel on the rayon thread pool and in no particular order. The keys are gathered first and the vault is not locked while the operation runs, so other threads can use it meanwhile and the operation may call methods on this vault. Items removed before the pass gets to them are skipped, and items added after it starts aren't visited. Items are shared across threads, so `T` must be `Send` and `Sync`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::atomic::{AtomicUsize, Ordering};
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<String>::new();
+    /// vault.add(String::from("ab"));
+    /// vault.add(String::from("cde"));
+    /// 
+    /// let total_length = AtomicUsize::new(0);
+    /// vault.par_for_each(|_, s| { total_length.fetch_add(s.len(), Ordering::Relaxed); });
+    /// assert_eq!(5, total_length.into_inner());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn par_for_each<F>(&self, operation: F)
+            where F: Fn(&VaultKey, &T) + Sync {
+        self.slots().par_iter().for_each(|(key, slot)| {
+            if let Some(item) = read_slot(slot).as_ref() {
+                operation(key, item);
+            }
+        });
+    }
+
+    /// Applies the operation to every item in the vault in parallel, like `par_for_each`, and returns the results in no particular order.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<String>::new();
+    /// vault.add(String::from("ab"));
+    /// vault.add(String::from("cde"));
+    /// 
+    /// let mut lengths = vault.par_values_map(|s| s.len());
+    /// lengths.sort();
+    /// assert_eq!(vec![2, 3], lengths);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn par_values_map<R, F>(&self, operation: F) -> Vec<R>
+            where F: Fn(&T) -> R + Sync, R: Send {
+        self.slots().par_iter()
+            .filter_map(|(_, slot)| read_slot(slot).as_ref().map(&operation))
+            .collect()
+    }
+
+    /// Applies the operation to every item in the vault in place, in parallel on the rayon thread pool, and returns the number of items updated. As with `par_for_each`, the vault is not locked while the operation runs, only each item while it is updated, and items added after the pass starts aren't visited.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// vault.add(2);
+    /// 
+    /// assert_eq!(2, vault.par_update_all(|_, i| *i *= 10));
+    /// assert_eq!(Some(10), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn par_update_all<F>(&self, operation: F) -> usize
+            where F: Fn(&VaultKey, &mut T) + Sync {
+        let updated = self.slots().par_iter()
+            .filter(|(key, slot)| {
+                let mut item = write_slot(slot);
+                let updated = match item.as_mut() {
+                    Some(item) => {
+                        operation(key, item);
+                        true
+                    }
+                    None => false,
+                };
+                self.reweigh(key, slot, &item);
+                updated
+            })
+            .count();
+        self.watches.changed_all();
+        updated
+    }
+
+    /// Keeps only the items for which the predicate returns true, like `retain`, but calls the predicate in parallel on the rayon thread pool. The vault is not locked while the predicate runs; the items it rejects are then removed all at once. Items added after the pass starts are kept, and an item replaced under its key in the meantime is kept as well, since the predicate never saw it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// vault.add_many(1..=6);
+    /// 
+    /// vault.par_retain(|_, i| i % 2 == 0);
+    /// assert_eq!(3, vault.len());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn par_retain<F>(&self, predicate: F)
+            where F: Fn(&VaultKey, &T) -> bool + Sync {
+        let rejected: Vec<(VaultKey, Slot<T>)> = self.slots().into_par_iter()
+            .filter(|(key, slot)| read_slot(slot).as_ref().is_some_and(|item| !predicate(key, item)))
+            .collect();
+        let mut unlocked = self.write_all();
+        for (key, slot) in &rejected {
+            if unlocked.get(key).is_some_and(|found| Arc::ptr_eq(found, slot)) {
+                unlocked.remove(key);
+                self.forget_use(key);
+            }
+        }
+        drop(unlocked);
+        self.watches.changed_all();
+    }
+}
+
+impl<T> Vault<T> {
+    fn from_map(map: HashMap<VaultKey, T>) -> Vault<T> {
+        let vault = Vault::new();
+        vault.extend_entries(map);
+        vault
+    }
+
+    // Gathers the keys and their slots, so that their items can be got at
+    // after the vault is released.
+    #[cfg(feature = "rayon")]
+    fn slots(&self) -> Vec<(VaultKey, Slot<T>)> {
+        self.read_all().iter().map(|(key, slot)| (*key, Arc::clone(slot))).collect()
+    }
+
+    fn shard(&self, key: &VaultKey) -> &ItemsLock<T> {
+        &self.shards[shard::index(key, self.shards.len())]
+    }
+
+    fn shard_scope(&self, key: &VaultKey) -> Scope {
+        Scope::Shard(shard::index(key, self.shards.len()))
+    }
+
+    // Marks the thread as running a caller's operation, for the named method,
+    // under the given part of the vault until the hold is dropped. The
+    // blocking lock helpers below check for it before locking; see reentry.
+    fn hold(&self, scope: Scope, name: &'static str) -> reentry::Held {
+        reentry::enter(self as *const Vault<T> as usize, scope, name)
+    }
+
+    fn check_reentry(&self, key: Option<&VaultKey>) {
+        reentry::check(self as *const Vault<T> as usize, key.map(|key| (shard::index(key, self.shards.len()), key)));
+    }
+
+    fn guard<'a>(&'a self, items: WriteGuard<'a, Items<T>>) -> ItemsGuard<'a, T> {
+        let len_before = items.len();
+        ItemsGuard {items, count: &self.count, len_before, watches: &self.watches}
+    }
+
+    fn try_read_shard(&self, key: &VaultKey) -> Result<ItemsReadGuard<'_, T>, VaultError> {
+        self.shard(key).try_read().map_err(VaultError::from)
+    }
+
+    fn try_write_shard(&self, key: &VaultKey) -> Result<ItemsGuard<'_, T>, VaultError> {
+        self.shard(key).try_write()
+            .map(|items| self.guard(items))
+            .map_err(VaultError::from)
+    }
+
+    // Gives up, releasing any shards it has taken, at the first one that is
+    // in use or poisoned.
+    fn try_write_all(&self) -> Result<Shards<ItemsGuard<'_, T>>, VaultError> {
+        let guards = self.shards.iter()
+            .map(|shard| shard.try_write().map(|items| self.guard(items)).map_err(VaultError::from))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Shards::new(guards))
+    }
+
+    // Runs a transaction with every shard locked and makes its changes only if
+    // commit approves of its result. A panicking transaction is caught so the
+    // shards are released before carrying on with the panic; releasing them
+    // while unwinding would mark them poisoned though nothing was changed.
+    fn transact<R, F, C>(&self, name: &'static str, transaction: F, commit: C) -> R
+            where F: FnOnce(&mut VaultTxn<'_, T>) -> R, C: FnOnce(&R) -> bool {
+        let mut txn = VaultTxn {items: self.write_all(), watches: &self.watches, keys: &*self.keys, limit: self.limit.as_ref(), pending: HashMap::new()};
+        let _held = self.hold(Scope::Vault, name);
+        match panic::catch_unwind(AssertUnwindSafe(|| transaction(&mut txn))) {
+            Ok(result) => {
+                if commit(&result) {
+                    txn.commit();
+                }
+                result
+            }
+            Err(payload) => {
+                drop(txn);
+                panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    // Finds the key's slot and runs the operation on it with the slot locked
+    // but its shard not, so that a slow operation doesn't hold up other keys.
+    // A slot found empty has just had its item checked out by an entry;
+    // looking the key up again waits for the entry to be done.
+    fn with_slot<R, F>(&self, key: &VaultKey, generation: Option<u64>, operation: F) -> Option<R>
+            where F: FnOnce(&Slot<T>, &mut Option<T>) -> R {
+        loop {
+            let slot = self.find_slot(key, generation)?;
+            let mut item = write_slot(&slot);
+            if item.is_some() {
+                let result = operation(&slot, &mut item);
+                // An alias's item is booked under the key it was added with.
+                let owner = match &self.limit {
+                    Some(limit) if limit.max_weight().is_some() => self.access.alias_of(key).map_or(*key, |(first, _)| first),
+                    _ => *key,
+                };
+                self.reweigh(&owner, &slot, &item);
+                return Some(result);
+            }
+            drop(item);
+            thread::yield_now();
+        }
+    }
+
+    // Runs the operation on the key's item in place, if the generation
+    // stands, on behalf of the named public method.
+    pub(crate) fn modify<R, F>(&self, key: &VaultKey, generation: Option<u64>, name: &'static str, operation: F) -> Option<R>
+            where F: FnOnce(&mut T) -> R {
+        let result = self.with_slot(key, generation, |_, item| {
+            let _held = self.hold(Scope::Item(*key), name);
+            operation(item.as_mut().expect("locked slot has an item"))
+        })?;
+        self.watches.changed(key);
+        Some(result)
+    }
+
+    // Runs an update on the item of a locked slot, in place. A panic is
+    // caught and handed back, so that the caller can let go of the slot
+    // before carrying on with it; releasing the slot while unwinding would
+    // leave it poisoned.
+    fn update_locked<F>(item: &mut Option<T>, operation: F) -> thread::Result<()>
+            where F: FnOnce(&mut T) {
+        let item = item.as_mut().expect("locked slot has an item");
+        panic::catch_unwind(AssertUnwindSafe(|| operation(item)))
+    }
+
+    // Finds the key's slot like find_slot, or that of the item the key is the
+    // read key of, along with the key the slot is stored under.
+    fn find_readable_slot(&self, key: &VaultKey, generation: Option<u64>) -> Option<(VaultKey, Slot<T>)> {
+        if let Some(slot) = self.find_slot(key, generation) {
+            return Some((*key, slot));
+        }
+        let (owner, _) = self.access.stands_in_for(key)?;
+        match self.find_stand_in(&owner, key)? {
+            (Rule::Roles {..}, slot) => Some((owner, slot)),
+            _ => None,
+        }
+    }
+
+    // Replaces the item in the key's slot with what the operation makes of
+    // it, which is nothing if it returns None, with the key's shard locked.
+    // The slot stays the same, keeping the item's rules. A panic is caught
+    // and handed back like in update_locked; the item is gone then, as the
+    // operation was given it, and the key is left without one too.
+    fn replace_in<F>(&self, unlocked: &mut ItemsGuard<'_, T>, key: &VaultKey, slot: &Slot<T>, operation: F) -> thread::Result<()>
+            where F: FnOnce(T) -> Option<T> {
+        let mut item = write_slot(slot);
+        let taken = item.take().expect("locked slot has an item");
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| operation(taken))).map(|updated| *item = updated);
+        if item.is_some() {
+            self.reweigh(key, slot, &item);
+            self.used(key);
+        } else {
+            drop(item);
+            self.forget(key);
+            unlocked.remove(key);
+        }
+        outcome
+    }
+
+    // The key's slot, if it has one and the generation stands, or that of
+    // the item the key is an alias of.
+    fn find_slot(&self, key: &VaultKey, generation: Option<u64>) -> Option<Slot<T>> {
+        {
+            let unlocked = self.read_shard(key);
+            if !self.stands(generation) {
+                return None;
+            }
+            if let Some(slot) = unlocked.get(key) {
+                self.used(key);
+                return Some(Arc::clone(slot));
+            }
+        }
+        self.find_aliased(key)
+    }
+
+    // The key the key's item is stored under, which is the key itself, or the
+    // first key of the item it is an alias of, along with that item's slot.
+    // The slot found under the key is checked against it with resolved, with
+    // the key's shard locked.
+    fn resolve(&self, key: &VaultKey) -> (VaultKey, Option<*const Lock<Option<T>>>) {
+        match self.access.alias_of(key) {
+            Some((first, aliased)) => (first, Some(aliased)),
+            None => (*key, None),
+        }
+    }
+
+    fn find_aliased(&self, alias: &VaultKey) -> Option<Slot<T>> {
+        let (first, aliased) = self.access.alias_of(alias)?;
+        let unlocked = self.read_shard(&first);
+        let slot = unlocked.get(&first).filter(|slot| Arc::as_ptr(slot) == aliased).cloned()?;
+        self.used(&first);
+        Some(slot)
+    }
+
+    // Whether keys handed out in the generation are still good, which keys
+    // without a generation always are. This is only asked with a shard
+    // locked; every clear moves on to the next generation with all of them
+    // locked, so the answer stays right for as long as the shard is.
+    fn stands(&self, generation: Option<u64>) -> bool {
+        generation.is_none_or(|generation| generation == self.generation())
+    }
+
+    // Drops the rules and name of the key's item, as it is removed, with the
+    // key's shard locked.
+    fn forget(&self, key: &VaultKey) {
+        self.access.release(key);
+        self.names.release(key);
+        self.forget_use(key);
+    }
+
+    // Moves the rules and names of items that are moving from one key to
+    // another, with their shards locked.
+    fn moved(&self, moves: &[(VaultKey, VaultKey)]) {
+        self.access.rename(moves.iter().copied());
+        self.names.rename(moves);
+        if let Some(limit) = &self.limit {
+            limit.rename(moves);
+        }
+    }
+
+    // Called with every shard locked, by the methods that empty the vault,
+    // before they do.
+    fn next_generation(&self, unlocked: &Shards<ItemsGuard<'_, T>>) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.access.release_all();
+        self.names.release_all();
+        if let Some(limit) = &self.limit {
+            limit.release_all(unlocked.keys());
+        }
+    }
+
+    // Blocks until no thread is changing the key's shard. See write_shard
+    // for poisoning.
+    fn read_shard(&self, key: &VaultKey) -> ItemsReadGuard<'_, T> {
+        self.check_reentry(Some(key));
+        self.shard(key).read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Locks the vault's room for adding under the key, if the vault has a cap
+    // on its items; see limit. It is never waited on with a shard locked.
+    // If the vault evicts and is full, it then makes space for an item
+    // under the key, unless the key already has one.
+    fn room(&self, key: &VaultKey, load: usize) -> Room<'_, T> {
+        if self.limit.is_some() {
+            self.check_reentry(Some(key));
+            reentry::check_room(self as *const Vault<T> as usize);
+        }
+        let mut room = Room::lock(self.limit.as_ref(), &self.count, &self.watches);
+        let evicting = self.limit.as_ref().is_some_and(|limit| limit.evicts() && limit.could_fit(load));
+        if evicting && !room.fits(load) && !self.read_shard(key).contains_key(key) {
+            while !room.fits(load) && self.evict(&mut room.evicted) {}
+        }
+        room
+    }
+
+    // What the item counts for against the vault's cap, if it has one.
+    fn load(&self, item: &T) -> usize {
+        self.limit.as_ref().map_or(1, |limit| limit.load(item))
+    }
+
+    // What any item counts for at least, for making space before the item
+    // is made: one, or nothing if items are weighed.
+    fn least_load(&self) -> usize {
+        match &self.limit {
+            Some(limit) if limit.max_weight().is_some() => 0,
+            _ => 1,
+        }
+    }
+
+    fn store(&self, key: &VaultKey, item: T, load: usize) -> Slot<T> {
+        store(self.limit.as_ref(), key, item, load)
+    }
+
+    fn reweigh(&self, key: &VaultKey, slot: &Slot<T>, item: &Option<T>) {
+        reweigh(self.limit.as_ref(), key, slot, item);
+    }
+
+    // Tells the vault's cap of its items being swapped for those of another
+    // vault, which were under the given keys, with every shard locked.
+    fn relist(&self, unlocked: &Shards<ItemsGuard<'_, T>>, old_keys: &[VaultKey]) {
+        if let Some(limit) = &self.limit {
+            limit.release_all(old_keys.iter());
+            for (key, slot) in unlocked.iter() {
+                if let Some(item) = read_slot(slot).as_ref() {
+                    limit.book(key, slot, limit.load(item));
+                    limit.inserted(key);
+                }
+            }
+        }
+    }
+
+    // Tells the vault's eviction policy, if it has one, of the key's item
+    // being added or used. Called with the key's shard locked.
+    fn inserted(&self, key: &VaultKey) {
+        if let Some(limit) = &self.limit {
+            limit.inserted(key);
+        }
+    }
+
+    fn used(&self, key: &VaultKey) {
+        if let Some(limit) = &self.limit {
+            limit.used(key);
+        }
+    }
+
+    fn forget_use(&self, key: &VaultKey) {
+        if let Some(limit) = &self.limit {
+            limit.release(key);
+        }
+    }
+
+    // Takes out the item the policy chooses, or the first it chooses that
+    // can be evicted, locking one shard at a time, with the room locked and
+    // no shard. Returns whether it found one.
+    fn evict(&self, evicted: &mut Evicted<'_, T>) -> bool {
+        let mut victims = match &self.limit {
+            Some(limit) => Victims::new(limit),
+            None => return false,
+        };
+        while let Some(key) = victims.next() {
+            let mut unlocked = self.write_shard(&key);
+            match unlocked.get(&key) {
+                None => victims.gone(&key),
+                Some(slot) if self.access.refusal(&key, slot).is_some() => victims.kept(&key),
+                Some(_) => {
+                    let slot = unlocked.remove(&key).expect("the item was just found");
+                    self.forget(&key);
+                    evicted.push(key, slot);
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Takes out the items the policy chooses that can be evicted, with every
+    // shard locked, until there is space for adding more or none is left.
+    fn make_room(&self, unlocked: &mut Shards<ItemsGuard<'_, T>>, adding: usize, evicted: &mut Evicted<'_, T>) {
+        let limit = match &self.limit {
+            Some(limit) if limit.evicts() && limit.could_fit(adding) => limit,
+            _ => return,
+        };
+        let mut victims = Victims::new(limit);
+        while !limit.fits(unlocked.len(), adding) {
+            let key = match victims.next() {
+                Some(key) => key,
+                None => return,
+            };
+            match unlocked.get(&key) {
+                None => victims.gone(&key),
+                Some(slot) if self.access.refusal(&key, slot).is_some() => victims.kept(&key),
+                Some(_) => {
+                    let slot = unlocked.remove(&key).expect("the item was just found");
+                    self.forget(&key);
+                    evicted.push(key, slot);
+                }
+            }
+        }
+    }
+
+    // Blocks until the key's shard is free. A poisoned lock means another
+    // thread panicked part way through changing the shard; every operation
+    // leaves the map itself whole and ItemsGuard keeps the count in step
+    // with it, so carry on.
+    fn write_shard(&self, key: &VaultKey) -> ItemsGuard<'_, T> {
+        self.check_reentry(Some(key));
+        let items = self.shard(key).write().unwrap_or_else(PoisonError::into_inner);
+        self.guard(items)
+    }
+
+    // Locks every shard, always in shard order so that threads locking the
+    // whole vault can't deadlock against each other.
+    fn read_all(&self) -> Shards<ItemsReadGuard<'_, T>> {
+        self.check_reentry(None);
+        Shards::new(self.shards.iter()
+            .map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner))
+            .collect())
+    }
+
+    fn write_all(&self) -> Shards<ItemsGuard<'_, T>> {
+        self.check_reentry(None);
+        Shards::new(self.shards.iter()
+            .map(|shard| self.guard(shard.write().unwrap_or_else(PoisonError::into_inner)))
+            .collect())
+    }
+
+    // Locks two different vaults, always in address order so that threads
+    // locking the same pair from opposite sides can't deadlock.
+    fn lock_pair<'a>(&'a self, other: &'a Vault<T>) -> (Shards<ItemsGuard<'a, T>>, Shards<ItemsGuard<'a, T>>) {
+        debug_assert!(!std::ptr::eq(self, other));
+        if (self as *const Vault<T>) < (other as *const Vault<T>) {
+            let mine = self.write_all();
+            let theirs = other.write_all();
+            (mine, theirs)
+        } else {
+            let theirs = other.write_all();
+            let mine = self.write_all();
+            (mine, theirs)
+        }
+    }
+
+    // Locks the key's shard in two different vaults, in the same address
+    // order as lock_pair.
+    fn lock_shard_pair<'a>(&'a self, other: &'a Vault<T>, key: &VaultKey) -> (ItemsGuard<'a, T>, ItemsGuard<'a, T>) {
+        debug_assert!(!std::ptr::eq(self, other));
+        if (self as *const Vault<T>) < (other as *const Vault<T>) {
+            let mine = self.write_shard(key);
+            let theirs = other.write_shard(key);
+            (mine, theirs)
+        } else {
+            let theirs = other.write_shard(key);
+            let mine = self.write_shard(key);
+            (mine, theirs)
+        }
+    }
+}
+
+impl<T> Default for Vault<T> {
+    fn default() -> Vault<T> {
+        Vault::new()
+    }
+}
+
+impl<T> From<HashMap<VaultKey, T>> for Vault<T> {
+    /// Moves the map's items into a new vault, each under its key, as `Vault::from_entries` does.
+    fn from(map: HashMap<VaultKey, T>) -> Vault<T> {
+        Vault::from_map(map)
+    }
+}
+
+impl<T> FromIterator<T> for Vault<T> {
+    /// Collects the objects into a new vault under newly generated keys. Use `Vault::collect_with_keys` to also get the keys back.
+    fn from_iter<I>(iter: I) -> Vault<T>
+            where I: IntoIterator<Item = T> {
+        Vault::collect_with_keys(iter).0
+    }
+}
+
+impl<T> Extend<(VaultKey, T)> for Vault<T> {
+    /// Adds every item under its key, replacing any item already stored under that key. See `Vault::extend_entries`.
+    fn extend<I>(&mut self, iter: I)
+            where I: IntoIterator<Item = (VaultKey, T)> {
+        self.extend_entries(iter)
+    }
+}
+
+impl<T> Extend<T> for Vault<T> {
+    /// Adds every object under a newly generated key. Use `Vault::extend_values` to also get the keys back.
+    fn extend<I>(&mut self, iter: I)
+            where I: IntoIterator<Item = T> {
+        self.add_many(iter);
+    }
+}
+
+impl<T> IntoIterator for Vault<T> {
+    type Item = (VaultKey, T);
+    type IntoIter = IntoIter<T>;
+
+    /// Consumes the vault and yields each item along with its key, in no particular order. See `Vault::into_inner`.
+    fn into_iter(self) -> IntoIter<T> {
+        let remaining = self.len();
+        let shards: Vec<Items<T>> = self.shards.into_iter()
+            .map(|shard| shard.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect();
+        IntoIter {inner: shards.into_iter().flatten(), remaining}
+    }
+}
+
+/// An iterator over the items of a consumed vault, created by `Vault::into_iter`.
+pub struct IntoIter<T> {
+    inner: iter::Flatten<vec::IntoIter<Items<T>>>,
+    remaining: usize,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (VaultKey, T);
+
+    fn next(&mut self) -> Option<(VaultKey, T)> {
+        for (key, slot) in &mut self.inner {
+            self.remaining -= 1;
+            if let Some(item) = take_slot(slot) {
+                return Some((key, item));
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+/// The ways a non-panicking vault operation can fail.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VaultError {
+    /// A thread panicked while changing the vault.
+    Poisoned,
+    /// The vault is in use by another thread.
+    WouldBlock,
+    /// There is no item with the requested key.
+    KeyNotFound,
+    /// The vault was still in use by another thread when the timeout ran out.
+    Timeout,
+    /// The master key given isn't the vault's own.
+    WrongMasterKey,
+    /// The item can't be got at with its key alone.
+    Access(AccessError),
+    /// The vault holds as many items as it was made to hold, with `Vault::with_max_items`.
+    Full,
+    /// There is already an item with the requested key.
+    KeyInUse,
+}
+
+impl fmt::Display for VaultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VaultError::Poisoned => write!(f, "a thread panicked while changing the vault"),
+            VaultError::WouldBlock => write!(f, "the vault is in use by another thread"),
+            VaultError::KeyNotFound => write!(f, "no item with the requested key"),
+            VaultError::Timeout => write!(f, "timed out waiting for the vault"),
+            VaultError::WrongMasterKey => write!(f, "the master key is for another vault"),
+            VaultError::Access(error) => write!(f, "{}", error),
+            VaultError::Full => write!(f, "the vault is full"),
+            VaultError::KeyInUse => write!(f, "the key is already in use"),
+        }
+    }
+}
+
+impl Error for VaultError {}
+
+/// The error returned when a string or a slice of bytes can't be parsed as a `VaultKey`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct VaultKeyParseError {
+    invalid: Invalid,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum Invalid {
+    // A string of the wrong length, or one of the right length that isn't a
+    // UUID.
+    Length(usize),
+    Format,
+    // A slice of bytes of the wrong length.
+    ByteLength(usize),
+    // A token of the wrong length, or with a character, at the given index,
+    // that can't be in a token there.
+    TokenLength(usize),
+    TokenCharacter(char, usize),
+    // A value too wide for a compact key.
+    #[cfg_attr(not(feature = "compact-keys"), allow(dead_code))]
+    Width,
+}
+
+impl fmt::Display for VaultKeyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.invalid {
+            Invalid::Length(length) => write!(f, "invalid vault key: expected 32, 36 or 45 characters, found {}", length),
+            Invalid::Format => write!(f, "invalid vault key: expected a UUID of hexadecimal digits, hyphenated as 8-4-4-4-12 or not at all, with an optional urn:uuid: prefix"),
+            Invalid::ByteLength(length) => write!(f, "invalid vault key: expected 16 bytes, found {}", length),
+            Invalid::TokenLength(length) => write!(f, "invalid vault key: expected a {}-character token or a UUID, found {} characters", token::LENGTH, length),
+            Invalid::TokenCharacter(character, index) => write!(f, "invalid vault key: unexpected character {:?} at position {} of token", character, index),
+            Invalid::Width => write!(f, "invalid vault key: compact keys hold only 64 bits, so the first 64 must be zero"),
+        }
+    }
+}
+
+impl Error for VaultKeyParseError {}
+
+impl<G> From<TryLockError<G>> for VaultError {
+    fn from(error: TryLockError<G>) -> VaultError {
+        match error {
+            TryLockError::Poisoned(_) => VaultError::Poisoned,
+            TryLockError::WouldBlock => VaultError::WouldBlock,
+        }
+    }
+}
+
+/// The differences between two vaults, as returned by `Vault::diff`. Keys are in no particular order.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct VaultDiff {
+    /// Keys with an item only in the vault `diff` was called on.
+    pub only_left: Vec<VaultKey>,
+    /// Keys with an item only in the vault passed to `diff`.
+    pub only_right: Vec<VaultKey>,
+    /// Keys with an item in both vaults, where the items are not equal.
+    pub changed: Vec<VaultKey>,
+}
+
+/// Describes which branch `Vault::update_or_insert` took.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UpsertResult {
+    /// An existing item was updated.
+    Updated,
+    /// The key was not in use, so a new item was added.
+    Inserted,
+    /// The key's item can't be removed with the key alone, or the key has no item of its own but stands in for another item's key or is held for one, so neither operation was called.
+    Refused,
+}
+
+/// Describes the outcome of `Vault::update_filter`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum UpdateFilterResult {
+    /// The operation returned a new item, which replaced the old one.
+    Updated,
+    /// The operation returned None, so the item was removed.
+    Removed,
+    /// No item with the key was found, so the operation was not called.
+    NotFound,
+}
+
+/// Describes the outcome of `Vault::watch_item`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WatchResult<T> {
+    /// The key's item changed; this is a copy of it as it is now.
+    Changed(T),
+    /// The key's item was removed.
+    Removed,
+    /// The key didn't change before the timeout ran out.
+    TimedOut,
+}
+
+/// A view into a single key of a vault, obtained from `Vault::entry`. The part of the vault holding the key stays locked for as long as the entry is alive.
+pub enum Entry<'a, T> {
+    Occupied(OccupiedEntry<'a, T>),
+    Vacant(VacantEntry<'a, T>),
+}
+
+/// An entry for a key that has an item in the vault.
+pub struct OccupiedEntry<'a, T> {
+    items: ItemsGuard<'a, T>,
+    key: VaultKey,
+    // checked out of the key's slot, and put back when the entry is dropped
+    item: Option<T>,
+    // let go of after the shard, which catches the count up first
+    room: Room<'a, T>,
+}
+
+/// An entry for a key that has no item in the vault.
+pub struct VacantEntry<'a, T> {
+    items: ItemsGuard<'a, T>,
+    key: VaultKey,
+    room: Room<'a, T>,
+}
+
+impl<'a, T> Entry<'a, T> {
+    /// Returns the key of this entry.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// assert_eq!(key, vault.entry(&key).key());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn key(&self) -> VaultKey {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Adds the item to the vault if the entry is vacant, and returns the now occupied entry.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// let item = *vault.entry(&key).or_insert(1).get();
+    /// assert_eq!(1, item);
+    /// let item = *vault.entry(&key).or_insert(2).get();
+    /// assert_eq!(1, item);
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn or_insert(self, to_add: T) -> OccupiedEntry<'a, T> {
+        self.or_insert_with(|| to_add)
+    }
+
+    /// Adds the item produced by the initializer to the vault if the entry is vacant, and returns the now occupied entry. The initializer is only called when the entry is vacant.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<Vec<i32>>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// vault.entry(&key).or_insert_with(Vec::new).get_mut().push(1);
+    /// assert_eq!(Some(vec![1]), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn or_insert_with<F>(self, init: F) -> OccupiedEntry<'a, T>
+            where F: FnOnce() -> T {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert(init()),
+        }
+    }
+
+    /// Applies the operation to the item if the entry is occupied, and returns the entry.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// vault.entry(&key).and_modify(|i| *i *= 10);
+    /// assert_eq!(Some(10), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn and_modify<F>(self, operation: F) -> Entry<'a, T>
+            where F: FnOnce(&mut T) {
+        match self {
+            Entry::Occupied(mut entry) => {
+                operation(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    /// Removes and returns the item if the entry is occupied, otherwise returns None.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Some(1), vault.entry(&key).remove());
+    /// assert_eq!(None, vault.entry(&key).remove());
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove(self) -> Option<T> {
+        match self {
+            Entry::Occupied(entry) => Some(entry.remove()),
+            Entry::Vacant(_) => None,
+        }
+    }
+}
+
+impl<'a, T> OccupiedEntry<'a, T> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> VaultKey {
+        self.key
+    }
+
+    /// Returns a reference to the item in this entry.
+    pub fn get(&self) -> &T {
+        self.item.as_ref().expect("occupied entry has an item")
+    }
+
+    /// Returns a mutable reference to the item in this entry.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.item.as_mut().expect("occupied entry has an item")
+    }
+
+    /// Replaces the item in this entry and returns the previous item.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Entry, Vault};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// if let Entry::Occupied(mut entry) = vault.entry(&key) {
+    ///     assert_eq!(1, entry.insert(2));
+    /// }
+    /// assert_eq!(Some(2), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn insert(&mut self, to_add: T) -> T {
+        std::mem::replace(self.get_mut(), to_add)
+    }
+
+    /// Removes and returns the item in this entry.
+    pub fn remove(mut self) -> T {
+        self.items.remove(&self.key);
+        if let Some(limit) = self.room.limit() {
+            limit.release(&self.key);
+        }
+        self.items.watches.changed(&self.key);
+        self.item.take().expect("occupied entry has an item")
+    }
+}
+
+impl<'a, T> Drop for OccupiedEntry<'a, T> {
+    fn drop(&mut self) {
+        if let (Some(item), Some(slot)) = (self.item.take(), self.items.get(&self.key)) {
+            let mut checked_in = write_slot(slot);
+            *checked_in = Some(item);
+            reweigh(self.room.limit(), &self.key, slot, &checked_in);
+            drop(checked_in);
+            self.items.watches.changed(&self.key);
+        }
+    }
+}
+
+impl<'a, T> VacantEntry<'a, T> {
+    /// Returns the key of this entry.
+    pub fn key(&self) -> VaultKey {
+        self.key
+    }
+
+    /// Adds the item to the vault under this entry's key, and returns the now occupied entry.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Entry, Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// if let Entry::Vacant(entry) = vault.entry(&key) {
+    ///     entry.insert(1);
+    /// }
+    /// assert_eq!(true, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn insert(mut self, to_add: T) -> OccupiedEntry<'a, T> {
+        let load = self.room.limit().map_or(1, |limit| limit.load(&to_add));
+        if !self.room.admit(&self.key, load) {
+            panic!("{}", VaultError::Full);
+        }
+        let slot = Arc::new(Lock::new(None));
+        if let Some(limit) = self.room.limit() {
+            limit.book(&self.key, &slot, load);
+        }
+        self.items.insert(self.key, slot);
+        OccupiedEntry {items: self.items, key: self.key, item: Some(to_add), room: self.room}
+    }
+}
+
+/// Sole use of a whole vault, obtained from `Vault::lock`, for running several operations as a single step. The vault stays locked for as long as the guard is alive.
+pub struct VaultGuard<'a, T> {
+    items: Shards<ItemsGuard<'a, T>>,
+    watches: &'a Watches,
+    keys: &'a dyn KeyGenerator,
+    generation: &'a AtomicU64,
+    access: &'a Access<T>,
+    names: &'a Names<T>,
+    limit: Option<&'a Limit<T>>,
+    _held: reentry::Held,
+}
+
+impl<'a, T> VaultGuard<'a, T> {
+    /// Adds an object to the locked vault and returns a key, like `Vault::add`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let mut guard = vault.lock();
+    /// 
+    /// let key = guard.add(1);
+    /// assert_eq!(true, guard.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add(&mut self, to_add: T) -> VaultKey {
+        let load = self.limit.map_or(1, |limit| limit.load(&to_add));
+        if !limit::fits(self.limit, self.items.len(), load) {
+            panic!("{}", VaultError::Full);
+        }
+        let items = &self.items;
+        let key = keygen::fresh_key(self.keys, |key| Some(key).filter(|key| !items.contains_key(key)));
+        self.items.insert(key, store(self.limit, &key, to_add, load));
+        if let Some(limit) = self.limit {
+            limit.inserted(&key);
+        }
+        self.watches.changed(&key);
+        key
+    }
+
+    /// Removes and returns the stored object with a matching key from the locked vault, like `Vault::remove`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let mut guard = vault.lock();
+    /// 
+    /// assert_eq!(Some(1), guard.remove(&key));
+    /// assert_eq!(None, guard.remove(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove(&mut self, key: &VaultKey) -> Option<T> {
+        if self.access.refusal(key, self.items.get(key)?).is_some() {
+            return None;
+        }
+        self.access.release(key);
+        self.names.release(key);
+        if let Some(limit) = self.limit {
+            limit.release(key);
+        }
+        let slot = self.items.remove(key)?;
+        self.watches.changed(key);
+        take_slot(slot)
+    }
+
+    /// Returns true if there exists an item in the locked vault with the provided key, like `Vault::has_item`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let guard = vault.lock();
+    /// 
+    /// assert_eq!(true, guard.has_item(&key));
+    /// assert_eq!(false, guard.has_item(&VaultKey::new()));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn has_item(&self, key: &VaultKey) -> bool {
+        self.items.contains_key(key)
+    }
+
+    /// Adds an object to the locked vault under the provided key, like `Vault::add_with_key`. Returns false without adding it if the key already has an item.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// let mut guard = vault.lock();
+    /// 
+    /// assert_eq!(true, guard.add_with_key(1, &key));
+    /// assert_eq!(false, guard.add_with_key(2, &key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_with_key(&mut self, to_add: T, key: &VaultKey) -> bool {
+        let load = self.limit.map_or(1, |limit| limit.load(&to_add));
+        let full = !limit::fits(self.limit, self.items.len(), load);
+        match self.items.entry(*key) {
+            hash_map::Entry::Occupied(_) => false,
+            hash_map::Entry::Vacant(_) if self.access.is_stand_in(key) || full => false,
+            hash_map::Entry::Vacant(entry) => {
+                entry.insert(store(self.limit, key, to_add, load));
+                if let Some(limit) = self.limit {
+                    limit.inserted(key);
+                }
+                self.watches.changed(key);
+                true
+            }
+        }
+    }
+
+    /// Updates an item in the locked vault with the specified key by applying the operation to it, like `Vault::update_item`. Returns false if an item with the key is not found, otherwise returns true. If the operation panics, the item stays in the vault as the operation left it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let mut guard = vault.lock();
+    /// 
+    /// assert_eq!(true, guard.update_item(&key, |i| *i += 1));
+    /// assert_eq!(Some(2), guard.remove(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_item<F>(&mut self, key: &VaultKey, operation: F) -> bool
+            where F: FnOnce(&mut T) {
+        let outcome = match self.items.get(key) {
+            Some(slot) => {
+                let mut item = write_slot(slot);
+                if item.is_none() {
+                    return false;
+                }
+                let outcome = Vault::update_locked(&mut item, operation);
+                reweigh(self.limit, key, slot, &item);
+                outcome
+            }
+            None => return false,
+        };
+        self.watches.changed(key);
+        outcome.unwrap_or_else(|payload| panic::resume_unwind(payload));
+        true
+    }
+
+    /// Clears the contents of the locked vault, like `Vault::clear`.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// let mut guard = vault.lock();
+    /// 
+    /// guard.clear();
+    /// assert_eq!(false, guard.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn clear(&mut self) {
+        if let Some(limit) = self.limit {
+            limit.release_all(self.items.keys());
+        }
+        self.items.clear();
+        self.generation.fetch_add(1, Ordering::Relaxed);
+        self.watches.changed_all();
+    }
+}
+
+/// A transaction on a whole vault, run by `Vault::atomically` or `Vault::try_atomically`. Its changes are held back until the transaction is over, and its reads see those changes.
+pub struct VaultTxn<'a, T> {
+    items: Shards<ItemsGuard<'a, T>>,
+    watches: &'a Watches,
+    keys: &'a dyn KeyGenerator,
+    limit: Option<&'a Limit<T>>,
+    // The transaction's changes by key: Some for an item added or updated,
+    // None for one removed.
+    pending: HashMap<VaultKey, Option<T>>,
+}
+
+impl<'a, T> VaultTxn<'a, T> {
+    /// Adds an object to the vault when the transaction is over, and returns its key.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// 
+    /// let key = vault.atomically(|txn| txn.add(1));
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add(&mut self, to_add: T) -> VaultKey {
+        if !self.fits(&to_add) {
+            panic!("{}", VaultError::Full);
+        }
+        let (items, pending) = (&self.items, &self.pending);
+        let key = keygen::fresh_key(self.keys, |key| Some(key).filter(|key| !items.contains_key(key) && !pending.contains_key(key)));
+        self.pending.insert(key, Some(to_add));
+        key
+    }
+
+    /// Adds an object to the vault under the provided key when the transaction is over. Returns false without adding it if the key already has an item.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{Vault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = VaultKey::new();
+    /// 
+    /// let added = vault.atomically(|txn| (txn.add_with_key(1, &key), txn.add_with_key(2, &key)));
+    /// assert_eq!((true, false), added);
+    /// assert_eq!(Some(1), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn add_with_key(&mut self, to_add: T, key: &VaultKey) -> bool {
+        if self.has_item(key) || !self.fits(&to_add) {
+            return false;
+        }
+        self.pending.insert(*key, Some(to_add));
+        true
+    }
+
+    /// Removes the stored object with a matching key from the vault when the transaction is over, and returns a copy of it.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let removed = vault.atomically(|txn| txn.remove(&key));
+    /// assert_eq!(Some(1), removed);
+    /// assert_eq!(false, vault.has_item(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn remove(&mut self, key: &VaultKey) -> Option<T>
+            where T: Clone {
+        let removed = self.get_clone(key)?;
+        self.pending.insert(*key, None);
+        Some(removed)
+    }
+
+    /// Returns true if there is an item with the provided key, counting the transaction's changes so far.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// vault.atomically(|txn| {
+    ///     txn.remove(&key);
+    ///     assert_eq!(false, txn.has_item(&key));
+    /// });
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn has_item(&self, key: &VaultKey) -> bool {
+        self.with_item(key, |_| ()).is_some()
+    }
+
+    /// Returns a copy of the item with the provided key, counting the transaction's changes so far.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// vault.atomically(|txn| {
+    ///     txn.update_item(&key, |i| *i += 1);
+    ///     assert_eq!(Some(2), txn.get_clone(&key));
+    /// });
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn get_clone(&self, key: &VaultKey) -> Option<T>
+            where T: Clone {
+        self.with_item(key, T::clone)
+    }
+
+    /// Updates the item with the provided key by applying the operation to a copy of it; the update is made in the vault when the transaction is over. Returns false if an item with the key is not found, otherwise returns true.
+    /// # Example
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = Vault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// let updated = vault.atomically(|txn| txn.update_item(&key, |i| *i *= 10));
+    /// assert_eq!(true, updated);
+    /// assert_eq!(Some(10), vault.get_clone(&key));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn update_item<F>(&mut self, key: &VaultKey, operation: F) -> bool
+            where T: Clone, F: FnOnce(&mut T) {
+        match self.get_clone(key) {
+            Some(mut item) => {
+                operation(&mut item);
+                self.pending.insert(*key, Some(item));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn with_item<R, F>(&self, key: &VaultKey, operation: F) -> Option<R>
+            where F: FnOnce(&T) -> R {
+        match self.pending.get(key) {
+            Some(pending) => pending.as_ref().map(operation),
+            None => self.items.get(key).and_then(|slot| read_slot(slot).as_ref().map(operation)),
+        }
+    }
+
+    // Whether the vault will have space for adding the item once the changes
+    // so far are made.
+    fn fits(&self, adding: &T) -> bool {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return true,
+        };
+        let replacing = self.pending.keys().map(|key| limit.load_of(key, self.items.contains_key(key))).sum();
+        let incoming = self.pending.values().flatten().map(|item| limit.load(item)).sum::<usize>();
+        limit.fits_changed(self.items.len(), replacing, incoming.saturating_add(limit.load(adding)))
+    }
+
+    // Makes the transaction's changes. An item updated in place keeps its
+    // slot, so an update already waiting on that slot applies after this one.
+    fn commit(mut self) {
+        for (key, change) in self.pending.drain() {
+            self.watches.changed(&key);
+            match change {
+                Some(item) => match self.items.get(&key) {
+                    Some(slot) => {
+                        let mut updated = write_slot(slot);
+                        *updated = Some(item);
+                        reweigh(self.limit, &key, slot, &updated);
+                    }
+                    None => {
+                        let load = self.limit.map_or(1, |limit| limit.load(&item));
+                        self.items.insert(key, store(self.limit, &key, item, load));
+                        if let Some(limit) = self.limit {
+                            limit.inserted(&key);
+                        }
+                    }
+                },
+                None => {
+                    self.items.remove(&key);
+                    if let Some(limit) = self.limit {
+                        limit.release(&key);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_has_item() {
+        let vault = Vault::new();
+        let to_add = "stuff";
+        let key = vault.add(to_add);
+        let has_item = vault.has_item(&key);
+        assert_eq!(true, has_item);
+    }
+
+    #[test]
+    fn has_item_wrong_key() {
+        let vault = Vault::new();
+        let to_add = "garbage";
+        vault.add(to_add);
+        let wrong_key = VaultKey::new();
+        let has_item = vault.has_item(&wrong_key);
+        assert_eq!(false, has_item);
+    }
+
+    #[test]
+    fn add_duplicates_unique_keys() {
+        let vault = Vault::new();
+        let to_add = 1.0;
+        let key_1 = vault.add(to_add);
+        let key_2 = vault.add(to_add);
+        assert_ne!(key_1, key_2);
+    }
+
+    #[test]
+    fn add_with_key_has_item() {
+        let vault = Vault::new();
+        let to_add = 1.0;
+        let key = VaultKey::new();
+        let added = vault.add_with_key(to_add, &key);
+        let has_item = vault.has_item(&key);
+        assert_eq!(true, added);
+        assert_eq!(true, has_item);
+    }
+
+    #[test]
+    fn add_with_key_duplicate_fails() {
+        let vault = Vault::new();
+        let to_add = 1.0;
+        let key = VaultKey::new();
+        let added_1 = vault.add_with_key(to_add, &key);
+        let added_2 = vault.add_with_key(to_add, &key);
+        assert_eq!(true, added_1);
+        assert_eq!(false, added_2);
+    }
+
+    #[test]
+    fn add_remove() {
+        let vault = Vault::new();
+        let to_add = 1;
+        let key = vault.add(to_add);
+        let retrieved = vault.remove(&key).unwrap();
+        assert_eq!(to_add, retrieved);
+    }
+
+    #[test]
+    fn remove_before_add() {
+        let vault = Vault::<i32>::new();
+        let key = VaultKey::new();
+        let retrieved = vault.remove(&key);
+        assert_eq!(None, retrieved);
+    }
+
+    #[test]
+    fn seeded_keys_repeat_across_vaults() {
+        let first = Vault::with_key_generator(SeededKeyGenerator::new(42));
+        let second = Vault::with_key_generator(SeededKeyGenerator::new(42));
+        let keys = first.add_many(0..100);
+        assert_eq!(keys, second.add_many(0..100));
+        assert_eq!(100, keys.iter().collect::<std::collections::HashSet<_>>().len());
+        #[cfg(not(feature = "compact-keys"))]
+        assert!(keys.iter().all(|key| key.as_uuid().get_version_num() == 4));
+        assert_ne!(keys[0], Vault::with_key_generator(SeededKeyGenerator::new(43)).add(0));
+    }
+
+    // Gives each key twice in a row.
+    #[derive(Default)]
+    struct Stutter(AtomicUsize);
+
+    impl KeyGenerator for Stutter {
+        fn next_key(&self) -> VaultKey {
+            VaultKey::from_u128(self.0.fetch_add(1, Ordering::Relaxed) as u128 / 2 + 1)
+        }
+    }
+
+    #[test]
+    fn repeated_keys_are_skipped() {
+        let vault = Vault::with_key_generator(Stutter::default());
+        assert_eq!(VaultKey::from_u128(1), vault.add(1));
+        assert_eq!(VaultKey::from_u128(2), vault.add(2));
+        assert_eq!(vec![VaultKey::from_u128(3), VaultKey::from_u128(4)], vault.add_many(vec![3, 4]));
+        assert_eq!(4, vault.len());
+        assert_eq!(Some(1), vault.get_clone(&VaultKey::from_u128(1)));
+        assert_eq!(Some(2), vault.get_clone(&VaultKey::from_u128(2)));
+    }
+
+    // Gives the same key every time.
+    struct Stuck;
+
+    impl KeyGenerator for Stuck {
+        fn next_key(&self) -> VaultKey {
+            VaultKey::MAX
+        }
+    }
+
+    #[test]
+    fn stuck_key_generator_panics() {
+        let vault = Vault::with_key_generator(Stuck);
+        assert_eq!(VaultKey::MAX, vault.add(1));
+        let second = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| vault.add(2)));
+        let message = second.unwrap_err().downcast_ref::<String>().cloned().unwrap_or_default();
+        assert_eq!(true, message.contains("already in use"), "{}", message);
+        assert_eq!(1, vault.len());
+        assert_eq!(Some(1), vault.get_clone(&VaultKey::MAX));
+    }
+
+    #[test]
+    fn add_update() {
+        let vault = Vault::new();
+        let to_add = 1.0;
+        let key = vault.add(to_add);
+        let double = |i: &mut f64| *i *= 2.0;
+        let expected = 2.0;
+        let updated = vault.update_item(&key, double);
+        let retrieved = vault.remove(&key).unwrap();
+        assert_eq!(true, updated);
+        assert_eq!(expected, retrieved);
+    }
+
+    #[test]
+    fn add_remove_twice() {
+        let vault = Vault::new();
+        let to_add = 3;
+        let key = vault.add(to_add);
+        vault.remove(&key);
+        let second = vault.remove(&key);
+        assert_eq!(None, second);
+    }
+
+    #[test] 
+    fn add_clear_doesnt_have_item() {
+        let vault = Vault::new();
+        let to_add = "thing";
+        let key = vault.add(to_add);
+        vault.clear();
+        let has_item = vault.has_item(&key);
+        assert_eq!(false, has_item);
+    }
+
+    #[test]
+    fn add_remove_doesnt_have_item() {
+        let vault = Vault::new();
+        let to_add = "an item";
+        let key = vault.add(to_add);
+        vault.remove(&key);
+        let has_item = vault.has_item(&key);
+        assert_eq!(false, has_item);
+    }
+
+    #[test]
+    fn add_get_clone() {
+        let vault = Vault::new();
+        let to_add = "a clone";
+        let key = vault.add(to_add);
+        let retrieved = vault.get_clone(&key);
+        assert_eq!(Some(to_add), retrieved);
+    }
+
+    #[test]
+    fn get_clone_repeated_keeps_item() {
+        let vault = Vault::new();
+        let to_add = String::from("read me twice");
+        let key = vault.add(to_add.clone());
+        let first = vault.get_clone(&key);
+        let second = vault.get_clone(&key);
+        let has_item = vault.has_item(&key);
+        assert_eq!(Some(to_add.clone()), first);
+        assert_eq!(Some(to_add), second);
+        assert_eq!(true, has_item);
+    }
+
+    #[test]
+    fn get_clone_wrong_key() {
+        let vault = Vault::new();
+        let to_add = 4;
+        let key = vault.add(to_add);
+        let wrong_key = VaultKey::new();
+        let retrieved = vault.get_clone(&wrong_key);
+        let has_item = vault.has_item(&key);
+        assert_eq!(None, retrieved);
+        assert_eq!(true, has_item);
+    }
+
+    #[test]
+    fn add_with_item() {
+        let vault = Vault::new();
+        let to_add = vec![1, 2, 3];
+        let key = vault.add(to_add);
+        let sum = vault.with_item(&key, |v| v.iter().sum::<i32>());
+        let has_item = vault.has_item(&key);
+        assert_eq!(Some(6), sum);
+        assert_eq!(true, has_item);
+    }
+
+    #[test]
+    fn with_item_wrong_key_skips_operation() {
+        let vault = Vault::new();
+        let to_add = 1;
+        vault.add(to_add);
+        let wrong_key = VaultKey::new();
+        let mut called = false;
+        let result = vault.with_item(&wrong_key, |_| called = true);
+        assert_eq!(None, result);
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn with_item_releases_lock() {
+        let vault = Vault::new();
+        let to_add = "locked?";
+        let key = vault.add(to_add);
+        vault.with_item(&key, |_| ());
+        let removed = vault.remove(&key);
+        assert_eq!(Some(to_add), removed);
+    }
+
+    #[test]
+    fn add_with_item_mut() {
+        let vault = Vault::new();
+        let to_add = 2;
+        let key = vault.add(to_add);
+        let result = vault.with_item_mut(&key, |i| {
+            *i *= 5;
+            *i + 1
+        });
+        let retrieved = vault.remove(&key);
+        assert_eq!(Some(11), result);
+        assert_eq!(Some(10), retrieved);
+    }
+
+    #[test]
+    fn with_item_mut_wrong_key_skips_operation() {
+        let vault = Vault::new();
+        let to_add = 1;
+        vault.add(to_add);
+        let wrong_key = VaultKey::new();
+        let mut called = false;
+        let result = vault.with_item_mut(&wrong_key, |_| called = true);
+        assert_eq!(None, result);
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn add_with_key_duplicate_keeps_original() {
+        let vault = Vault::new();
+        let key = VaultKey::new();
+        vault.add_with_key(1, &key);
+        vault.add_with_key(2, &key);
+        let retrieved = vault.remove(&key);
+        assert_eq!(Some(1), retrieved);
+    }
+
+    #[test]
+    fn new_is_empty() {
+        let vault = Vault::<i32>::new();
+        assert_eq!(0, vault.len());
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn len_tracks_mutations() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = VaultKey::new();
+        vault.add_with_key(2, &key_2);
+        assert_eq!(2, vault.len());
+        vault.add_with_key(3, &key_2);
+        assert_eq!(2, vault.len());
+        vault.remove(&key_1);
+        vault.remove(&key_1);
+        assert_eq!(1, vault.len());
+        vault.update_item(&key_2, |i| *i += 1);
+        assert_eq!(1, vault.len());
+        vault.add(4);
+        vault.clear();
+        assert_eq!(0, vault.len());
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn len_polled_from_many_threads() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let done = Arc::new(AtomicBool::new(false));
+        let max_len = 100;
+        let pollers: Vec<_> = (0..4).map(|_| {
+            let vault = Arc::clone(&vault);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    assert!(vault.len() <= max_len);
+                }
+            })
+        }).collect();
+        let writer = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for round in 0..200 {
+                    let keys: Vec<_> = (0..max_len).map(|i| vault.add(i)).collect();
+                    for key in keys.iter().take(round % max_len) {
+                        vault.remove(key);
+                    }
+                    assert_eq!(max_len - round % max_len, vault.len());
+                    vault.clear();
+                }
+                let key = vault.add(0);
+                vault.add_with_key(1, &key);
+            })
+        };
+        writer.join().unwrap();
+        done.store(true, Ordering::Relaxed);
+        for poller in pollers {
+            poller.join().unwrap();
+        }
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn keys_empty() {
+        let vault = Vault::<i32>::new();
+        let keys = vault.keys();
+        assert_eq!(true, keys.is_empty());
+    }
+
+    #[test]
+    fn keys_matches_added() {
+        let vault = Vault::new();
+        let mut added = vec![vault.add(1), vault.add(2), vault.add(3)];
+        let mut keys = vault.keys();
+        added.sort();
+        keys.sort();
+        assert_eq!(added, keys);
+    }
+
+    #[test]
+    fn keys_snapshot_survives_mutation() {
+        let vault = Vault::new();
+        let key_1 = vault.add("first");
+        let keys = vault.keys();
+        vault.remove(&key_1);
+        let key_2 = vault.add("second");
+        assert_eq!(vec![key_1], keys);
+        assert_eq!(vec![key_2], vault.keys());
+    }
+
+    #[test]
+    fn add_drain() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        let mut drained = vault.drain();
+        drained.sort_by_key(|(_, i)| *i);
+        assert_eq!(vec![(key_1, 1), (key_2, 2)], drained);
+        assert_eq!(false, vault.has_item(&key_1));
+        assert_eq!(0, vault.len());
+    }
+
+    #[test]
+    fn drain_empty() {
+        let vault = Vault::<i32>::new();
+        let drained = vault.drain();
+        assert_eq!(true, drained.is_empty());
+    }
+
+    #[test]
+    fn drain_while_adding() {
+        use std::collections::HashSet;
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let adders: Vec<_> = (0..4).map(|t| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                (0..1000).map(|i| vault.add(t * 1000 + i)).collect::<Vec<_>>()
+            })
+        }).collect();
+        let drainer = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                (0..100).flat_map(|_| vault.drain()).collect::<Vec<_>>()
+            })
+        };
+        let added: HashSet<_> = adders.into_iter()
+            .flat_map(|adder| adder.join().unwrap())
+            .collect();
+        let mut drained = drainer.join().unwrap();
+        drained.extend(vault.drain());
+        let drained_keys: HashSet<_> = drained.iter().map(|(key, _)| *key).collect();
+        assert_eq!(added.len(), drained.len());
+        assert_eq!(added, drained_keys);
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn retain_keeps_all() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        vault.retain(|_, _| true);
+        assert_eq!(true, vault.has_item(&key_1));
+        assert_eq!(true, vault.has_item(&key_2));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn retain_keeps_none() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        vault.retain(|_, _| false);
+        assert_eq!(false, vault.has_item(&key_1));
+        assert_eq!(false, vault.has_item(&key_2));
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn retain_mixed() {
+        let vault = Vault::new();
+        let keys: Vec<_> = (0..10).map(|i| vault.add(i)).collect();
+        let dropped_key = keys[4];
+        vault.retain(|key, i| i % 2 == 0 && *key != dropped_key);
+        for (i, key) in keys.iter().enumerate() {
+            let has_item = vault.has_item(key);
+            assert_eq!(i % 2 == 0 && i != 4, has_item);
+        }
+        assert_eq!(4, vault.len());
+    }
+
+    #[test]
+    fn remove_where_mixed() {
+        let vault = Vault::new();
+        let keys: Vec<_> = (0..6).map(|i| vault.add(i)).collect();
+        let mut removed = vault.remove_where(|_, i| i % 3 == 0);
+        removed.sort_by_key(|(_, i)| *i);
+        assert_eq!(vec![(keys[0], 0), (keys[3], 3)], removed);
+        assert_eq!(4, vault.len());
+        for key in keys.iter().filter(|key| !removed.iter().any(|(k, _)| k == *key)) {
+            assert_eq!(true, vault.has_item(key));
+        }
+    }
+
+    #[test]
+    fn remove_where_none_match() {
+        let vault = Vault::new();
+        let key = vault.add("keep");
+        let removed = vault.remove_where(|_, _| false);
+        assert_eq!(true, removed.is_empty());
+        assert_eq!(Some("keep"), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn remove_where_empty_skips_predicate() {
+        let vault = Vault::<i32>::new();
+        let mut called = false;
+        let removed = vault.remove_where(|_, _| {
+            called = true;
+            true
+        });
+        assert_eq!(true, removed.is_empty());
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn pop_any_empty() {
+        let vault = Vault::<i32>::new();
+        let popped = vault.pop_any();
+        assert_eq!(None, popped);
+    }
+
+    #[test]
+    fn add_pop_any() {
+        let vault = Vault::new();
+        let to_add = "only";
+        let key = vault.add(to_add);
+        let popped = vault.pop_any();
+        assert_eq!(Some((key, to_add)), popped);
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(0, vault.len());
+    }
+
+    #[test]
+    fn pop_any_from_many_threads() {
+        use std::collections::HashSet;
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let added: HashSet<_> = (0..4000).map(|i| vault.add(i)).collect();
+        let poppers: Vec<_> = (0..4).map(|_| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                let mut popped = Vec::new();
+                while let Some((key, _)) = vault.pop_any() {
+                    popped.push(key);
+                }
+                popped
+            })
+        }).collect();
+        let popped: Vec<_> = poppers.into_iter()
+            .flat_map(|popper| popper.join().unwrap())
+            .collect();
+        let unique: HashSet<_> = popped.iter().copied().collect();
+        assert_eq!(popped.len(), unique.len());
+        assert_eq!(added, unique);
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn contains_value_empty() {
+        let vault = Vault::<i32>::new();
+        let contains = vault.contains_value(&0);
+        assert_eq!(false, contains);
+    }
+
+    #[test]
+    fn contains_value_duplicates() {
+        let vault = Vault::new();
+        let to_add = "twice";
+        let key_1 = vault.add(to_add);
+        let key_2 = vault.add(to_add);
+        vault.add("other");
+        assert_eq!(true, vault.contains_value(&to_add));
+        vault.remove(&key_1);
+        assert_eq!(true, vault.contains_value(&to_add));
+        vault.remove(&key_2);
+        assert_eq!(false, vault.contains_value(&to_add));
+        assert_eq!(true, vault.contains_value(&"other"));
+    }
+
+    #[test]
+    fn find_key_matches() {
+        let vault = Vault::new();
+        vault.add(1);
+        let key = vault.add(2);
+        vault.add(3);
+        let found = vault.find_key(|i| *i == 2);
+        assert_eq!(Some(key), found);
+        assert_eq!(true, vault.has_item(&key));
+    }
+
+    #[test]
+    fn find_key_no_match() {
+        let vault = Vault::new();
+        vault.add(1);
+        let found = vault.find_key(|i| *i == 2);
+        assert_eq!(None, found);
+    }
+
+    #[test]
+    fn find_keys_empty() {
+        let vault = Vault::<i32>::new();
+        let found = vault.find_keys(|_| true);
+        assert_eq!(true, found.is_empty());
+    }
+
+    #[test]
+    fn find_keys_no_match() {
+        let vault = Vault::new();
+        vault.add(1);
+        vault.add(3);
+        let found = vault.find_keys(|i| i % 2 == 0);
+        assert_eq!(true, found.is_empty());
+    }
+
+    #[test]
+    fn find_keys_mixed() {
+        use std::collections::HashSet;
+
+        let vault = Vault::new();
+        let expected: HashSet<_> = (0..10)
+            .map(|i| (i, vault.add(i)))
+            .filter(|(i, _)| i % 2 == 0)
+            .map(|(_, key)| key)
+            .collect();
+        let found = vault.find_keys(|i| i % 2 == 0);
+        let found_set: HashSet<_> = found.iter().copied().collect();
+        assert_eq!(expected.len(), found.len());
+        assert_eq!(expected, found_set);
+    }
+
+    #[test]
+    fn count_where_mixed() {
+        let vault = Vault::new();
+        for i in 0..10 {
+            vault.add(i);
+        }
+        let count = vault.count_where(|i| *i < 3);
+        assert_eq!(3, count);
+    }
+
+    #[test]
+    fn count_where_while_adding() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let adder = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for i in 0..2000 {
+                    vault.add(i);
+                }
+            })
+        };
+        let mut last = 0;
+        while last < 1000 {
+            let count = vault.count_where(|i| i % 2 == 0);
+            assert!(count >= last);
+            last = count;
+        }
+        adder.join().unwrap();
+        assert_eq!(1000, vault.count_where(|i| i % 2 == 0));
+    }
+
+    #[test]
+    fn get_or_insert_with_absent() {
+        let vault = Vault::new();
+        let key = VaultKey::new();
+        let inserted = vault.get_or_insert_with(&key, || 5);
+        assert_eq!(true, inserted);
+        assert_eq!(Some(5), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn get_or_insert_with_present_skips_init() {
+        let vault = Vault::new();
+        let key = vault.add(5);
+        let mut called = false;
+        let inserted = vault.get_or_insert_with(&key, || {
+            called = true;
+            6
+        });
+        assert_eq!(false, inserted);
+        assert_eq!(false, called);
+        assert_eq!(Some(5), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn get_or_insert_with_race() {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        for _ in 0..100 {
+            let vault = Arc::new(Vault::new());
+            let key = VaultKey::new();
+            let calls = Arc::new(AtomicUsize::new(0));
+            let barrier = Arc::new(Barrier::new(2));
+            let racers: Vec<_> = (0..2).map(|t| {
+                let vault = Arc::clone(&vault);
+                let calls = Arc::clone(&calls);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    barrier.wait();
+                    vault.get_or_insert_with(&key, || {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        t
+                    })
+                })
+            }).collect();
+            let inserted = racers.into_iter()
+                .map(|racer| racer.join().unwrap())
+                .filter(|inserted| *inserted)
+                .count();
+            assert_eq!(1, inserted);
+            assert_eq!(1, calls.load(Ordering::SeqCst));
+            assert_eq!(1, vault.len());
+        }
+    }
+
+    #[test]
+    fn entry_vacant_or_insert() {
+        let vault = Vault::new();
+        let key = VaultKey::new();
+        let item = *vault.entry(&key).or_insert(3).get();
+        assert_eq!(3, item);
+        assert_eq!(Some(3), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn entry_occupied_or_insert_with_skips_init() {
+        let vault = Vault::new();
+        let key = vault.add(3);
+        let mut called = false;
+        let item = *vault.entry(&key).or_insert_with(|| {
+            called = true;
+            4
+        }).get();
+        assert_eq!(3, item);
+        assert_eq!(false, called);
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn entry_and_modify() {
+        let vault = Vault::new();
+        let key = vault.add(3);
+        let missing = VaultKey::new();
+        vault.entry(&key).and_modify(|i| *i += 1);
+        vault.entry(&missing).and_modify(|i| *i += 1);
+        assert_eq!(Some(4), vault.get_clone(&key));
+        assert_eq!(false, vault.has_item(&missing));
+    }
+
+    #[test]
+    fn entry_remove() {
+        let vault = Vault::new();
+        let key = vault.add("gone");
+        let removed = vault.entry(&key).remove();
+        let removed_again = vault.entry(&key).remove();
+        assert_eq!(Some("gone"), removed);
+        assert_eq!(None, removed_again);
+        assert_eq!(0, vault.len());
+    }
+
+    #[test]
+    fn entry_occupied_insert_replaces() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let previous = match vault.entry(&key) {
+            Entry::Occupied(mut entry) => Some(entry.insert(2)),
+            Entry::Vacant(_) => None,
+        };
+        assert_eq!(Some(1), previous);
+        assert_eq!(Some(2), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn entry_releases_lock_on_drop() {
+        let vault = Vault::new();
+        let key = VaultKey::new();
+        let entry = vault.entry(&key);
+        drop(entry);
+        let added = vault.add_with_key(1, &key);
+        assert_eq!(true, added);
+    }
+
+    #[test]
+    fn entry_concurrent_counters() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key = VaultKey::new();
+        let workers: Vec<_> = (0..4).map(|_| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for _ in 0..1000 {
+                    vault.entry(&key).and_modify(|i| *i += 1).or_insert(1);
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(Some(4000), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn replace_fresh_key() {
+        let vault = Vault::new();
+        let key = VaultKey::new();
+        let replaced = vault.replace(&key, "new");
+        assert_eq!(None, replaced);
+        assert_eq!(Some("new"), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn replace_overwrites() {
+        let vault = Vault::new();
+        let key = vault.add("old");
+        let replaced = vault.replace(&key, "new");
+        assert_eq!(Some("old"), replaced);
+        assert_eq!(Some("new"), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn rekey_moves_item() {
+        let vault = Vault::new();
+        let old_key = vault.add("moving");
+        let new_key = VaultKey::new();
+        let moved = vault.rekey(&old_key, &new_key);
+        assert_eq!(true, moved);
+        assert_eq!(false, vault.has_item(&old_key));
+        assert_eq!(Some("moving"), vault.get_clone(&new_key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn rekey_missing_old_key() {
+        let vault = Vault::<i32>::new();
+        let old_key = VaultKey::new();
+        let new_key = VaultKey::new();
+        let moved = vault.rekey(&old_key, &new_key);
+        assert_eq!(false, moved);
+        assert_eq!(false, vault.has_item(&new_key));
+    }
+
+    #[test]
+    fn rekey_occupied_new_key_keeps_both() {
+        let vault = Vault::new();
+        let old_key = vault.add(1);
+        let new_key = vault.add(2);
+        let moved = vault.rekey(&old_key, &new_key);
+        assert_eq!(false, moved);
+        assert_eq!(Some(1), vault.get_clone(&old_key));
+        assert_eq!(Some(2), vault.get_clone(&new_key));
+    }
+
+    #[test]
+    fn rekey_fresh_moves_item() {
+        let vault = Vault::new();
+        let old_key = vault.add(1);
+        let new_key = vault.rekey_fresh(&old_key).unwrap();
+        assert_ne!(old_key, new_key);
+        assert_eq!(false, vault.has_item(&old_key));
+        assert_eq!(Some(1), vault.get_clone(&new_key));
+    }
+
+    #[test]
+    fn rekey_fresh_missing_old_key() {
+        let vault = Vault::<i32>::new();
+        let old_key = VaultKey::new();
+        let new_key = vault.rekey_fresh(&old_key);
+        assert_eq!(None, new_key);
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn swap_exchanges_items() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        let swapped = vault.swap(&key_1, &key_2);
+        assert_eq!(true, swapped);
+        assert_eq!(Some(2), vault.get_clone(&key_1));
+        assert_eq!(Some(1), vault.get_clone(&key_2));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn swap_missing_key() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let missing = VaultKey::new();
+        let swapped = vault.swap(&key_1, &missing);
+        assert_eq!(false, swapped);
+        assert_eq!(Some(1), vault.get_clone(&key_1));
+        assert_eq!(false, vault.has_item(&missing));
+    }
+
+    #[test]
+    fn swap_same_key() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let swapped = vault.swap(&key, &key);
+        assert_eq!(true, swapped);
+        assert_eq!(Some(1), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn swap_never_leaves_key_empty() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let active = vault.add("active");
+        let standby = vault.add("standby");
+        let done = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..4).map(|_| {
+            let vault = Arc::clone(&vault);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    assert_eq!(true, vault.has_item(&active));
+                    assert_eq!(true, vault.has_item(&standby));
+                }
+            })
+        }).collect();
+        for _ in 0..10000 {
+            vault.swap(&active, &standby);
+        }
+        done.store(true, Ordering::Relaxed);
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(Some("active"), vault.get_clone(&active));
+    }
+
+    #[test]
+    fn add_update_item_map() {
+        let vault = Vault::new();
+        let key = vault.add(String::from("abc"));
+        let old_length = vault.update_item_map(&key, |s| {
+            let old_length = s.len();
+            s.push('d');
+            old_length
+        });
+        assert_eq!(Some(3), old_length);
+        assert_eq!(Some(String::from("abcd")), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn update_item_map_missing_key_skips_operation() {
+        let vault = Vault::<i32>::new();
+        let key = VaultKey::new();
+        let mut called = false;
+        let result = vault.update_item_map(&key, |_| called = true);
+        assert_eq!(None, result);
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn try_update_item_success() {
+        let vault = Vault::new();
+        let key = vault.add(String::from("41"));
+        let updated = vault.try_update_item(&key, |s| {
+            s.parse::<i32>().map(|i| (i + 1).to_string())
+        });
+        assert_eq!(Ok(true), updated);
+        assert_eq!(Some(String::from("42")), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn try_update_item_error_keeps_original() {
+        let vault = Vault::new();
+        let key = vault.add(String::from("not a number"));
+        let updated = vault.try_update_item(&key, |s| {
+            s.parse::<i32>().map(|i| (i + 1).to_string())
+        });
+        assert_eq!(true, updated.is_err());
+        assert_eq!(Some(String::from("not a number")), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn try_update_item_missing_key() {
+        let vault = Vault::<i32>::new();
+        let key = VaultKey::new();
+        let mut called = false;
+        let updated: Result<bool, ()> = vault.try_update_item(&key, |i| {
+            called = true;
+            Ok(*i)
+        });
+        assert_eq!(Ok(false), updated);
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn update_or_insert_inserts() {
+        let vault = Vault::new();
+        let key = VaultKey::new();
+        let mut updated = false;
+        let result = vault.update_or_insert(&key, |i| {
+            updated = true;
+            i
+        }, || 10);
+        assert_eq!(UpsertResult::Inserted, result);
+        assert_eq!(false, updated);
+        assert_eq!(Some(10), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn update_or_insert_updates() {
+        let vault = Vault::new();
+        let key = vault.add(10);
+        let mut inserted = false;
+        let result = vault.update_or_insert(&key, |i| i * 2, || {
+            inserted = true;
+            0
+        });
+        assert_eq!(UpsertResult::Updated, result);
+        assert_eq!(false, inserted);
+        assert_eq!(Some(20), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn update_or_insert_panicking_update_drops_item() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::new();
+        let key = vault.add(10);
+        let other = vault.add(20);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.update_or_insert(&key, |_| panic!("update failed"), || 0);
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(None, vault.get_clone(&key));
+        assert_eq!(false, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(1, vault.len());
+        assert_eq!(UpsertResult::Inserted, vault.update_or_insert(&key, |i| i + 1, || 1));
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(Some(20), vault.get_clone(&other));
+    }
+
+    #[test]
+    fn update_all_empty() {
+        let vault = Vault::<i32>::new();
+        let updated = vault.update_all(|_, i| *i += 1);
+        assert_eq!(0, updated);
+    }
+
+    #[test]
+    fn update_all_changes_every_item() {
+        let vault = Vault::new();
+        let keys: Vec<_> = (0..300).map(|i| vault.add(i)).collect();
+        let updated = vault.update_all(|_, i| *i += 1000);
+        assert_eq!(300, updated);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i + 1000), vault.get_clone(key));
+        }
+    }
+
+    #[test]
+    fn increment_decrement() {
+        let vault = Vault::new();
+        let key = vault.add(5u32);
+        assert_eq!(Some(6), vault.increment(&key));
+        assert_eq!(Some(7), vault.increment(&key));
+        assert_eq!(Some(6), vault.decrement(&key));
+        assert_eq!(Some(6), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn add_assign_sub_assign() {
+        let vault = Vault::new();
+        let key = vault.add(1.5);
+        assert_eq!(Some(4.0), vault.add_assign(&key, 2.5));
+        assert_eq!(Some(3.0), vault.sub_assign(&key, 1.0));
+    }
+
+    #[test]
+    fn increment_missing_key() {
+        let vault = Vault::<i64>::new();
+        let key = VaultKey::new();
+        assert_eq!(None, vault.increment(&key));
+        assert_eq!(None, vault.add_assign(&key, 3));
+        assert_eq!(false, vault.has_item(&key));
+    }
+
+    #[test]
+    fn increment_from_many_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(0u64);
+        let workers: Vec<_> = (0..8).map(|_| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for _ in 0..5000 {
+                    vault.increment(&key);
+                }
+            })
+        }).collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(Some(40000), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn update_filter_updates() {
+        let vault = Vault::new();
+        let key = vault.add(3);
+        let result = vault.update_filter(&key, |i| Some(i - 1));
+        assert_eq!(UpdateFilterResult::Updated, result);
+        assert_eq!(Some(2), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn update_filter_removes() {
+        let vault = Vault::new();
+        let key = vault.add(3);
+        let result = vault.update_filter(&key, |_| None);
+        assert_eq!(UpdateFilterResult::Removed, result);
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(0, vault.len());
+    }
+
+    #[test]
+    fn update_filter_missing_key() {
+        let vault = Vault::<i32>::new();
+        let key = VaultKey::new();
+        let mut called = false;
+        let result = vault.update_filter(&key, |i| {
+            called = true;
+            Some(i)
+        });
+        assert_eq!(UpdateFilterResult::NotFound, result);
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn update_filter_panicking_operation_drops_item() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::new();
+        let key = vault.add_named("config", 3);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.update_filter(&key, |_| panic!("operation failed"));
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(None, vault.key_for("config"));
+        assert_eq!(0, vault.len());
+        assert_eq!(UpdateFilterResult::NotFound, vault.update_filter(&key, Some));
+        assert_eq!(true, vault.add_with_key(4, &key));
+        assert_eq!(UpdateFilterResult::Updated, vault.update_filter(&key, |i| Some(i + 1)));
+        assert_eq!(Some(5), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn add_many_empty() {
+        let vault = Vault::<i32>::new();
+        let keys = vault.add_many(Vec::new());
+        assert_eq!(true, keys.is_empty());
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn add_many_keys_in_order() {
+        let vault = Vault::new();
+        let before = vault.len();
+        let keys = vault.add_many(0..1000);
+        assert_eq!(0, before);
+        assert_eq!(1000, vault.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i), vault.get_clone(key));
+        }
+    }
+
+    #[test]
+    fn add_many_all_or_nothing() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let batch_size = 100;
+        let adder = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for _ in 0..200 {
+                    vault.add_many(vec![0; batch_size]);
+                }
+            })
+        };
+        while !adder.is_finished() {
+            let count = vault.count_where(|_| true);
+            assert_eq!(0, count % batch_size);
+        }
+        adder.join().unwrap();
+        assert_eq!(200 * batch_size, vault.len());
+    }
+
+    #[test]
+    fn remove_many_mixed() {
+        let vault = Vault::new();
+        let key_1 = vault.add("one");
+        let key_2 = vault.add("two");
+        let key_3 = vault.add("three");
+        let unknown = VaultKey::new();
+        let removed = vault.remove_many(&[key_1, unknown, key_2, key_1]);
+        assert_eq!(vec![Some("one"), None, Some("two"), None], removed);
+        assert_eq!(false, vault.has_item(&key_1));
+        assert_eq!(false, vault.has_item(&key_2));
+        assert_eq!(true, vault.has_item(&key_3));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn remove_many_empty_slice() {
+        let vault = Vault::new();
+        vault.add(1);
+        let removed = vault.remove_many(&[]);
+        assert_eq!(true, removed.is_empty());
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn get_many_between_writes() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        let first = vault.get_many(&[key_1, key_2]);
+        vault.update_all(|_, i| *i *= 10);
+        let second = vault.get_many(&[key_1, key_2]);
+        assert_eq!(vec![Some(1), Some(2)], first);
+        assert_eq!(vec![Some(10), Some(20)], second);
+        assert_eq!(true, vault.has_item(&key_1));
+    }
+
+    #[test]
+    fn get_many_consistent_under_writes() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key_1 = vault.add(0);
+        let key_2 = vault.add(0);
+        let writer = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                for _ in 0..5000 {
+                    vault.update_all(|_, i| *i += 1);
+                }
+            })
+        };
+        while !writer.is_finished() {
+            let items = vault.get_many(&[key_1, key_2]);
+            assert_eq!(items[0], items[1]);
+        }
+        writer.join().unwrap();
+        assert_eq!(vec![Some(5000), Some(5000)], vault.get_many(&[key_1, key_2]));
+    }
+
+    #[test]
+    fn update_many_skips_missing() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        let untouched = vault.add(3);
+        let keys = [VaultKey::new(), key_1, VaultKey::new(), key_2, VaultKey::new()];
+        let updated = vault.update_many(&keys, |_, i| *i *= 100);
+        assert_eq!(2, updated);
+        assert_eq!(vec![Some(100), Some(200), Some(3)], vault.get_many(&[key_1, key_2, untouched]));
+    }
+
+    #[test]
+    fn update_many_all_or_nothing() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let keys = vault.add_many(vec![0; 4]);
+        let writer = {
+            let vault = Arc::clone(&vault);
+            let keys = keys.clone();
+            thread::spawn(move || {
+                for _ in 0..5000 {
+                    vault.update_many(&keys, |_, i| *i += 1);
+                }
+            })
+        };
+        while !writer.is_finished() {
+            let items = vault.get_many(&keys);
+            assert_eq!(true, items.iter().all(|item| *item == items[0]));
+        }
+        writer.join().unwrap();
+        assert_eq!(vec![Some(5000); 4], vault.get_many(&keys));
+    }
+
+    #[test]
+    fn swap_contents_exchanges() {
+        let vault_1 = Vault::new();
+        let vault_2 = Vault::new();
+        let key_1 = vault_1.add(1);
+        let key_2 = vault_2.add(2);
+        let key_3 = vault_2.add(3);
+        vault_1.swap_contents(&vault_2);
+        assert_eq!(vec![None, Some(2), Some(3)], vault_1.get_many(&[key_1, key_2, key_3]));
+        assert_eq!(vec![Some(1), None, None], vault_2.get_many(&[key_1, key_2, key_3]));
+        assert_eq!(2, vault_1.len());
+        assert_eq!(1, vault_2.len());
+    }
+
+    #[test]
+    fn swap_contents_with_self() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        vault.swap_contents(&vault);
+        assert_eq!(Some(1), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn swap_contents_opposite_directions() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::thread;
+
+        let blue = Arc::new(Vault::new());
+        let green = Arc::new(Vault::new());
+        let key_blue = blue.add("blue");
+        let key_green = green.add("green");
+        let done = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let blue = Arc::clone(&blue);
+            let green = Arc::clone(&green);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                while !done.load(Ordering::Relaxed) {
+                    for vault in [&blue, &green] {
+                        let items = vault.get_many(&[key_blue, key_green]);
+                        assert_eq!(1, items.iter().flatten().count());
+                    }
+                }
+            })
+        };
+        let swappers: Vec<_> = (0..2).map(|t| {
+            let blue = Arc::clone(&blue);
+            let green = Arc::clone(&green);
+            thread::spawn(move || {
+                for _ in 0..5000 {
+                    if t == 0 {
+                        blue.swap_contents(&green);
+                    } else {
+                        green.swap_contents(&blue);
+                    }
+                }
+            })
+        }).collect();
+        for swapper in swappers {
+            swapper.join().unwrap();
+        }
+        done.store(true, Ordering::Relaxed);
+        reader.join().unwrap();
+        assert_eq!(Some("blue"), blue.get_clone(&key_blue));
+        assert_eq!(Some("green"), green.get_clone(&key_green));
+    }
+
+    #[test]
+    fn clear_with_disposes_every_item() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..10);
+        let mut disposed = Vec::new();
+        vault.clear_with(|key, item| disposed.push((key, item)));
+        disposed.sort_by_key(|(_, item)| *item);
+        let expected: Vec<_> = keys.into_iter().zip(0..10).collect();
+        assert_eq!(expected, disposed);
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn clear_with_releases_lock_before_disposing() {
+        let vault = Vault::new();
+        vault.add(1);
+        let mut readded = None;
+        vault.clear_with(|_, item| readded = Some(vault.add(item + 1)));
+        let readded = readded.unwrap();
+        assert_eq!(Some(2), vault.get_clone(&readded));
+    }
+
+    #[test]
+    fn clear_with_panicking_disposal() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::new();
+        vault.add_many(0..10);
+        let mut disposed = 0;
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.clear_with(|_, _| {
+                disposed += 1;
+                if disposed == 3 {
+                    panic!("disposal failed");
+                }
+            });
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(3, disposed);
+        assert_eq!(true, vault.is_empty());
+        assert_eq!(0, vault.count_where(|_| true));
+    }
+
+    #[test]
+    fn transfer_moves_item() {
+        let source = Vault::new();
+        let dest = Vault::new();
+        let key = source.add(1);
+        let moved = source.transfer(&key, &dest);
+        assert_eq!(true, moved);
+        assert_eq!(false, source.has_item(&key));
+        assert_eq!(Some(1), dest.get_clone(&key));
+        assert_eq!(0, source.len());
+        assert_eq!(1, dest.len());
+    }
+
+    #[test]
+    fn transfer_missing_key() {
+        let source = Vault::<i32>::new();
+        let dest = Vault::new();
+        let key = VaultKey::new();
+        let moved = source.transfer(&key, &dest);
+        assert_eq!(false, moved);
+        assert_eq!(true, dest.is_empty());
+    }
+
+    #[test]
+    fn transfer_occupied_destination_keeps_both() {
+        let source = Vault::new();
+        let dest = Vault::new();
+        let key = source.add(1);
+        dest.add_with_key(2, &key);
+        let moved = source.transfer(&key, &dest);
+        assert_eq!(false, moved);
+        assert_eq!(Some(1), source.get_clone(&key));
+        assert_eq!(Some(2), dest.get_clone(&key));
+    }
+
+    #[test]
+    fn transfer_to_self() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let moved = vault.transfer(&key, &vault);
+        assert_eq!(false, moved);
+        assert_eq!(Some(1), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn transfer_never_leaves_item_in_neither() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let pending = Arc::new(Vault::new());
+        let active = Arc::new(Vault::new());
+        let keys = pending.add_many(0..2000);
+        let mover = {
+            let pending = Arc::clone(&pending);
+            let active = Arc::clone(&active);
+            let keys = keys.clone();
+            thread::spawn(move || {
+                for key in keys {
+                    pending.transfer(&key, &active);
+                }
+            })
+        };
+        while !mover.is_finished() {
+            for key in &keys {
+                let found = pending.has_item(key) || active.has_item(key);
+                assert_eq!(true, found);
+            }
+        }
+        mover.join().unwrap();
+        assert_eq!(true, pending.is_empty());
+        assert_eq!(2000, active.len());
+    }
+
+    #[test]
+    fn absorb_empty_other() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let skipped = vault.absorb(Vault::new());
+        assert_eq!(true, skipped.is_empty());
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn absorb_keeps_keys() {
+        let vault = Vault::new();
+        let other = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = other.add(2);
+        let key_3 = other.add(3);
+        let skipped = vault.absorb(other);
+        assert_eq!(true, skipped.is_empty());
+        assert_eq!(vec![Some(1), Some(2), Some(3)], vault.get_many(&[key_1, key_2, key_3]));
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn absorb_collision_keeps_existing() {
+        let vault = Vault::new();
+        let other = Vault::new();
+        let shared = vault.add("mine");
+        other.add_with_key("theirs", &shared);
+        let unique = other.add("unique");
+        let skipped = vault.absorb(other);
+        assert_eq!(vec![shared], skipped);
+        assert_eq!(Some("mine"), vault.get_clone(&shared));
+        assert_eq!(Some("unique"), vault.get_clone(&unique));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn split_off_nothing_matches() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let split = vault.split_off(|_, _| false);
+        assert_eq!(true, split.is_empty());
+        assert_eq!(Some(1), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn split_off_everything_matches() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..5);
+        let split = vault.split_off(|_, _| true);
+        assert_eq!(true, vault.is_empty());
+        assert_eq!(5, split.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i), split.get_clone(key));
+        }
+    }
+
+    #[test]
+    fn split_off_mixed() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..6);
+        let split = vault.split_off(|_, i| i % 2 == 1);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(i % 2 == 1, split.has_item(key));
+            assert_eq!(i % 2 == 0, vault.has_item(key));
+        }
+        assert_eq!(3, split.len());
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn diff_identical() {
+        let left = Vault::new();
+        let right = Vault::new();
+        let key = left.add(1);
+        right.add_with_key(1, &key);
+        let diff = left.diff(&right);
+        assert_eq!(VaultDiff::default(), diff);
+    }
+
+    #[test]
+    fn diff_with_self() {
+        let vault = Vault::new();
+        vault.add(1);
+        let diff = vault.diff(&vault);
+        assert_eq!(VaultDiff::default(), diff);
+    }
+
+    #[test]
+    fn diff_mixed() {
+        let left = Vault::new();
+        let right = Vault::new();
+        let same = left.add("same");
+        right.add_with_key("same", &same);
+        let changed = left.add("before");
+        right.add_with_key("after", &changed);
+        let removed = left.add("left only");
+        let added = right.add("right only");
+        let diff = left.diff(&right);
+        assert_eq!(vec![removed], diff.only_left);
+        assert_eq!(vec![added], diff.only_right);
+        assert_eq!(vec![changed], diff.changed);
+        let reverse = right.diff(&left);
+        assert_eq!(vec![added], reverse.only_left);
+        assert_eq!(vec![removed], reverse.only_right);
+        assert_eq!(vec![changed], reverse.changed);
+    }
+
+    #[test]
+    fn from_hash_map() {
+        let key = VaultKey::new();
+        let mut map = HashMap::new();
+        map.insert(key, "stored");
+        let vault = Vault::from(map);
+        assert_eq!(Some("stored"), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn from_entries_keeps_last_duplicate() {
+        let key_1 = VaultKey::new();
+        let key_2 = VaultKey::new();
+        let vault = Vault::from_entries(vec![(key_1, 1), (key_2, 2), (key_1, 3)]);
+        assert_eq!(vec![Some(3), Some(2)], vault.get_many(&[key_1, key_2]));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn from_entries_empty() {
+        let vault = Vault::<i32>::from_entries(Vec::new());
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn into_inner_round_trip() {
+        let keys = [VaultKey::new(), VaultKey::new()];
+        let vault = Vault::from_entries(vec![(keys[0], "a"), (keys[1], "b")]);
+        let map = vault.into_inner();
+        assert_eq!(2, map.len());
+        assert_eq!(Some(&"a"), map.get(&keys[0]));
+        assert_eq!(Some(&"b"), map.get(&keys[1]));
+    }
+
+    #[test]
+    fn into_inner_after_poison() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let poisoner = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                vault.with_item_mut(&key, |_| panic!("poison the vault"));
+            })
+        };
+        assert_eq!(true, poisoner.join().is_err());
+        let vault = Arc::try_unwrap(vault).ok().unwrap();
+        let map = vault.into_inner();
+        assert_eq!(Some(&1), map.get(&key));
+    }
+
+    #[test]
+    fn collect_into_vault() {
+        let vault: Vault<i32> = (0..10).collect();
+        assert_eq!(10, vault.len());
+        assert_eq!(true, vault.contains_value(&9));
+    }
+
+    #[test]
+    fn collect_with_keys_in_order() {
+        use std::collections::HashSet;
+
+        let (vault, keys) = Vault::collect_with_keys(0..100);
+        let unique: HashSet<_> = keys.iter().copied().collect();
+        assert_eq!(100, unique.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i), vault.get_clone(key));
+        }
+    }
+
+    #[test]
+    fn extend_entries_disjoint() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = VaultKey::new();
+        vault.extend_entries(vec![(key_2, 2)]);
+        assert_eq!(vec![Some(1), Some(2)], vault.get_many(&[key_1, key_2]));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn extend_entries_overlapping_overwrites() {
+        let vault = Vault::new();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        let key_3 = VaultKey::new();
+        vault.extend_entries(vec![(key_2, 20), (key_3, 30)]);
+        assert_eq!(vec![Some(1), Some(20), Some(30)], vault.get_many(&[key_1, key_2, key_3]));
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn extend_trait_impls() {
+        let mut vault = Vault::new();
+        let key = VaultKey::new();
+        vault.extend(vec![(key, 1)]);
+        vault.extend(vec![2, 3]);
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn extend_values_returns_keys() {
+        let vault = Vault::new();
+        let keys = vault.extend_values(vec!["a", "b"]);
+        assert_eq!(vec![Some("a"), Some("b")], vault.get_many(&keys));
+    }
+
+    #[test]
+    fn into_iter_yields_every_item() {
+        let (vault, keys) = Vault::collect_with_keys(0..50);
+        let iter = vault.into_iter();
+        assert_eq!((50, Some(50)), iter.size_hint());
+        let mut yielded: Vec<_> = iter.collect();
+        yielded.sort_by_key(|(_, i)| *i);
+        let expected: Vec<_> = keys.into_iter().zip(0..50).collect();
+        assert_eq!(expected, yielded);
+    }
+
+    #[test]
+    fn into_iter_for_loop() {
+        let vault = Vault::new();
+        vault.add(1);
+        vault.add(2);
+        let mut total = 0;
+        for (_, item) in vault {
+            total += item;
+        }
+        assert_eq!(3, total);
+    }
+
+    #[test]
+    fn into_iter_after_poison() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let poisoner = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                vault.with_item_mut(&key, |_| panic!("poison the vault"));
+            })
+        };
+        assert_eq!(true, poisoner.join().is_err());
+        let vault = Arc::try_unwrap(vault).ok().unwrap();
+        let yielded: Vec<_> = vault.into_iter().collect();
+        assert_eq!(vec![(key, 1)], yielded);
+    }
+
+    #[test]
+    fn iter_yields_clones() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..10);
+        let mut yielded: Vec<_> = vault.iter().collect();
+        yielded.sort_by_key(|(_, i)| *i);
+        let expected: Vec<_> = keys.into_iter().zip(0..10).collect();
+        assert_eq!(expected, yielded);
+        assert_eq!(10, vault.len());
+    }
+
+    #[test]
+    fn iter_is_snapshot() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let mut iter = vault.iter();
+        vault.update_all(|_, i| *i = 100);
+        vault.add(2);
+        vault.remove(&key);
+        assert_eq!(Some((key, 1)), iter.next());
+        assert_eq!(None, iter.next());
+    }
+
+    #[test]
+    fn iter_mutating_mid_iteration() {
+        let vault = Vault::new();
+        vault.add_many(0..10);
+        let mut seen = 0;
+        for (key, _) in vault.iter() {
+            vault.remove(&key);
+            vault.add(seen);
+            seen += 1;
+        }
+        assert_eq!(10, seen);
+        assert_eq!(10, vault.len());
+    }
+
+    #[test]
+    fn values_clones() {
+        let vault = Vault::new();
+        vault.add_many(vec![3, 1, 2]);
+        let mut values = vault.values();
+        values.sort();
+        assert_eq!(vec![1, 2, 3], values);
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn take_values_empties() {
+        let vault = Vault::new();
+        let keys = vault.add_many(vec![3, 1, 2]);
+        let mut values = vault.take_values();
+        values.sort();
+        assert_eq!(vec![1, 2, 3], values);
+        assert_eq!(false, vault.has_item(&keys[0]));
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn for_each_visits_every_item() {
+        use std::collections::HashSet;
+
+        let vault = Vault::new();
+        let keys: HashSet<_> = vault.add_many(0..20).into_iter().collect();
+        let mut visited = HashSet::new();
+        let mut total = 0;
+        vault.for_each(|key, i| {
+            visited.insert(*key);
+            total += i;
+        });
+        assert_eq!(keys, visited);
+        assert_eq!(190, total);
+    }
+
+    #[test]
+    fn for_each_empty() {
+        let vault = Vault::<i32>::new();
+        let mut called = false;
+        vault.for_each(|_, _| called = true);
+        assert_eq!(false, called);
+    }
+
+    #[test]
+    fn map_values_keeps_keys() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..5);
+        let mapped = vault.map_values(|_, i| i.to_string());
+        assert_eq!(5, mapped.len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i.to_string()), mapped.get_clone(key));
+            assert_eq!(Some(i), vault.get_clone(key));
+        }
+    }
+
+    #[test]
+    fn map_values_empty() {
+        let vault = Vault::<i32>::new();
+        let mapped = vault.map_values(|_, i| *i);
+        assert_eq!(true, mapped.is_empty());
+    }
+
+    #[test]
+    fn filter_no_matches() {
+        let vault = Vault::new();
+        vault.add_many(0..5);
+        let filtered = vault.filter(|_, _| false);
+        assert_eq!(true, filtered.is_empty());
+        assert_eq!(5, vault.len());
+    }
+
+    #[test]
+    fn filter_all_match() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..5);
+        let filtered = vault.filter(|_, _| true);
+        assert_eq!(VaultDiff::default(), vault.diff(&filtered));
+        assert_eq!(vault.get_many(&keys), filtered.get_many(&keys));
+    }
+
+    #[test]
+    fn filter_leaves_source_unchanged() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..6);
+        let filtered = vault.filter(|_, i| i % 2 == 0);
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(i % 2 == 0, filtered.has_item(key));
+            assert_eq!(Some(i), vault.get_clone(key));
+        }
+        assert_eq!(3, filtered.len());
+        assert_eq!(6, vault.len());
+    }
+
+    #[test]
+    fn retain_mut_ages_and_evicts() {
+        let vault = Vault::new();
+        let keys = vault.add_many(vec![1, 2, 3]);
+        let removed = vault.retain_mut(|_, age| {
+            *age -= 1;
+            *age > 0
+        });
+        assert_eq!(1, removed);
+        assert_eq!(vec![None, Some(1), Some(2)], vault.get_many(&keys));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn retain_mut_drops_removed_once() {
+        use std::sync::Arc;
+
+        struct Tracked {
+            mutated: bool,
+            drops: Arc<AtomicUsize>,
+        }
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                assert_eq!(true, self.mutated);
+                self.drops.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let drops = Arc::new(AtomicUsize::new(0));
+        let vault = Vault::new();
+        let keep = vault.add(Tracked {mutated: false, drops: Arc::clone(&drops)});
+        vault.add(Tracked {mutated: false, drops: Arc::clone(&drops)});
+        let removed = vault.retain_mut(|key, item| {
+            item.mutated = true;
+            *key == keep
+        });
+        assert_eq!(1, removed);
+        assert_eq!(1, drops.load(Ordering::SeqCst));
+        drop(vault);
+        assert_eq!(2, drops.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn mixed_operations_from_many_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let workers: Vec<_> = (0..8).map(|t| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                let mut kept = Vec::new();
+                for i in 0..2000 {
+                    let key = vault.add(t * 2000 + i);
+                    assert_eq!(true, vault.has_item(&key));
+                    if i % 4 == 0 {
+                        kept.push(key);
+                    } else {
+                        assert_eq!(Some(t * 2000 + i), vault.remove(&key));
+                        assert_eq!(false, vault.has_item(&key));
+                    }
+                }
+                kept
+            })
+        }).collect();
+        let kept: Vec<_> = workers.into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+        assert_eq!(8 * 500, kept.len());
+        assert_eq!(kept.len(), vault.len());
+        for key in &kept {
+            assert_eq!(true, vault.has_item(key));
+        }
+    }
+
+    // Returns two fresh keys that land in different shards of the vault.
+    fn keys_in_different_shards<T>(vault: &Vault<T>) -> (VaultKey, VaultKey) {
+        let a = VaultKey::new();
+        loop {
+            let b = VaultKey::new();
+            if shard::index(&a, vault.shards.len()) != shard::index(&b, vault.shards.len()) {
+                return (a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn with_shards_holds_every_item() {
+        let vault = Vault::with_shards(4);
+        let keys = vault.add_many(0..100);
+        assert_eq!(100, vault.len());
+        assert_eq!(100, vault.keys().len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i), vault.get_clone(key));
+        }
+        vault.retain(|_, i| i % 2 == 0);
+        assert_eq!(50, vault.len());
+        assert_eq!(50, vault.into_iter().len());
+    }
+
+    #[test]
+    fn with_shards_zero_is_one() {
+        let vault = Vault::with_shards(0);
+        let key = vault.add(1);
+        assert_eq!(1, vault.shards.len());
+        assert_eq!(Some(1), vault.remove(&key));
+    }
+
+    #[test]
+    fn other_shards_dont_wait() {
+        let vault = Vault::with_shards(2);
+        let (a, b) = keys_in_different_shards(&vault);
+        vault.add_with_key(1, &a);
+        vault.add_with_key(2, &b);
+        let entry = vault.entry(&a);
+        assert_eq!(Ok(true), vault.try_has_item(&b));
+        assert_eq!(Ok(2), vault.try_remove(&b));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_has_item(&a));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_clear());
+        drop(entry);
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn swap_contents_different_shard_counts() {
+        let vault_1 = Vault::with_shards(1);
+        let vault_2 = Vault::with_shards(3);
+        let keys_1 = vault_1.add_many(0..10);
+        let keys_2 = vault_2.add_many(10..15);
+        vault_1.swap_contents(&vault_2);
+        assert_eq!(5, vault_1.len());
+        assert_eq!(10, vault_2.len());
+        for (i, key) in keys_1.iter().enumerate() {
+            assert_eq!(Some(i), vault_2.get_clone(key));
+        }
+        for (i, key) in keys_2.iter().enumerate() {
+            assert_eq!(Some(i + 10), vault_1.get_clone(key));
+        }
+    }
+
+    // Has each of the threads update the whole vault over and over for a
+    // while, and returns how many updates each of them got in.
+    fn contended_counts(vault: Vault<usize>, threads: usize) -> Vec<usize> {
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let vault = Arc::new(vault);
+        for _ in 0..16 {
+            vault.add(0);
+        }
+        let start = Arc::new(Barrier::new(threads));
+        let workers: Vec<_> = (0..threads).map(|_| {
+            let vault = Arc::clone(&vault);
+            let start = Arc::clone(&start);
+            thread::spawn(move || {
+                start.wait();
+                let until = Instant::now() + Duration::from_millis(300);
+                let mut count = 0;
+                while Instant::now() < until {
+                    vault.update_all(|_, i| *i += 1);
+                    count += 1;
+                }
+                count
+            })
+        }).collect();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    }
+
+    #[test]
+    fn fair_vault_serves_threads_evenly() {
+        let counts = contended_counts(Vault::new_fair(), 4);
+        let fewest = *counts.iter().min().unwrap();
+        let most = *counts.iter().max().unwrap();
+        assert_eq!(true, fewest > 0, "{:?}", counts);
+        assert_eq!(true, most <= fewest * 3, "{:?}", counts);
+
+        // the usual lock makes no promise about the spread, only that the
+        // updates are made
+        let counts = contended_counts(Vault::new(), 4);
+        assert_eq!(true, counts.iter().sum::<usize>() > 0, "{:?}", counts);
+    }
+
+    #[test]
+    fn sharded_stress_from_many_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::with_shards(8));
+        let workers: Vec<_> = (0..16).map(|t| {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                let mut kept = Vec::new();
+                for i in 0..1000 {
+                    let key = vault.add(t * 1000 + i);
+                    assert_eq!(true, vault.update_item(&key, |item| *item += 1));
+                    match i % 4 {
+                        0 => kept.push(key),
+                        1 => assert_eq!(Some(t * 1000 + i + 1), vault.remove(&key)),
+                        2 => assert_eq!(1, vault.retain_mut(|k, _| *k != key)),
+                        _ => assert_eq!(true, vault.entry(&key).remove().is_some()),
+                    }
+                }
+                kept
+            })
+        }).collect();
+        let kept: Vec<_> = workers.into_iter()
+            .flat_map(|worker| worker.join().unwrap())
+            .collect();
+        assert_eq!(16 * 250, kept.len());
+        assert_eq!(kept.len(), vault.len());
+        assert_eq!(kept.len(), vault.keys().len());
+        for key in &kept {
+            assert_eq!(true, vault.has_item(key));
+        }
+    }
+
+    #[test]
+    fn slow_update_doesnt_block_other_keys() {
+        use std::sync::{Arc, mpsc};
+        use std::thread;
+
+        // one shard, so that only the per-item locks keep the keys apart
+        let vault = Arc::new(Vault::with_shards(1));
+        let slow = vault.add(1);
+        let other = vault.add(2);
+        let (started_send, started) = mpsc::channel();
+        let (finish, finish_recv) = mpsc::channel::<()>();
+        let updater = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                vault.update_item(&slow, |i| {
+                    started_send.send(()).unwrap();
+                    finish_recv.recv().unwrap();
+                    *i += 10;
+                })
+            })
+        };
+        started.recv().unwrap();
+        assert_eq!(Ok(true), vault.try_has_item(&other));
+        assert_eq!(Ok(()), vault.try_update(&other, |i| *i += 1));
+        assert_eq!(Ok(3), vault.try_remove(&other));
+        let added = vault.try_add(4).unwrap();
+        assert_eq!(Some(4), vault.get_clone(&added));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_update(&slow, |_| ()));
+        finish.send(()).unwrap();
+        assert_eq!(true, updater.join().unwrap());
+        assert_eq!(Some(11), vault.get_clone(&slow));
+    }
+
+    #[test]
+    fn remove_waits_for_update() {
+        use std::sync::{Arc, mpsc};
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let (started_send, started) = mpsc::channel();
+        let (finish, finish_recv) = mpsc::channel::<()>();
+        let updater = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                vault.update_item(&key, |i| {
+                    started_send.send(()).unwrap();
+                    finish_recv.recv().unwrap();
+                    *i += 10;
+                })
+            })
+        };
+        started.recv().unwrap();
+        let remover = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || vault.remove(&key))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(false, remover.is_finished());
+        finish.send(()).unwrap();
+        assert_eq!(true, updater.join().unwrap());
+        assert_eq!(Some(11), remover.join().unwrap());
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(0, vault.len());
+    }
+
+    #[test]
+    fn update_after_remove() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        vault.remove(&key);
+        assert_eq!(false, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(None, vault.with_item_mut(&key, |i| *i));
+    }
+
+    #[test]
+    fn lock_compound_operations() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let moved = VaultKey::new();
+        {
+            let mut guard = vault.lock();
+            if guard.has_item(&key) {
+                let item = guard.remove(&key).unwrap();
+                assert_eq!(true, guard.add_with_key(item, &moved));
+            }
+            assert_eq!(true, guard.update_item(&moved, |i| *i += 1));
+            assert_eq!(false, guard.update_item(&key, |i| *i += 1));
+            guard.add(5);
+        }
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(Some(2), vault.get_clone(&moved));
+        assert_eq!(2, vault.len());
+        vault.lock().clear();
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn lock_holds_off_other_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let keys = vault.add_many(vec![0; 2]);
+        let mover = {
+            let vault = Arc::clone(&vault);
+            let keys = keys.clone();
+            thread::spawn(move || {
+                // moves one unit back and forth between the two keys, so the
+                // total is 1 after every locked step
+                vault.update_item(&keys[0], |i| *i += 1);
+                for round in 0..2000 {
+                    let (from, to) = if round % 2 == 0 { (keys[0], keys[1]) } else { (keys[1], keys[0]) };
+                    let mut guard = vault.lock();
+                    guard.update_item(&from, |i| *i -= 1);
+                    guard.update_item(&to, |i| *i += 1);
+                }
+            })
+        };
+        while !mover.is_finished() {
+            let items = vault.get_many(&keys);
+            let total: i32 = items.iter().flatten().sum();
+            assert_eq!(true, total == 0 || total == 1);
+            if total == 1 {
+                assert_eq!(1, items.iter().flatten().filter(|i| **i == 1).count());
+            }
+        }
+        mover.join().unwrap();
+        assert_eq!(vec![Some(1), Some(0)], vault.get_many(&keys));
+    }
+
+    #[test]
+    fn lock_update_panic_keeps_item() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.lock().update_item(&key, |_| panic!("failed update"));
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn atomically_reads_see_own_writes() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let (before, after, added, gone) = vault.atomically(|txn| {
+            let before = txn.get_clone(&key);
+            txn.update_item(&key, |i| *i += 1);
+            let after = txn.get_clone(&key);
+            let added = txn.add(7);
+            let added = txn.get_clone(&added);
+            txn.remove(&key);
+            (before, after, added, txn.has_item(&key))
+        });
+        assert_eq!(Some(1), before);
+        assert_eq!(Some(2), after);
+        assert_eq!(Some(7), added);
+        assert_eq!(false, gone);
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn try_atomically_rolls_back_on_error() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let removed = vault.add(2);
+        let result: Result<(), &str> = vault.try_atomically(|txn| {
+            txn.update_item(&key, |i| *i += 100);
+            txn.remove(&removed);
+            txn.add(3);
+            Err("abort")
+        });
+        assert_eq!(Err("abort"), result);
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(Some(2), vault.get_clone(&removed));
+        assert_eq!(2, vault.len());
+
+        let result: Result<(), &str> = vault.try_atomically(|txn| {
+            txn.update_item(&key, |i| *i += 100);
+            Ok(())
+        });
+        assert_eq!(Ok(()), result);
+        assert_eq!(Some(101), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn atomically_rolls_back_on_panic() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.atomically(|txn| {
+                txn.update_item(&key, |i| *i += 1);
+                txn.add(2);
+                panic!("failed transaction");
+            })
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+        assert_eq!(false, vault.is_poisoned());
+    }
+
+    #[test]
+    fn with_items_duplicate_keys() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let other = vault.add(2);
+        let seen = vault.with_items(&[other, key, other, key], |items| {
+            items.iter().map(|item| item.as_ref().map(|i| **i)).collect::<Vec<_>>()
+        });
+        assert_eq!(vec![Some(2), Some(1), None, None], seen);
+    }
+
+    #[test]
+    fn with_items_transfers_keep_total() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let keys = Arc::new(vault.add_many(vec![100; 4]));
+        let movers = (0..4)
+            .map(|thread| {
+                let vault = Arc::clone(&vault);
+                let keys = Arc::clone(&keys);
+                thread::spawn(move || {
+                    for round in 0..500 {
+                        let from = keys[(thread + round) % 4];
+                        let to = keys[(thread + round + 1 + round % 3) % 4];
+                        vault.with_items(&[from, to], |items| {
+                            if let [Some(from), Some(to)] = items {
+                                **from -= 1;
+                                **to += 1;
+                            }
+                        });
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        while movers.iter().any(|mover| !mover.is_finished()) {
+            let total = vault.with_items(&keys, |items| items.iter().flatten().map(|i| **i).sum::<i32>());
+            assert_eq!(400, total);
+        }
+        for mover in movers {
+            mover.join().unwrap();
+        }
+        assert_eq!(400, vault.values().iter().sum::<i32>());
+    }
+
+    #[test]
+    fn readers_proceed_in_parallel() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicBool;
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        const READERS: usize = 8;
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(0);
+        let inside = Arc::new(AtomicUsize::new(0));
+        let all_inside = Arc::new(AtomicBool::new(false));
+        let readers: Vec<_> = (0..READERS).map(|_| {
+            let vault = Arc::clone(&vault);
+            let inside = Arc::clone(&inside);
+            let all_inside = Arc::clone(&all_inside);
+            thread::spawn(move || {
+                // every reader waits inside with_item until all of them are
+                // in there together, which a vault serializing its readers
+                // would never allow
+                vault.with_item(&key, |_| {
+                    inside.fetch_add(1, Ordering::SeqCst);
+                    let deadline = Instant::now() + Duration::from_secs(10);
+                    while inside.load(Ordering::SeqCst) < READERS && Instant::now() < deadline {
+                        thread::yield_now();
+                    }
+                    if inside.load(Ordering::SeqCst) == READERS {
+                        all_inside.store(true, Ordering::SeqCst);
+                    }
+                });
+                for _ in 0..1000 {
+                    assert_eq!(true, vault.has_item(&key));
+                }
+            })
+        }).collect();
+        let writer = {
+            let vault = Arc::clone(&vault);
+            let inside = Arc::clone(&inside);
+            thread::spawn(move || {
+                // a waiting writer may hold back new readers, so only start
+                // writing once they have met
+                while inside.load(Ordering::SeqCst) < READERS {
+                    thread::yield_now();
+                }
+                (0..1000).map(|i| vault.add(i)).collect::<Vec<_>>()
+            })
+        };
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        let added = writer.join().unwrap();
+        assert_eq!(true, all_inside.load(Ordering::SeqCst));
+        assert_eq!(1001, vault.len());
+        for key in &added {
+            assert_eq!(true, vault.has_item(key));
+        }
+    }
+
+    #[test]
+    fn usable_after_panic_in_update() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let other = vault.add(2);
+        let poisoner = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                vault.update_item(&key, |_| panic!("poison the vault"));
+            })
+        };
+        assert_eq!(true, poisoner.join().is_err());
+        // the update's panic is caught and passed on without any lock being
+        // held, so nothing is left poisoned
+        assert_eq!(false, vault.is_poisoned());
+
+        let user = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                let added = vault.add(3);
+                assert_eq!(Some(3), vault.remove(&added));
+                assert_eq!(Some(2), vault.remove(&other));
+            })
+        };
+        user.join().unwrap();
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn reentrant_calls_panic() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        fn panic_message(payload: Box<dyn std::any::Any + Send>) -> String {
+            payload.downcast_ref::<String>().cloned().unwrap_or_default()
+        }
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let other = vault.add(2);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.update_item(&key, |_| {
+                vault.remove(&key);
+            });
+        }));
+        let message = panic_message(result.unwrap_err());
+        assert_eq!(true, message.contains("`Vault::update_item`"), "{}", message);
+        assert_eq!(true, message.contains("deadlock"), "{}", message);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.for_each(|_, _| {
+                vault.add(3);
+            });
+        }));
+        assert_eq!(true, panic_message(result.unwrap_err()).contains("`Vault::for_each`"));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _guard = vault.lock();
+            vault.has_item(&key);
+        }));
+        assert_eq!(true, panic_message(result.unwrap_err()).contains("`Vault::lock`"));
+
+        // other keys are free while an item is updated, and the vault works
+        // as usual once the panicking calls have unwound
+        assert_eq!(true, vault.update_item(&key, |i| *i += vault.get_clone(&other).unwrap()));
+        assert_eq!(Some(3), vault.get_clone(&key));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn update_never_hides_item() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(0);
+        let done = Arc::new(AtomicBool::new(false));
+        let poller = {
+            let vault = Arc::clone(&vault);
+            let done = Arc::clone(&done);
+            thread::spawn(move || {
+                let mut polls = 0;
+                loop {
+                    assert_eq!(true, vault.has_item(&key));
+                    polls += 1;
+                    if done.load(Ordering::SeqCst) {
+                        break polls;
+                    }
+                }
+            })
+        };
+        for _ in 0..10_000 {
+            assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        }
+        done.store(true, Ordering::SeqCst);
+        assert_eq!(true, poller.join().unwrap() > 0);
+        assert_eq!(Some(10_000), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn panicking_update_keeps_item() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.update_item(&key, |_| panic!("failed update"));
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(true, vault.has_item(&key));
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(false, vault.is_poisoned());
+
+        // a panic partway through keeps the changes made before it
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.update_item(&key, |i| {
+                *i += 1;
+                panic!("failed update");
+            });
+        }));
+        assert_eq!(true, result.is_err());
+        assert_eq!(Some(2), vault.get_clone(&key));
+        assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(Some(3), vault.get_clone(&key));
+    }
+
+    #[test]
+    #[cfg(not(feature = "parking_lot"))]
+    fn clear_poison() {
+        let (vault, key) = poisoned_vault();
+        assert_eq!(true, vault.is_poisoned());
+        assert_eq!(true, vault.has_item(&key));
+        assert_eq!(Err(VaultError::Poisoned), vault.try_has_item(&key));
+        vault.clear_poison();
+        assert_eq!(false, vault.is_poisoned());
+        assert_eq!(Ok(true), vault.try_has_item(&key));
+    }
+
+    fn poisoned_vault() -> (Vault<i32>, VaultKey) {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let vault = Vault::with_shards(1);
+        let key = vault.add(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            vault.update_all(|_, _| panic!("poison the vault"));
+        }));
+        assert_eq!(true, result.is_err());
+        (vault, key)
+    }
+
+    #[test]
+    fn try_methods_succeed() {
+        let vault = Vault::new();
+        let key = vault.try_add(1).unwrap();
+        assert_eq!(Ok(true), vault.try_has_item(&key));
+        assert_eq!(Ok(()), vault.try_update(&key, |i| *i += 1));
+        assert_eq!(Ok(2), vault.try_remove(&key));
+        vault.try_add(3).unwrap();
+        assert_eq!(Ok(()), vault.try_clear());
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn try_methods_key_not_found() {
+        let vault = Vault::<i32>::new();
+        let key = VaultKey::new();
+        assert_eq!(Err(VaultError::KeyNotFound), vault.try_remove(&key));
+        assert_eq!(Err(VaultError::KeyNotFound), vault.try_update(&key, |_| ()));
+        assert_eq!(Ok(false), vault.try_has_item(&key));
+    }
+
+    #[test]
+    fn try_methods_would_block() {
+        // one shard, so that the entry blocks every key
+        let vault = Vault::with_shards(1);
+        let key = vault.add(1);
+        let entry = vault.entry(&key);
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_add(2));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_remove(&key));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_has_item(&key));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_update(&key, |_| ()));
+        assert_eq!(Err(VaultError::WouldBlock), vault.try_clear());
+        drop(entry);
+        assert_eq!(Ok(true), vault.try_has_item(&key));
+    }
+
+    #[test]
+    #[cfg(not(feature = "parking_lot"))]
+    fn try_methods_poisoned() {
+        let (vault, key) = poisoned_vault();
+        assert_eq!(Err(VaultError::Poisoned), vault.try_add(2));
+        assert_eq!(Err(VaultError::Poisoned), vault.try_remove(&key));
+        assert_eq!(Err(VaultError::Poisoned), vault.try_has_item(&key));
+        assert_eq!(Err(VaultError::Poisoned), vault.try_update(&key, |_| ()));
+        assert_eq!(Err(VaultError::Poisoned), vault.try_clear());
+    }
+
+    #[test]
+    fn try_has_item_matches_has_item() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        let (owner, reader) = vault.add_with_roles(2);
+        let alias = vault.add_alias(&owner.untyped()).unwrap();
+        for key in [first, second, owner.untyped(), reader.untyped(), alias, VaultKey::new()] {
+            assert_eq!(Ok(vault.has_item(&key)), vault.try_has_item(&key));
+        }
+        assert_eq!(Ok(true), vault.try_has_item(&second));
+        assert_eq!(Ok(true), vault.try_has_item(&reader.untyped()));
+        assert_eq!(Some(2), vault.remove(&owner.untyped()));
+        assert_eq!(Ok(false), vault.try_has_item(&reader.untyped()));
+        assert_eq!(Ok(false), vault.try_has_item(&alias));
+    }
+
+    #[test]
+    #[cfg(feature = "parking_lot")]
+    fn panic_never_poisons() {
+        let (vault, key) = poisoned_vault();
+        assert_eq!(false, vault.is_poisoned());
+        assert_eq!(Ok(true), vault.try_has_item(&key));
+        assert_eq!(Ok(1), vault.try_remove(&key));
+    }
+
+    #[test]
+    fn vault_error_display() {
+        let message = VaultError::KeyNotFound.to_string();
+        assert_eq!("no item with the requested key", message);
+    }
+
+    // Holds the whole vault locked on another thread until the returned
+    // sender is used or dropped.
+    fn hold_vault(vault: &std::sync::Arc<Vault<i32>>) -> (std::sync::mpsc::Sender<()>, std::thread::JoinHandle<()>) {
+        use std::sync::{Arc, mpsc};
+        use std::thread;
+
+        let (locked_send, locked) = mpsc::channel();
+        let (release, release_recv) = mpsc::channel::<()>();
+        let vault = Arc::clone(vault);
+        let holder = thread::spawn(move || {
+            let _guard = vault.lock();
+            locked_send.send(()).unwrap();
+            let _ = release_recv.recv();
+        });
+        locked.recv().unwrap();
+        (release, holder)
+    }
+
+    #[test]
+    fn take_blocking_waits_for_producer() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let keys = [VaultKey::new(), VaultKey::new(), VaultKey::new()];
+        let consumers = keys.iter()
+            .map(|key| {
+                let vault = Arc::clone(&vault);
+                let key = *key;
+                thread::spawn(move || vault.take_blocking(&key, Some(Duration::from_secs(10))))
+            })
+            .collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(50));
+        for (i, key) in keys.iter().enumerate().rev() {
+            vault.add(usize::MAX);
+            assert_eq!(true, vault.add_with_key(i, key));
+            thread::sleep(Duration::from_millis(10));
+        }
+        let taken = consumers.into_iter().map(|consumer| consumer.join().unwrap()).collect::<Vec<_>>();
+        assert_eq!(vec![Some(0), Some(1), Some(2)], taken);
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn take_blocking_times_out() {
+        use std::time::{Duration, Instant};
+
+        let vault = Vault::<i32>::new();
+        let key = VaultKey::new();
+        vault.add(1);
+        let start = Instant::now();
+        assert_eq!(None, vault.take_blocking(&key, Some(Duration::from_millis(100))));
+        assert_eq!(true, start.elapsed() >= Duration::from_millis(100));
+        vault.add_with_key(2, &key);
+        assert_eq!(Some(2), vault.take_blocking(&key, Some(Duration::ZERO)));
+    }
+
+    #[test]
+    fn take_blocking_keeps_waiting_after_lost_race() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = VaultKey::new();
+        let consumer = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || vault.take_blocking(&key, Some(Duration::from_secs(10))))
+        };
+        thread::sleep(Duration::from_millis(50));
+        // added and taken again under one lock, so the consumer can't win it
+        {
+            let mut guard = vault.lock();
+            guard.add_with_key(1, &key);
+            guard.remove(&key);
+        }
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(false, consumer.is_finished());
+        vault.add_with_key(2, &key);
+        assert_eq!(Some(2), consumer.join().unwrap());
+    }
+
+    #[test]
+    fn wait_for_item_already_present() {
+        use std::time::{Duration, Instant};
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let start = Instant::now();
+        assert_eq!(true, vault.wait_for_item(&key, Duration::from_secs(10)));
+        assert_eq!(true, start.elapsed() < Duration::from_secs(5));
+        assert_eq!(Some(1), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn wait_for_item_delayed_insert() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = VaultKey::new();
+        let waiters = (0..3)
+            .map(|_| {
+                let vault = Arc::clone(&vault);
+                thread::spawn(move || vault.wait_for_item(&key, Duration::from_secs(10)))
+            })
+            .collect::<Vec<_>>();
+        thread::sleep(Duration::from_millis(50));
+        vault.add(0);
+        vault.add_with_key(1, &key);
+        for waiter in waiters {
+            assert_eq!(true, waiter.join().unwrap());
+        }
+        assert_eq!(Some(1), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn wait_for_item_times_out() {
+        use std::time::{Duration, Instant};
+
+        let vault = Vault::<i32>::new();
+        vault.add(1);
+        let start = Instant::now();
+        assert_eq!(false, vault.wait_for_item(&VaultKey::new(), Duration::from_millis(100)));
+        let waited = start.elapsed();
+        assert_eq!(true, waited >= Duration::from_millis(100));
+        assert_eq!(true, waited < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn watch_item_sees_try_clear() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let watcher = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || vault.watch_item(&key, Duration::from_secs(10)))
+        };
+        thread::sleep(Duration::from_millis(50));
+        vault.try_clear().unwrap();
+        assert_eq!(WatchResult::Removed, watcher.join().unwrap());
+    }
+
+    #[test]
+    fn watch_item_returns_latest_change() {
+        use std::sync::{Arc, mpsc};
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let (ready_send, ready) = mpsc::channel();
+        let watcher = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                ready_send.send(()).unwrap();
+                vault.watch_item(&key, Duration::from_secs(10))
+            })
+        };
+        ready.recv().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        // the watcher wakes at the first change, but can't look at the item
+        // until the guard is dropped
+        {
+            let mut guard = vault.lock();
+            guard.update_item(&key, |i| *i += 1);
+            guard.update_item(&key, |i| *i *= 10);
+            thread::sleep(Duration::from_millis(20));
+            guard.update_item(&key, |i| *i += 1);
+        }
+        assert_eq!(WatchResult::Changed(21), watcher.join().unwrap());
+    }
+
+    #[test]
+    fn watch_item_sees_removal_and_addition() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let watch = |vault: &Arc<Vault<i32>>| {
+            let vault = Arc::clone(vault);
+            thread::spawn(move || vault.watch_item(&key, Duration::from_secs(10)))
+        };
+
+        let watcher = watch(&vault);
+        thread::sleep(Duration::from_millis(50));
+        vault.update_item(&VaultKey::new(), |i| *i += 1);
+        vault.add(5);
+        vault.remove(&key);
+        assert_eq!(WatchResult::Removed, watcher.join().unwrap());
+
+        let watcher = watch(&vault);
+        thread::sleep(Duration::from_millis(50));
+        vault.add_with_key(3, &key);
+        assert_eq!(WatchResult::Changed(3), watcher.join().unwrap());
+    }
+
+    #[test]
+    fn watch_item_times_out() {
+        use std::time::{Duration, Instant};
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let start = Instant::now();
+        assert_eq!(WatchResult::TimedOut, vault.watch_item(&key, Duration::from_millis(100)));
+        assert_eq!(true, start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_vault_mirrors_sync_semantics() {
+        let vault = AsyncVault::new();
+        let key = vault.add(1).await;
+        let given = VaultKey::new();
+        assert_eq!(true, vault.add_with_key(2, &given).await);
+        assert_eq!(false, vault.add_with_key(3, &given).await);
+        assert_eq!(true, vault.update_item(&key, |i| *i += 10).await);
+        assert_eq!(false, vault.update_item(&VaultKey::new(), |i| *i += 10).await);
+        assert_eq!(Some(11), vault.remove(&key).await);
+        assert_eq!(false, vault.has_item(&key).await);
+        assert_eq!(true, vault.has_item(&given).await);
+        vault.clear().await;
+        assert_eq!(false, vault.has_item(&given).await);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn async_vault_from_many_tasks() {
+        use std::sync::Arc;
+
+        let vault = Arc::new(AsyncVault::new());
+        let key = vault.add(0).await;
+        let tasks = (0..16)
+            .map(|_| {
+                let vault = Arc::clone(&vault);
+                tokio::spawn(async move {
+                    for _ in 0..100 {
+                        vault.update_item(&key, |i| *i += 1).await;
+                        tokio::task::yield_now().await;
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        assert_eq!(Some(1600), vault.remove(&key).await);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_vault_update_panic_keeps_item() {
+        use std::sync::Arc;
+
+        let vault = Arc::new(AsyncVault::new());
+        let key = vault.add(1).await;
+        let panicking = {
+            let vault = Arc::clone(&vault);
+            tokio::spawn(async move {
+                vault.update_item(&key, |_| panic!("failed update")).await;
+            })
+        };
+        assert_eq!(true, panicking.await.is_err());
+        assert_eq!(true, vault.has_item(&key).await);
+        assert_eq!(false, vault.add_with_key(2, &key).await);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(start_paused = true)]
+    async fn async_wait_for_item_resolves_on_insert() {
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let vault = Arc::new(AsyncVault::new());
+        let key = VaultKey::new();
+        let waiter = {
+            let vault = Arc::clone(&vault);
+            tokio::spawn(async move { vault.wait_for_item(&key).await })
+        };
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        assert_eq!(false, waiter.is_finished());
+        vault.add(0).await;
+        assert_eq!(true, vault.add_with_key(1, &key).await);
+        assert_eq!(1, waiter.await.unwrap());
+        assert_eq!(false, vault.has_item(&key).await);
+        assert_eq!(0, vault.waiting_keys());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(start_paused = true)]
+    async fn async_wait_for_item_times_out() {
+        use std::time::Duration;
+
+        let vault = AsyncVault::<i32>::new();
+        let key = VaultKey::new();
+        let start = tokio::time::Instant::now();
+        assert_eq!(None, vault.wait_for_item_timeout(&key, Duration::from_secs(30)).await);
+        assert_eq!(Duration::from_secs(30), start.elapsed());
+        assert_eq!(0, vault.waiting_keys());
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test(start_paused = true)]
+    async fn async_wait_for_item_cancelled() {
+        use std::time::Duration;
+
+        let vault = AsyncVault::new();
+        let key = VaultKey::new();
+        tokio::select! {
+            _ = vault.wait_for_item(&key) => panic!("nothing was added"),
+            _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+        }
+        assert_eq!(0, vault.waiting_keys());
+        assert_eq!(true, vault.add_with_key(1, &key).await);
+        assert_eq!(Some(1), vault.remove(&key).await);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_subscribe_event_sequence() {
+        use tokio_stream::StreamExt;
+
+        let vault = AsyncVault::new();
+        let mut events = Box::pin(vault.subscribe());
+        let first = vault.add(1).await;
+        let second = VaultKey::new();
+        vault.add_with_key(2, &second).await;
+        vault.add_with_key(3, &second).await;
+        vault.update_item(&first, |i| *i += 1).await;
+        vault.update_item(&VaultKey::new(), |i| *i += 1).await;
+        vault.remove(&first).await;
+        vault.remove(&first).await;
+        vault.clear().await;
+
+        let expected = vec![
+            VaultEvent::Added(first),
+            VaultEvent::Added(second),
+            VaultEvent::Updated(first),
+            VaultEvent::Removed(first),
+            VaultEvent::Cleared,
+        ];
+        let mut seen = Vec::new();
+        for _ in 0..expected.len() {
+            seen.push(events.next().await.unwrap());
+        }
+        assert_eq!(expected, seen);
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn async_subscribe_lagging_and_unsubscribe() {
+        use tokio_stream::StreamExt;
+
+        let vault = AsyncVault::new();
+        let mut events = Box::pin(vault.subscribe());
+        let keys = {
+            let mut keys = Vec::new();
+            for i in 0..300 {
+                keys.push(vault.add(i).await);
+            }
+            keys
+        };
+        assert_eq!(Some(VaultEvent::Lagged(44)), events.next().await);
+        assert_eq!(Some(VaultEvent::Added(keys[44])), events.next().await);
+        assert_eq!(1, vault.subscribers());
+        drop(events);
+        assert_eq!(0, vault.subscribers());
+        vault.clear().await;
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_for_each_runs_in_parallel() {
+        use std::collections::HashSet;
+        use std::sync::Mutex;
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Vault::new();
+        vault.add_many(0..64);
+        let threads = Mutex::new(HashSet::new());
+        let total = Mutex::new(0);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        pool.install(|| vault.par_for_each(|_, i| {
+            threads.lock().unwrap().insert(thread::current().id());
+            *total.lock().unwrap() += i;
+            // the pass may call back into the vault, which isn't locked
+            assert_eq!(64, vault.len());
+            thread::sleep(Duration::from_millis(5));
+        }));
+        assert_eq!((0..64).sum::<i32>(), total.into_inner().unwrap());
+        assert_eq!(true, threads.into_inner().unwrap().len() > 1);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_values_map_matches_sequential() {
+        let vault = Vault::with_shards(4);
+        vault.add_many(0..1000);
+        let mut parallel = vault.par_values_map(|i| i * 2);
+        parallel.sort();
+        let mut sequential = vault.map_values(|_, i| i * 2).values();
+        sequential.sort();
+        assert_eq!(sequential, parallel);
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn par_bulk_mutation_matches_sequential() {
+        let entries = (0..10_000).map(|i| (VaultKey::new(), i)).collect::<Vec<_>>();
+        let sequential = Vault::with_shards(4);
+        sequential.extend_entries(entries.clone());
+        let parallel = Vault::with_shards(4);
+        parallel.extend_entries(entries);
+
+        let updated = sequential.update_all(|_, i| *i = *i * 3 + 1);
+        assert_eq!(updated, parallel.par_update_all(|_, i| *i = *i * 3 + 1));
+        sequential.retain(|_, i| i % 7 != 0);
+        parallel.par_retain(|_, i| i % 7 != 0);
+        assert_eq!(sequential.len(), parallel.len());
+        assert_eq!(sequential.into_inner(), parallel.into_inner());
+    }
+
+    #[test]
+    fn timeout_methods_time_out() {
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let (release, holder) = hold_vault(&vault);
+        let timeout = Duration::from_millis(100);
+
+        let start = Instant::now();
+        assert_eq!(Err(VaultError::Timeout), vault.remove_timeout(&key, timeout));
+        let waited = start.elapsed();
+        assert_eq!(true, waited >= timeout);
+        assert_eq!(true, waited < Duration::from_secs(2));
+        assert_eq!(Err(VaultError::Timeout), vault.add_timeout(2, timeout));
+        let mut called = false;
+        assert_eq!(Err(VaultError::Timeout), vault.update_timeout(&key, |i| { called = true; *i += 1 }, timeout));
+        assert_eq!(false, called);
+
+        release.send(()).unwrap();
+        holder.join().unwrap();
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn timeout_methods_wait_for_release() {
+        use std::sync::Arc;
+        use std::thread;
+        use std::time::{Duration, Instant};
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let (release, holder) = hold_vault(&vault);
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            drop(release);
+        });
+
+        let start = Instant::now();
+        assert_eq!(Ok(true), vault.update_timeout(&key, |i| *i += 1, Duration::from_secs(10)));
+        assert_eq!(true, start.elapsed() < Duration::from_secs(5));
+        assert_eq!(Ok(Some(2)), vault.remove_timeout(&key, Duration::from_secs(10)));
+        assert_eq!(Ok(None), vault.remove_timeout(&key, Duration::from_secs(10)));
+        assert_eq!(Ok(false), vault.update_timeout(&key, |_| (), Duration::from_secs(10)));
+        releaser.join().unwrap();
+        holder.join().unwrap();
+    }
+
+    #[test]
+    fn update_timeout_waits_on_item() {
+        use std::sync::{Arc, mpsc};
+        use std::thread;
+        use std::time::Duration;
+
+        let vault = Arc::new(Vault::new());
+        let key = vault.add(1);
+        let (started_send, started) = mpsc::channel();
+        let (finish, finish_recv) = mpsc::channel::<()>();
+        let updater = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || {
+                vault.update_item(&key, |i| {
+                    started_send.send(()).unwrap();
+                    finish_recv.recv().unwrap();
+                    *i += 10;
+                })
+            })
+        };
+        started.recv().unwrap();
+        assert_eq!(Err(VaultError::Timeout), vault.update_timeout(&key, |i| *i += 1, Duration::from_millis(50)));
+        assert_eq!(Err(VaultError::Timeout), vault.remove_timeout(&key, Duration::from_millis(50)));
+        finish.send(()).unwrap();
+        assert_eq!(true, updater.join().unwrap());
+        assert_eq!(Ok(true), vault.update_timeout(&key, |i| *i += 1, Duration::from_millis(50)));
+        assert_eq!(Some(12), vault.get_clone(&key));
+    }
+
+    #[test]
+    #[cfg(not(feature = "compact-keys"))]
+    fn key_from_known_uuid() {
+        let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let vault = Vault::new();
+        assert_eq!(true, vault.add_with_key(1, &VaultKey::from_uuid(id)));
+
+        let key: VaultKey = id.into();
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(id, key.as_uuid());
+        assert_eq!(id, Uuid::from(key));
+        assert_eq!(VaultKey::zero(), VaultKey::from_uuid(Uuid::nil()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "compact-keys"))]
+    fn key_display_round_trips() {
+        let key = VaultKey::new();
+        assert_eq!(key, key.to_string().parse::<VaultKey>().unwrap());
+        assert_eq!(36, key.to_string().len());
+
+        let key = VaultKey::from_uuid(Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap());
+        assert_eq!("67e55044-10b1-426f-9247-bb680e5fe0c8", key.to_string());
+        assert_eq!(Ok(key), "67E55044-10B1-426F-9247-BB680E5FE0C8".parse());
+        assert_eq!(Ok(key), "67e5504410b1426f9247bb680e5fe0c8".parse());
+        assert_eq!(Ok(key), "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8".parse());
+    }
+
+    #[test]
+    fn key_parse_rejects_garbage() {
+        let short = "67e55044-10b1".parse::<VaultKey>().unwrap_err();
+        assert_eq!("invalid vault key: expected 32, 36 or 45 characters, found 13", short.to_string());
+        assert_eq!(true, "".parse::<VaultKey>().is_err());
+
+        let garbage = "zze55044-10b1-426f-9247-bb680e5fe0c8".parse::<VaultKey>().unwrap_err();
+        assert_eq!(true, garbage.to_string().starts_with("invalid vault key: expected a UUID"));
+        assert_eq!(true, "67e55044-10b1-426f-9247bb680e5fe0c8-".parse::<VaultKey>().is_err());
+        assert_eq!(true, "urn:uuid:67e5504410b1426f9247bb680e5fe0c8".parse::<VaultKey>().is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", not(feature = "compact-keys")))]
+    fn key_serde_json_round_trips() {
+        let key = VaultKey::from_uuid(Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap());
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!("\"67e55044-10b1-426f-9247-bb680e5fe0c8\"", json);
+        assert_eq!(key, serde_json::from_str::<VaultKey>(&json).unwrap());
+
+        let keys = vec![VaultKey::new(), VaultKey::zero()];
+        let json = serde_json::to_string(&keys).unwrap();
+        assert_eq!(keys, serde_json::from_str::<Vec<VaultKey>>(&json).unwrap());
+
+        let error = serde_json::from_str::<VaultKey>("\"67e55044\"").unwrap_err();
+        assert_eq!(true, error.to_string().starts_with("invalid vault key: expected 32, 36 or 45 characters, found 8"));
+        assert_eq!(true, serde_json::from_str::<VaultKey>("17").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn key_serde_bincode_round_trips() {
+        let key = VaultKey::new();
+        let bytes = bincode::serialize(&key).unwrap();
+        // the 16 bytes of the UUID, after bincode's length prefix
+        assert_eq!(24, bytes.len());
+        assert_eq!(&key.to_bytes(), &bytes[8..]);
+        assert_eq!(key, bincode::deserialize::<VaultKey>(&bytes).unwrap());
+
+        let short = bincode::serialize(&vec![1u8, 2, 3]).unwrap();
+        let error = bincode::deserialize::<VaultKey>(&short).unwrap_err();
+        assert_eq!("invalid length 3, expected 16 bytes", error.to_string());
+    }
+
+    #[test]
+    #[cfg(not(feature = "compact-keys"))]
+    fn key_bytes_round_trip() {
+        let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        let key = VaultKey::from_uuid(id);
+        assert_eq!(id.as_bytes(), &key.to_bytes());
+        assert_eq!(0x67, key.to_bytes()[0]);
+        assert_eq!(key, VaultKey::from_bytes(key.to_bytes()));
+        assert_eq!(Ok(key), VaultKey::try_from_slice(id.as_bytes()));
+
+        let key = VaultKey::new();
+        assert_eq!(Ok(key), VaultKey::try_from_slice(&key.to_bytes()));
+    }
+
+    #[test]
+    fn key_from_wrong_length_slice() {
+        let bytes = VaultKey::new().to_bytes();
+        let short = VaultKey::try_from_slice(&bytes[..15]).unwrap_err();
+        assert_eq!("invalid vault key: expected 16 bytes, found 15", short.to_string());
+        let mut long = bytes.to_vec();
+        long.push(0);
+        assert_eq!(true, VaultKey::try_from_slice(&long).is_err());
+        assert_eq!(true, VaultKey::try_from_slice(&[]).is_err());
+    }
+
+    #[test]
+    fn key_order_matches_uuid_order() {
+        use std::collections::BTreeSet;
+
+        let mut keys = (0..100).map(|_| VaultKey::new()).collect::<Vec<_>>();
+        keys.push(VaultKey::MIN);
+        keys.push(VaultKey::MAX);
+        keys.push(VaultKey::from_bytes([0x80; 16]));
+        let mut sorted = keys.clone();
+        sorted.sort();
+        let mut uuids = keys.iter().map(VaultKey::to_bytes).collect::<Vec<_>>();
+        uuids.sort();
+        assert_eq!(uuids, sorted.iter().map(VaultKey::to_bytes).collect::<Vec<_>>());
+        assert_eq!(VaultKey::MIN, sorted[0]);
+        assert_eq!(VaultKey::MAX, *sorted.last().unwrap());
+        assert_eq!(VaultKey::zero(), VaultKey::MIN);
+
+        // sorting again from another order gives the same result
+        keys.reverse();
+        keys.sort();
+        assert_eq!(sorted, keys);
+        let set = keys.iter().copied().collect::<BTreeSet<_>>();
+        assert_eq!(sorted, set.into_iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[cfg(not(feature = "compact-keys"))]
+    fn v7_keys_sort_by_creation() {
+        let before = SystemTime::now();
+        let mut keys = Vec::new();
+        for _ in 0..5 {
+            keys.push(VaultKey::new_v7());
+            thread::sleep(Duration::from_millis(2));
+        }
+        // keys made within the same millisecond keep their order as well
+        keys.extend((0..5000).map(|_| VaultKey::new_v7()));
+        let after = SystemTime::now();
+        assert_eq!(true, keys.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let times = keys.iter().map(|key| key.created_at().unwrap()).collect::<Vec<_>>();
+        assert_eq!(true, times.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(true, times[0] + Duration::from_millis(1) >= before);
+        assert_eq!(true, times[4] < times[5]);
+        assert_eq!(true, *times.last().unwrap() <= after + Duration::from_secs(1));
+        assert_eq!(7, keys[0].as_uuid().get_version_num());
+
+        assert_eq!(None, VaultKey::new().created_at());
+        assert_eq!(None, VaultKey::zero().created_at());
+    }
+
+    #[test]
+    #[cfg(not(feature = "compact-keys"))]
+    fn named_keys_match_known_answers() {
+        let settings = VaultKey::from_name(&VaultKey::NAMESPACE, b"user:42:settings");
+        assert_eq!("2c99cf4e-00fb-5e63-9525-899f7289eaf2", settings.to_string());
+        assert_eq!(settings, VaultKey::from_name(&VaultKey::NAMESPACE, b"user:42:settings"));
+        assert_ne!(settings, VaultKey::from_name(&VaultKey::NAMESPACE, b"user:43:settings"));
+
+        // name-based UUIDs made with the standard namespaces agree with other
+        // UUID tooling
+        let dns = VaultKey::from_uuid(Uuid::NAMESPACE_DNS);
+        assert_eq!("886313e1-3b8a-5372-9b90-0c9aee199e5d", VaultKey::from_name(&dns, b"python.org").to_string());
+        assert_eq!("269e025e-4e29-536b-b518-1b5d42fdd456", VaultKey::from_name(&dns, b"user:42:settings").to_string());
+        assert_eq!(5, settings.as_uuid().get_version_num());
+    }
+
+    #[test]
+    fn well_known_key_constant() {
+        const WELL_KNOWN: VaultKey = VaultKey::from_u128(1);
+        const WELL_KNOWN_VALUE: u128 = WELL_KNOWN.as_u128();
+
+        let vault = Vault::new();
+        assert_eq!(true, vault.add_with_key("config", &WELL_KNOWN));
+        assert_eq!(Some("config"), vault.get_clone(&VaultKey::from_u128(1)));
+        assert_eq!(1, WELL_KNOWN_VALUE);
+        #[cfg(not(feature = "compact-keys"))]
+        assert_eq!(Uuid::from_u128(1), WELL_KNOWN.as_uuid());
+        assert_eq!(VaultKey::MAX, VaultKey::from_u128(u128::MAX));
+
+        let key = VaultKey::new();
+        #[cfg(not(feature = "compact-keys"))]
+        assert_eq!(key.as_uuid().as_u128(), key.as_u128());
+        assert_eq!(key, VaultKey::from_u128(key.as_u128()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "compact-keys"))]
+    fn key_tokens_round_trip() {
+        for key in [VaultKey::MIN, VaultKey::MAX, VaultKey::from_u128(1), VaultKey::from_u128(1 << 2)] {
+            assert_eq!(Ok(key), VaultKey::from_token(&key.to_token()));
+        }
+        for _ in 0..1000 {
+            let key = VaultKey::new();
+            let token = key.to_token();
+            assert_eq!(22, token.len());
+            assert_eq!(true, token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(Ok(key), VaultKey::from_token(&token));
+        }
+        assert_eq!("AAAAAAAAAAAAAAAAAAAAAA", VaultKey::MIN.to_token());
+        assert_eq!("_____________________w", VaultKey::MAX.to_token());
+        // the same bytes as standard base64url of the UUID bytes, less the padding
+        let key = VaultKey::from_uuid(Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap());
+        assert_eq!("Z-VQRBCxQm-SR7toDl_gyA", key.to_token());
+
+        // the UUID forms are accepted too
+        assert_eq!(Ok(key), VaultKey::from_token("67e55044-10b1-426f-9247-bb680e5fe0c8"));
+        assert_eq!(Ok(key), VaultKey::from_token("67e5504410b1426f9247bb680e5fe0c8"));
+    }
+
+    #[test]
+    fn key_tokens_reject_malformed() {
+        let error = |text: &str| VaultKey::from_token(text).unwrap_err().to_string();
+        assert_eq!("invalid vault key: expected a 22-character token or a UUID, found 21 characters", error("Z-VQRBCxQm-SR7toDl_gy"));
+        assert_eq!("invalid vault key: expected a 22-character token or a UUID, found 24 characters", error("Z-VQRBCxQm-SR7toDl_gyA=="));
+        assert_eq!("invalid vault key: unexpected character '=' at position 21 of token", error("Z-VQRBCxQm-SR7toDl_gy="));
+        assert_eq!("invalid vault key: unexpected character '+' at position 1 of token", error("Z+VQRBCxQm-SR7toDl_gyA"));
+        assert_eq!("invalid vault key: unexpected character '/' at position 21 of token", error("Z-VQRBCxQm-SR7toDl_gy/"));
+        // the last character's unused bits must be zero
+        assert_eq!("invalid vault key: unexpected character 'B' at position 21 of token", error("Z-VQRBCxQm-SR7toDl_gyB"));
+        assert_eq!(true, VaultKey::from_token("").is_err());
+        assert_eq!(true, VaultKey::from_token("Z-VQRBCxQm-SR7toDl_gé").is_err());
+    }
+
+    #[test]
+    fn typed_vault_operations() {
+        let vault = TypedVault::new();
+        let key = vault.add(1);
+        assert_eq!(true, vault.has_item(&key));
+        assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone(&key));
+        assert_eq!(Some(4), vault.with_item_mut(&key, |i| { *i *= 2; *i }));
+        assert_eq!(vec![key], vault.keys());
+
+        let untyped = key.untyped();
+        assert_eq!(Some(4), vault.as_untyped().get_clone(&untyped));
+        assert_eq!(key, TypedKey::from_untyped(untyped));
+        let other = TypedKey::from_untyped(VaultKey::new());
+        assert_eq!(true, vault.add_with_key(5, &other));
+        assert_eq!(false, vault.add_with_key(6, &other));
+        assert_eq!(Some(5), vault.remove(&other));
+        assert_eq!(1, vault.len());
+
+        let vault = TypedVault::from(vault.into_untyped());
+        assert_eq!(Some(4), vault.remove(&key));
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn typed_keys_need_no_bounds_on_item() {
+        struct Opaque;
+
+        let vault = TypedVault::new();
+        let key = vault.add(Opaque);
+        let copied = key;
+        assert_eq!(key, copied);
+        assert_eq!(format!("TypedKey({:?})", key.untyped()), format!("{:?}", key));
+        let keys: std::collections::BTreeSet<TypedKey<Opaque>> = vec![key, copied].into_iter().collect();
+        assert_eq!(1, keys.len());
+    }
+
+    #[test]
+    fn sequential_keys_go_in_order() {
+        let vault = Vault::with_key_generator(SequentialKeyGenerator::new());
+        assert_eq!(VaultKey::from_u128(1), vault.add(1));
+        let keys = vault.add_many(vec![2, 3]);
+        assert_eq!(vec![VaultKey::from_u128(2), VaultKey::from_u128(3)], keys);
+        assert_eq!(Ok(VaultKey::from_u128(4)), vault.try_add(4));
+        assert_eq!(Ok(VaultKey::from_u128(5)), vault.add_timeout(5, Duration::from_millis(10)));
+        assert_eq!(VaultKey::from_u128(6), vault.lock().add(6));
+        let added = vault.atomically(|txn| txn.add(7));
+        assert_eq!(VaultKey::from_u128(7), added);
+        assert_eq!(Some(VaultKey::from_u128(8)), vault.rekey_fresh(&added));
+        assert_eq!(Some(7), vault.get_clone(&VaultKey::from_u128(8)));
+    }
+
+    #[test]
+    #[cfg(feature = "compact-keys")]
+    fn compact_keys_unique_within_process() {
+        let threads = (0..4)
+            .map(|_| thread::spawn(|| (0..10_000).map(|_| VaultKey::new()).collect::<Vec<_>>()))
+            .collect::<Vec<_>>();
+        let mut keys = std::collections::HashSet::new();
+        for thread in threads {
+            for key in thread.join().unwrap() {
+                assert_eq!(true, key.as_u128() <= u128::from(u64::MAX));
+                assert_ne!(VaultKey::zero(), key);
+                assert_eq!(true, keys.insert(key));
+            }
+        }
+        assert_eq!(40_000, keys.len());
+    }
+
+    #[test]
+    #[cfg(feature = "compact-keys")]
+    fn compact_keys_show_as_uuids() {
+        let key = VaultKey::from_u128(0x9247_bb68_0e5f_e0c8);
+        assert_eq!("00000000-0000-0000-9247-bb680e5fe0c8", key.to_string());
+        assert_eq!(Ok(key), "00000000-0000-0000-9247-BB680E5FE0C8".parse());
+        assert_eq!(Ok(key), "00000000000000009247bb680e5fe0c8".parse());
+        assert_eq!(Ok(key), "urn:uuid:00000000-0000-0000-9247-bb680e5fe0c8".parse());
+        assert_eq!(Ok(key), VaultKey::from_token(&key.to_token()));
+        assert_eq!(VaultKey::from_u128(u128::from(u64::MAX)), VaultKey::MAX);
+        assert_eq!(VaultKey::MAX, VaultKey::from_u128(u128::MAX));
+
+        // values of more than 64 bits are cut short where that can't fail,
+        // and rejected where it can
+        assert_eq!(key, VaultKey::from_u128(0x67e5_5044_10b1_426f_9247_bb68_0e5f_e0c8));
+        let mut bytes = [0xff; 16];
+        bytes[8..].copy_from_slice(&key.to_bytes()[8..]);
+        assert_eq!(key, VaultKey::from_bytes(bytes));
+        let wide = "67e55044-10b1-426f-9247-bb680e5fe0c8".parse::<VaultKey>().unwrap_err();
+        assert_eq!("invalid vault key: compact keys hold only 64 bits, so the first 64 must be zero", wide.to_string());
+        assert_eq!(true, VaultKey::from_token("Z-VQRBCxQm-SR7toDl_gyA").is_err());
+        assert_eq!(true, VaultKey::try_from_slice(&[0xff; 16]).is_err());
+        assert_eq!(Ok(key), VaultKey::try_from_slice(&key.to_bytes()));
+        assert_eq!(true, "zz000000-0000-0000-9247-bb680e5fe0c8".parse::<VaultKey>().is_err());
+        assert_eq!(true, "00000000-0000-0000-9247+bb680e5fe0c8".parse::<VaultKey>().is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "serde", feature = "compact-keys"))]
+    fn compact_key_serde_round_trips() {
+        let key = VaultKey::from_u128(0x9247_bb68_0e5f_e0c8);
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!("\"00000000-0000-0000-9247-bb680e5fe0c8\"", json);
+        assert_eq!(key, serde_json::from_str::<VaultKey>(&json).unwrap());
+        assert_eq!(true, serde_json::from_str::<VaultKey>("\"67e55044-10b1-426f-9247-bb680e5fe0c8\"").is_err());
+
+        let bytes = bincode::serialize(&key).unwrap();
+        assert_eq!(key, bincode::deserialize::<VaultKey>(&bytes).unwrap());
+        let wide = bincode::serialize(&[0xffu8; 16].to_vec()).unwrap();
+        assert_eq!(true, bincode::deserialize::<VaultKey>(&wide).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "signed-tokens")]
+    fn signed_tokens_round_trip() {
+        let later = SystemTime::now() + Duration::from_secs(60);
+        for key in [VaultKey::new(), VaultKey::MIN, VaultKey::MAX] {
+            let token = key.to_signed_token(b"secret", later);
+            assert_eq!(75, token.len());
+            assert_eq!(true, token.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+            assert_eq!(Ok(key), VaultKey::verify_token(&token, b"secret"));
+        }
+        // an empty secret still signs
+        let key = VaultKey::new();
+        let token = key.to_signed_token(b"", later);
+        assert_eq!(Ok(key), VaultKey::verify_token(&token, b""));
+        assert_eq!(Err(TokenError::BadSignature), VaultKey::verify_token(&token, b"secret"));
+        // the same key, secret and expiry always make the same token
+        assert_eq!(token, key.to_signed_token(b"", later));
+        assert_ne!(token, key.to_signed_token(b"", later + Duration::from_millis(1)));
+    }
+
+    #[test]
+    #[cfg(feature = "signed-tokens")]
+    fn signed_tokens_expire() {
+        let key = VaultKey::new();
+        let now = SystemTime::now();
+        let expired = key.to_signed_token(b"secret", now - Duration::from_millis(1));
+        assert_eq!(Err(TokenError::Expired), VaultKey::verify_token(&expired, b"secret"));
+        let long_ago = key.to_signed_token(b"secret", std::time::UNIX_EPOCH - Duration::from_secs(1));
+        assert_eq!(Err(TokenError::Expired), VaultKey::verify_token(&long_ago, b"secret"));
+        // the signature is checked before the expiry
+        assert_eq!(Err(TokenError::BadSignature), VaultKey::verify_token(&expired, b"other"));
+
+        let soon = key.to_signed_token(b"secret", now + Duration::from_millis(100));
+        assert_eq!(Ok(key), VaultKey::verify_token(&soon, b"secret"));
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(Err(TokenError::Expired), VaultKey::verify_token(&soon, b"secret"));
+        assert_eq!("the key token has expired", TokenError::Expired.to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "signed-tokens")]
+    fn signed_tokens_reject_tampering() {
+        const ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+        let key = VaultKey::new();
+        let token = key.to_signed_token(b"secret", SystemTime::now() + Duration::from_secs(60));
+        // every other character in every position, which covers every bit
+        // flip of the key, the expiry and the signature
+        for (index, original) in token.char_indices() {
+            for replacement in ALPHABET.chars().filter(|&c| c != original) {
+                let mut tampered = token.clone();
+                tampered.replace_range(index..index + 1, &replacement.to_string());
+                let result = VaultKey::verify_token(&tampered, b"secret");
+                assert_eq!(true, result.is_err(), "{} verified", tampered);
+                if index < 74 {
+                    assert_eq!(Err(TokenError::BadSignature), result);
+                }
+            }
+        }
+
+        let error = |text: &str| VaultKey::verify_token(text, b"secret").unwrap_err();
+        assert_eq!(TokenError::Malformed, error(""));
+        assert_eq!(TokenError::Malformed, error(&token[..74]));
+        assert_eq!(TokenError::Malformed, error(&format!("{}A", token)));
+        assert_eq!(TokenError::Malformed, error(&format!("{}=", token)));
+        assert_eq!(TokenError::Malformed, error(&token.replacen(&token[..1], "+", 1)));
+        assert_eq!(TokenError::Malformed, error(&key.to_string()));
+        assert_eq!(TokenError::Malformed, error(&key.to_token()));
+        assert_eq!(TokenError::BadSignature, error(&key.to_signed_token(b"secret!", SystemTime::now() + Duration::from_secs(60))));
+    }
+
+    #[test]
+    fn generational_keys_go_stale_on_clear() {
+        let vault = GenerationalVault::new();
+        let key = vault.add(1);
+        assert_eq!(0, key.generation());
+        assert_eq!(true, vault.has_item(&key));
+        assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone(&key));
+
+        vault.clear();
+        assert_eq!(1, vault.generation());
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(None, vault.get_clone(&key));
+        assert_eq!(None, vault.with_item_mut(&key, |i| *i));
+        assert_eq!(false, vault.update_item(&key, |_| panic!("stale key updated")));
+        assert_eq!(None, vault.remove(&key));
+
+        // the same VaultKey, added again after the clear, isn't found through
+        // the stale key
+        let again = vault.add_with_key(3, &key.key()).unwrap();
+        assert_eq!(1, again.generation());
+        assert_eq!(None, vault.get_clone(&key));
+        assert_eq!(Some(3), vault.get_clone(&again));
+        assert_eq!(None, vault.add_with_key(4, &key.key()));
+        assert_eq!(Some(3), vault.as_untyped().get_clone(&key.key()));
+
+        let fresh = vault.add(5);
+        assert_eq!(Some(5), vault.with_item(&fresh, |i| *i));
+        assert_eq!(Some(5), vault.remove(&fresh));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn every_way_of_emptying_moves_the_generation() {
         let vault = Vault::new();
-        let to_add = 3;
-        let key = vault.add(to_add);
+        vault.add(1);
+        vault.clear();
+        assert_eq!(Ok(()), vault.try_clear());
+        vault.drain();
+        vault.clear_with(|_, _| ());
+        vault.lock().clear();
+        assert_eq!(5, vault.generation());
+
+        // other changes don't
+        let key = vault.add(1);
         vault.remove(&key);
-        let second = vault.remove(&key);
-        assert_eq!(None, second);
+        vault.retain(|_, _| false);
+        assert_eq!(5, vault.generation());
+        let vault = GenerationalVault::from(vault);
+        assert_eq!(5, vault.add(1).generation());
     }
 
-    #[test] 
-    fn add_clear_doesnt_have_item() {
+    #[test]
+    fn generational_keys_never_see_a_later_clear() {
+        const KEY: VaultKey = VaultKey::from_u128(1);
+
+        let vault = Arc::new(GenerationalVault::new());
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let writer = {
+            let vault = vault.clone();
+            thread::spawn(move || for i in 0..1000 {
+                vault.clear();
+                sender.send((vault.add_with_key(i, &KEY).unwrap(), i)).unwrap();
+            })
+        };
+        // each generation has its own item under the same VaultKey, and a
+        // key only ever finds its own
+        let mut keys = Vec::new();
+        for received in receiver {
+            keys.push(received);
+            for (key, i) in keys.iter().rev().take(8) {
+                let found = vault.get_clone(key);
+                assert_eq!(true, found.is_none() || found == Some(*i), "{:?} found {:?}", key, found);
+            }
+        }
+        writer.join().unwrap();
+        let (last, i) = keys.pop().unwrap();
+        assert_eq!(Some(i), vault.get_clone(&last));
+        assert_eq!(true, keys.iter().all(|(key, _)| !vault.has_item(key)));
+    }
+
+    #[test]
+    fn rotate_all_keys_moves_every_item() {
+        let vault = Vault::with_shards(8);
+        let old = vault.add_many(0..5000);
+        let rotated = vault.rotate_all_keys();
+        assert_eq!(5000, rotated.len());
+        assert_eq!(5000, vault.len());
+
+        // a bijection from the old keys to the new ones, which share nothing
+        let new = rotated.values().copied().collect::<HashSet<_>>();
+        assert_eq!(5000, new.len());
+        assert_eq!(true, old.iter().all(|key| rotated.contains_key(key) && !new.contains(key)));
+        let mut keys = vault.keys();
+        keys.sort();
+        let mut expected = new.into_iter().collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(expected, keys);
+
+        for (i, key) in old.iter().enumerate() {
+            assert_eq!(false, vault.has_item(key));
+            assert_eq!(Some(i), vault.get_clone(&rotated[key]));
+        }
+        assert_eq!(true, Vault::<i32>::new().rotate_all_keys().is_empty());
+    }
+
+    #[test]
+    fn rotate_all_keys_panics_without_losing_items() {
+        let vault = Vault::with_key_generator(Stutter::default());
+        let keys = vault.add_many(0..10);
+        let stuck = Vault::with_key_generator(Stuck);
+        let key = stuck.add(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| stuck.rotate_all_keys()));
+        assert_eq!(true, result.is_err());
+        assert_eq!(Some(1), stuck.get_clone(&key));
+        assert_eq!(false, stuck.is_poisoned());
+
+        // repeated keys from the generator are skipped, as when adding
+        let rotated = vault.rotate_all_keys();
+        assert_eq!(10, rotated.values().collect::<HashSet<_>>().len());
+        for (i, key) in keys.iter().enumerate() {
+            assert_eq!(Some(i), vault.get_clone(&rotated[key]));
+        }
+    }
+
+    #[test]
+    fn master_key_opens_its_own_vault() {
+        let (vault, master) = Vault::with_master_key();
+        let key_1 = vault.add(1);
+        let key_2 = vault.add(2);
+        assert_eq!(Ok(Some(2)), vault.master_inspect(&master, &key_1, |i| i * 2));
+        assert_eq!(Ok(None), vault.master_inspect(&master, &VaultKey::new(), |i| i * 2));
+        assert_eq!(Ok(Some(1)), vault.master_remove(&master, &key_1));
+        assert_eq!(Ok(None), vault.master_remove(&master, &key_1));
+        assert_eq!(Ok(vec![(key_2, 2)]), vault.master_drain(&master));
+        assert_eq!(true, vault.is_empty());
+        assert_eq!("MasterKey(..)", format!("{:?}", master));
+    }
+
+    #[test]
+    fn master_key_rejected_by_other_vaults() {
+        let (vault, master) = Vault::<i32>::with_master_key();
+        let (other, other_master) = Vault::with_master_key();
+        let plain = Vault::new();
+        let key = other.add(1);
+        plain.add_with_key(2, &key);
+
+        assert_eq!(Err(VaultError::WrongMasterKey), other.master_remove(&master, &key));
+        assert_eq!(Err(VaultError::WrongMasterKey), other.master_inspect(&master, &key, |_| panic!("inspected")));
+        assert_eq!(Err(VaultError::WrongMasterKey), other.master_drain(&master));
+        assert_eq!(Err(VaultError::WrongMasterKey), plain.master_drain(&master));
+        assert_eq!(Err(VaultError::WrongMasterKey), vault.master_drain(&other_master));
+        assert_eq!(Some(1), other.get_clone(&key));
+        assert_eq!(Some(2), plain.get_clone(&key));
+        assert_eq!("the master key is for another vault", VaultError::WrongMasterKey.to_string());
+    }
+
+    #[test]
+    fn dropping_master_key_changes_nothing_else() {
+        let (vault, key) = {
+            let (vault, _master) = Vault::with_master_key();
+            let key = vault.add(1);
+            (vault, key)
+        };
+        assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone(&key));
+        let other = vault.add(3);
+        assert_eq!(Some(3), vault.remove(&other));
+        assert_eq!(vec![(key, 2)], vault.drain());
+        // a new master key for another vault doesn't open this one
+        let (_, master) = Vault::<i32>::with_master_key();
+        assert_eq!(Err(VaultError::WrongMasterKey), vault.master_drain(&master));
+    }
+
+    #[cfg(feature = "passphrases")]
+    #[test]
+    fn locked_item_takes_its_passphrase() {
         let vault = Vault::new();
-        let to_add = "thing";
-        let key = vault.add(to_add);
-        vault.clear();
-        let has_item = vault.has_item(&key);
-        assert_eq!(false, has_item);
+        let key = vault.add_locked(1, b"secret");
+        let missing = VaultKey::new();
+        assert_eq!(Err(AccessError::Denied), vault.remove_locked(&key, b"wrong"));
+        assert_eq!(Err(AccessError::Denied), vault.remove_locked(&key, b""));
+        assert_eq!(Err(AccessError::Denied), vault.remove_locked(&missing, b"secret"));
+        assert_eq!(true, vault.has_item(&key));
+        assert_eq!(false, vault.has_item_locked(&key, b"wrong"));
+        assert_eq!(false, vault.has_item_locked(&missing, b"secret"));
+        assert_eq!(true, vault.has_item_locked(&key, b"secret"));
+        assert_eq!(Ok(Some(1)), vault.remove_locked(&key, b"secret"));
+        assert_eq!(Err(AccessError::Denied), vault.remove_locked(&key, b"secret"));
+        // an item added without a passphrase takes any
+        let plain = vault.add(2);
+        assert_eq!(Ok(Some(2)), vault.remove_locked(&plain, b"anything"));
     }
 
+    #[cfg(feature = "passphrases")]
     #[test]
-    fn add_remove_doesnt_have_item() {
+    fn locked_item_refuses_plain_removal() {
+        use std::panic::{self, AssertUnwindSafe};
+
         let vault = Vault::new();
-        let to_add = "an item";
-        let key = vault.add(to_add);
+        let key = vault.add_locked(1, b"secret");
+        let plain = vault.add(2);
+        assert_eq!(true, panic::catch_unwind(AssertUnwindSafe(|| vault.remove(&key))).is_err());
+        assert_eq!(false, vault.is_poisoned());
+        assert_eq!(true, vault.has_item(&key));
+        assert_eq!(vec![None, Some(2)], vault.remove_many(&[key, plain]));
+        assert_eq!(Err(VaultError::Access(AccessError::PassphraseRequired)), vault.try_remove(&key));
+        assert_eq!(Err(VaultError::Access(AccessError::PassphraseRequired)), vault.remove_timeout(&key, Duration::from_millis(10)));
+        assert_eq!(None, vault.lock().remove(&key));
+        assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone(&key));
+        assert_eq!("the item is locked with a passphrase", VaultError::Access(AccessError::PassphraseRequired).to_string());
+        let (vault, master) = Vault::with_master_key();
+        let key = vault.add_locked(3, b"secret");
+        assert_eq!(Ok(Some(3)), vault.master_remove(&master, &key));
+    }
+
+    #[cfg(feature = "passphrases")]
+    #[test]
+    #[should_panic(expected = "the item is locked with a passphrase")]
+    fn removing_locked_item_panics() {
+        let vault = Vault::new();
+        let key = vault.add_locked(1, b"secret");
         vault.remove(&key);
-        let has_item = vault.has_item(&key);
-        assert_eq!(false, has_item);
+    }
+
+    #[cfg(feature = "passphrases")]
+    #[test]
+    fn lock_follows_item_not_key() {
+        let vault = Vault::new();
+        let key = vault.add_locked(1, b"secret");
+        let other = vault.add(2);
+        assert_eq!(true, vault.swap(&key, &other));
+        assert_eq!(Some(2), vault.remove(&key));
+        assert_eq!(Err(VaultError::Access(AccessError::PassphraseRequired)), vault.try_remove(&other));
+        let moved = vault.rekey_fresh(&other).unwrap();
+        let rotated = vault.rotate_all_keys();
+        assert_eq!(Err(VaultError::Access(AccessError::PassphraseRequired)), vault.try_remove(&rotated[&moved]));
+        // a lock doesn't outlive its item
+        assert_eq!(1, vault.drain().len());
+        vault.add_with_key(3, &key);
+        assert_eq!(Some(3), vault.remove(&key));
+        let locked = vault.add_locked(4, b"secret");
+        assert_eq!(Ok(Some(4)), vault.remove_locked(&locked, b"secret"));
+        vault.add_with_key(5, &locked);
+        assert_eq!(Some(5), vault.remove(&locked));
+    }
+
+    #[cfg(feature = "passphrases")]
+    #[test]
+    fn locked_item_refuses_extend_entries() {
+        let vault = Vault::new();
+        let key = vault.add_locked(1, b"secret");
+        vault.extend_entries(vec![(key, 2)]);
+        assert_eq!(Some(1), vault.get_clone(&key));
+        assert_eq!(Ok(Some(1)), vault.remove_locked(&key, b"secret"));
+    }
+
+    #[cfg(feature = "passphrases")]
+    #[test]
+    fn locked_item_keeps_its_lock_through_updates() {
+        let vault = Vault::new();
+        let key = vault.add_locked(1, b"secret");
+        let other = Vault::new();
+        let mut called = false;
+        let result = vault.update_filter(&key, |_| {
+            called = true;
+            None
+        });
+        assert_eq!(UpdateFilterResult::NotFound, result);
+        assert_eq!(false, called);
+        assert_eq!(false, vault.transfer(&key, &other));
+        assert_eq!(false, other.has_item(&key));
+        assert_eq!(UpsertResult::Refused, vault.update_or_insert(&key, |_| panic!("not called"), || 0));
+        assert_eq!(true, vault.update_item(&key, |i| *i += 1));
+        assert_eq!(Err(VaultError::Access(AccessError::PassphraseRequired)), vault.try_remove(&key));
+        assert_eq!(Ok(Some(2)), vault.remove_locked(&key, b"secret"));
+    }
+
+    #[test]
+    fn dual_item_keeps_both_keys_through_updates() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        let other = Vault::new();
+        assert_eq!(UpdateFilterResult::NotFound, vault.update_filter(&first, |_| None));
+        assert_eq!(UpdateFilterResult::NotFound, vault.update_filter(&second, |_| None));
+        assert_eq!(false, vault.transfer(&first, &other));
+        assert_eq!(false, vault.transfer(&second, &other));
+        assert_eq!(true, other.is_empty());
+        assert_eq!(UpsertResult::Refused, vault.update_or_insert(&first, |_| panic!("not called"), || 0));
+        assert_eq!(true, vault.update_item(&first, |i| *i += 1));
+        assert_eq!(1, vault.len());
+        assert_eq!(None, vault.remove(&first));
+        assert_eq!(Err(VaultError::Access(AccessError::BothKeysRequired)), vault.try_remove(&first));
+        assert_eq!(true, vault.has_item(&second));
+        assert_eq!(Some(2), vault.remove_dual(&first, &second));
+    }
+
+    #[test]
+    fn read_key_outlives_updates_but_changes_nothing() {
+        let vault = Vault::new();
+        let (owner, reader) = vault.add_with_roles(1);
+        let (owner, untyped) = (owner.untyped(), reader.untyped());
+        let other = Vault::new();
+        assert_eq!(UpdateFilterResult::NotFound, vault.update_filter(&untyped, |_| None));
+        assert_eq!(false, vault.transfer(&untyped, &other));
+        assert_eq!(UpdateFilterResult::Updated, vault.update_filter(&owner, |i| Some(i + 1)));
+        assert_eq!(UpsertResult::Updated, vault.update_or_insert(&owner, |i| i + 1, || 0));
+        assert_eq!(Some(3), vault.get_clone_read(&reader));
+        assert_eq!(Err(VaultError::Access(AccessError::ReadOnly)), vault.try_remove(&untyped));
+        assert_eq!(Some(3), vault.remove(&owner));
+        assert_eq!(false, vault.has_item_read(&reader));
+    }
+
+    #[test]
+    fn dual_item_takes_both_keys() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        assert_ne!(first, second);
+        assert_eq!(true, vault.has_item(&first));
+        assert_eq!(true, vault.has_item(&second));
+        assert_eq!(1, vault.len());
+        assert_eq!(None, vault.remove(&first));
+        assert_eq!(None, vault.remove(&second));
+        assert_eq!(vec![None, None], vault.remove_many(&[first, second]));
+        assert_eq!(Err(VaultError::Access(AccessError::BothKeysRequired)), vault.try_remove(&first));
+        assert_eq!(Err(VaultError::Access(AccessError::BothKeysRequired)), vault.try_remove(&second));
+        assert_eq!(None, vault.replace(&first, 2));
+        assert_eq!(None, vault.replace(&second, 2));
+        assert_eq!(None, vault.remove_dual(&first, &first));
+        assert_eq!(Some(1), vault.remove_dual(&second, &first));
+        assert_eq!(false, vault.has_item(&first));
+        assert_eq!(false, vault.has_item(&second));
+        assert_eq!(None, vault.remove_dual(&first, &second));
+    }
+
+    #[test]
+    fn dual_item_refuses_wrong_pairs() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        let (other_first, other_second) = vault.add_dual(2);
+        let plain = vault.add(3);
+        assert_eq!(None, vault.remove_dual(&first, &other_second));
+        assert_eq!(None, vault.remove_dual(&other_first, &second));
+        assert_eq!(None, vault.remove_dual(&second, &other_second));
+        assert_eq!(None, vault.remove_dual(&first, &plain));
+        assert_eq!(None, vault.remove_dual(&plain, &VaultKey::new()));
+        assert_eq!(None, vault.remove_dual(&first, &VaultKey::new()));
+        assert_eq!(3, vault.len());
+        assert_eq!(true, vault.has_item(&second));
+        assert_eq!(true, vault.has_item(&other_second));
+        assert_eq!(Some(1), vault.get_clone(&first));
+        assert_eq!(Some(2), vault.remove_dual(&other_first, &other_second));
+        assert_eq!(Some(1), vault.remove_dual(&first, &second));
+    }
+
+    #[test]
+    fn dual_item_keeps_its_pair_through_rekey() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        let plain = vault.add(2);
+        let moved = VaultKey::new();
+        assert_eq!(false, vault.rekey(&second, &moved));
+        assert_eq!(false, vault.rekey(&plain, &second));
+        assert_eq!(true, vault.rekey(&first, &moved));
+        assert_eq!(None, vault.remove_dual(&first, &second));
+        assert_eq!(true, vault.has_item(&second));
+        assert_eq!(true, vault.swap(&moved, &plain));
+        assert_eq!(Some(1), vault.get_clone(&plain));
+        let fresh = vault.rekey_fresh(&plain).unwrap();
+        let rotated = vault.rotate_all_keys();
+        assert_eq!(None, vault.remove(&rotated[&fresh]));
+        assert_eq!(Some(1), vault.remove_dual(&second, &rotated[&fresh]));
+        assert_eq!(Some(2), vault.remove(&rotated[&moved]));
+        // the pairing goes with the vault's items
+        let (first, second) = vault.add_dual(3);
+        vault.clear();
+        vault.add_with_key(4, &first);
+        assert_eq!(false, vault.has_item(&second));
+        assert_eq!(Some(4), vault.remove(&first));
+    }
+
+    #[test]
+    fn extend_entries_skips_protected_keys() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        let (owner, reader) = vault.add_with_roles(2);
+        let aliased = vault.add(3);
+        let alias = vault.add_alias(&aliased).unwrap();
+        let reservation = vault.reserve_key();
+        let reserved = reservation.key();
+        let named = vault.add_named("plain", 4);
+        let fresh = VaultKey::new();
+        vault.extend_entries(vec![(first, 10), (second, 11), (reader.untyped(), 12), (alias, 13), (reserved, 14), (named, 15), (fresh, 16)]);
+        assert_eq!(5, vault.len());
+        assert_eq!(None, vault.remove(&first));
+        assert_eq!(true, vault.has_item(&second));
+        assert_eq!(Some(2), vault.get_clone_read(&reader));
+        assert_eq!(Some(3), vault.get_clone(&alias));
+        assert_eq!(false, vault.has_item(&reserved));
+        assert_eq!(Some(15), vault.get_clone(&named));
+        assert_eq!(None, vault.key_for("plain"));
+        assert_eq!(Some(16), vault.get_clone(&fresh));
+        // a replaced item loses its read key with its rules
+        vault.extend_entries(vec![(owner.untyped(), 20)]);
+        assert_eq!(false, vault.has_item_read(&reader));
+        assert_eq!(Ok(()), reservation.fulfill(17));
+        assert_eq!(Some(1), vault.remove_dual(&first, &second));
+    }
+
+    #[test]
+    fn update_or_insert_refuses_stand_in_keys() {
+        let vault = Vault::new();
+        let (first, second) = vault.add_dual(1);
+        let (owner, reader) = vault.add_with_roles(2);
+        let reservation = vault.reserve_key();
+        let reserved = reservation.key();
+        let mut called = false;
+        for key in [second, reader.untyped(), reserved] {
+            assert_eq!(UpsertResult::Refused, vault.update_or_insert(&key, |i| {
+                called = true;
+                i
+            }, || 10));
+        }
+        assert_eq!(false, called);
+        assert_eq!(2, vault.len());
+        assert_eq!(Some(2), vault.get_clone_read(&reader));
+        assert_eq!(Ok(()), reservation.fulfill(3));
+        assert_eq!(Some(3), vault.get_clone(&reserved));
+        assert_eq!(Some(2), vault.remove(&owner.untyped()));
+        assert_eq!(Some(1), vault.remove_dual(&first, &second));
+    }
+
+    #[test]
+    fn read_key_only_reads() {
+        let vault = Vault::new();
+        let (owner, reader) = vault.add_with_roles(1);
+        let untyped = reader.untyped();
+        assert_ne!(owner.untyped(), untyped);
+        assert_eq!(true, vault.has_item_read(&reader));
+        assert_eq!(Some(1), vault.get_clone_read(&reader));
+        assert_eq!(Some(2), vault.with_item_read(&reader, |i| i * 2));
+        assert_eq!(true, vault.has_item(&untyped));
+        assert_eq!(Some(1), vault.get_clone(&untyped));
+        assert_eq!(None, vault.remove(&untyped));
+        assert_eq!(vec![None], vault.remove_many(&[untyped]));
+        assert_eq!(Err(VaultError::Access(AccessError::ReadOnly)), vault.try_remove(&untyped));
+        assert_eq!(false, vault.update_item(&untyped, |i| *i += 1));
+        assert_eq!(None, vault.with_item_mut(&untyped, |i| *i += 1));
+        assert_eq!(None, vault.replace(&untyped, 5));
+        assert_eq!(false, vault.add_with_key(5, &untyped));
+        assert_eq!(false, vault.rekey(&untyped, &VaultKey::new()));
+        assert_eq!(None, vault.lock().remove(&untyped));
+        assert_eq!(1, vault.len());
+        assert_eq!(Some(1), vault.get_clone(&owner));
+        assert_eq!("the key can only read the item", AccessError::ReadOnly.to_string());
+    }
+
+    #[test]
+    fn owner_key_does_everything() {
+        let vault = Vault::new();
+        let (owner, reader) = vault.add_with_roles(1);
+        assert_eq!(true, vault.update_item(&owner, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone_read(&reader));
+        let moved = VaultKey::new();
+        assert_eq!(true, vault.rekey(&owner, &moved));
+        assert_eq!(Some(2), vault.get_clone_read(&reader));
+        assert_eq!(Some(2), vault.replace(&moved, 3));
+        // the read key went with the replaced item
+        assert_eq!(false, vault.has_item_read(&reader));
+        let (owner, reader) = vault.add_with_roles(4);
+        assert_eq!(Ok(4), vault.try_remove(&owner));
+        assert_eq!(false, vault.has_item_read(&reader));
+        assert_eq!(None, vault.get_clone_read(&reader));
+        // a read key doesn't outlive its item
+        let (owner, reader) = vault.add_with_roles(5);
+        assert_eq!(UpdateFilterResult::Removed, vault.update_filter(&owner, |_| None));
+        vault.add_with_key(6, &owner);
+        assert_eq!(false, vault.has_item_read(&reader));
+    }
+
+    #[test]
+    fn aliases_share_their_item() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let alias_1 = vault.add_alias(&key).unwrap();
+        let alias_2 = vault.add_alias(&alias_1).unwrap();
+        assert_ne!(alias_1, alias_2);
+        assert_eq!(1, vault.len());
+        assert_eq!(true, vault.update_item(&alias_1, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone(&alias_2));
+        assert_eq!(true, vault.update_item(&alias_2, |i| *i *= 10));
+        assert_eq!(Some(20), vault.get_clone(&alias_1));
+        assert_eq!(Some(20), vault.get_clone(&key));
+        assert_eq!(false, vault.add_with_key(3, &alias_1));
+        assert_eq!(None, vault.add_alias(&VaultKey::new()));
+        // dropping one alias leaves the other
+        assert_eq!(true, vault.remove_alias(&alias_1));
+        assert_eq!(false, vault.remove_alias(&alias_1));
+        assert_eq!(false, vault.has_item(&alias_1));
+        assert_eq!(true, vault.has_item(&alias_2));
+        assert_eq!(Some(20), vault.remove(&alias_2));
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(false, vault.has_item(&alias_2));
+    }
+
+    #[test]
+    fn aliases_work_in_batch_and_bounded_methods() {
+        use std::time::Duration;
+
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let alias = vault.add_alias(&key).unwrap();
+        let wait = Duration::from_millis(10);
+        assert_eq!(vec![Some(1), None], vault.get_many(&[alias, VaultKey::new()]));
+        assert_eq!(Ok(true), vault.try_has_item(&alias));
+        assert_eq!(Ok(()), vault.try_update(&alias, |i| *i += 1));
+        assert_eq!(Ok(true), vault.update_timeout(&alias, |i| *i *= 10, wait));
+        assert_eq!(Some(20), vault.get_clone(&key));
+        assert_eq!(Ok(20), vault.try_remove(&alias));
+        assert_eq!(Ok(false), vault.try_has_item(&alias));
+        assert_eq!(Err(VaultError::KeyNotFound), vault.try_remove(&alias));
+
+        let key = vault.add(3);
+        let alias = vault.add_alias(&key).unwrap();
+        assert_eq!(Some(3), vault.remove_timeout(&alias, wait).unwrap());
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(None, vault.remove_timeout(&alias, wait).unwrap());
+        assert_eq!(Ok(false), vault.update_timeout(&alias, |i| *i += 1, wait));
+
+        let key = vault.add(4);
+        let alias = vault.add_alias(&key).unwrap();
+        assert_eq!(2, vault.update_many(&[alias, key], |_, i| *i += 1));
+        let seen = vault.with_items(&[alias, key], |items| {
+            if let Some(item) = items[0].as_mut() {
+                **item *= 10;
+            }
+            items.iter().map(|item| item.as_ref().map(|item| **item)).collect::<Vec<_>>()
+        });
+        assert_eq!(vec![Some(60), None], seen);
+        assert_eq!(Some(60), vault.get_clone(&key));
+        assert_eq!(vec![Some(60), None], vault.remove_many(&[alias, key]));
+        assert_eq!(true, vault.is_empty());
+        // a dangling alias doesn't reach a new item under the old key
+        vault.add_with_key(5, &key);
+        assert_eq!(vec![None], vault.get_many(&[alias]));
+        assert_eq!(0, vault.update_many(&[alias], |_, i| *i += 1));
+        assert_eq!(true, vault.with_items(&[alias, key], |items| items[0].is_none() && items[1].is_some()));
+        assert_eq!(vec![None], vault.remove_many(&[alias]));
+        assert_eq!(Ok(false), vault.try_has_item(&alias));
+        assert_eq!(Some(5), vault.get_clone(&key));
+        assert_eq!(Some(5), vault.remove(&key));
+
+        let key = vault.add(6);
+        let alias = vault.add_alias(&key).unwrap();
+        assert_eq!(UpsertResult::Updated, vault.update_or_insert(&alias, |i| i + 1, || 0));
+        assert_eq!(UpdateFilterResult::Updated, vault.update_filter(&alias, |i| Some(i * 10)));
+        assert_eq!(1, vault.len());
+        assert_eq!(Some(70), vault.get_clone(&alias));
+        assert_eq!(UpdateFilterResult::Removed, vault.update_filter(&alias, |_| None));
+        assert_eq!(true, vault.is_empty());
+    }
+
+    #[test]
+    fn removing_item_leaves_aliases_dangling() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let alias_1 = vault.add_alias(&key).unwrap();
+        let alias_2 = vault.add_alias(&key).unwrap();
+        assert_eq!(Some(1), vault.remove(&key));
+        assert_eq!(false, vault.has_item(&alias_1));
+        assert_eq!(None, vault.get_clone(&alias_2));
+        assert_eq!(false, vault.update_item(&alias_1, |i| *i += 1));
+        // a new item under the old key isn't reached through them
+        vault.add_with_key(2, &key);
+        assert_eq!(None, vault.remove(&alias_1));
+        assert_eq!(false, vault.remove_alias(&alias_2));
+        assert_eq!(true, vault.add_with_key(3, &alias_2));
+        assert_eq!(Some(2), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn aliases_follow_their_item() {
+        let vault = Vault::new();
+        let key = vault.add(1);
+        let alias = vault.add_alias(&key).unwrap();
+        let moved = VaultKey::new();
+        assert_eq!(true, vault.rekey(&key, &moved));
+        assert_eq!(Some(1), vault.get_clone(&alias));
+        let rotated = vault.rotate_all_keys();
+        assert_eq!(true, vault.update_item(&alias, |i| *i += 1));
+        assert_eq!(Some(2), vault.get_clone(&rotated[&moved]));
+        let (first, second) = vault.add_dual(3);
+        let dual_alias = vault.add_alias(&first).unwrap();
+        assert_eq!(None, vault.remove(&dual_alias));
+        assert_eq!(Some(3), vault.remove_dual(&first, &second));
+        vault.clear();
+        assert_eq!(false, vault.remove_alias(&alias));
+        assert_eq!(true, vault.add_with_key(4, &alias));
+    }
+
+    #[test]
+    fn names_find_their_items() {
+        let vault = Vault::new();
+        let first = vault.add_named("first", 1);
+        let second = vault.add_named("second", 2);
+        assert_eq!(Some(first), vault.key_for("first"));
+        assert_eq!(Some(second), vault.key_for("second"));
+        assert_eq!(None, vault.key_for("third"));
+        assert_eq!(Some(1), vault.remove_named("first"));
+        assert_eq!(None, vault.remove_named("first"));
+        assert_eq!(false, vault.has_item(&first));
+        // removing by key drops the name
+        assert_eq!(Some(2), vault.remove(&second));
+        assert_eq!(None, vault.key_for("second"));
+        assert_eq!(true, vault.add_with_key(3, &second));
+        assert_eq!(None, vault.key_for("second"));
+        assert_eq!(None, vault.remove_named("second"));
+        assert_eq!(Some(3), vault.get_clone(&second));
+    }
+
+    #[test]
+    fn names_move_to_the_newest_item() {
+        let vault = Vault::new();
+        let old = vault.add_named("config", 1);
+        let new = vault.add_named("config", 2);
+        assert_eq!(Some(new), vault.key_for("config"));
+        assert_eq!(Some(1), vault.get_clone(&old));
+        assert_eq!(Some(2), vault.remove_named("config"));
+        assert_eq!(None, vault.key_for("config"));
+        assert_eq!(Some(1), vault.remove(&old));
+    }
+
+    #[test]
+    fn names_keep_up_with_their_items() {
+        let vault = Vault::new();
+        let key = vault.add_named("config", 1);
+        let moved = VaultKey::new();
+        assert_eq!(true, vault.rekey(&key, &moved));
+        assert_eq!(Some(moved), vault.key_for("config"));
+        let rotated = vault.rotate_all_keys();
+        assert_eq!(Some(rotated[&moved]), vault.key_for("config"));
+        vault.clear();
+        assert_eq!(None, vault.key_for("config"));
+        let key = vault.add_named("config", 2);
+        assert_eq!(vec![(key, 2)], vault.drain());
+        assert_eq!(None, vault.key_for("config"));
+        let key = vault.add_named("config", 3);
+        assert_eq!(Some(3), vault.lock().remove(&key));
+        assert_eq!(None, vault.key_for("config"));
+        let key = vault.add_named("config", 4);
+        assert_eq!(UpdateFilterResult::Removed, vault.update_filter(&key, |_| None));
+        assert_eq!(None, vault.key_for("config"));
+    }
+
+    #[test]
+    fn reservations_hold_their_keys() {
+        let vault = Vault::new();
+        let reservation = vault.reserve_key();
+        let key = reservation.key();
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(false, vault.add_with_key(1, &key));
+        assert_eq!(None, vault.replace(&key, 1));
+        assert_eq!(false, vault.lock().add_with_key(1, &key));
+        vault.clear();
+        assert_eq!(false, vault.add_with_key(1, &key));
+        assert_eq!(Ok(()), reservation.fulfill(2));
+        assert_eq!(Some(2), vault.get_clone(&key));
+        assert_eq!(1, vault.len());
+    }
+
+    #[test]
+    fn reservations_let_go_of_their_keys() {
+        let vault = Vault::new();
+        let reservation = vault.reserve_key();
+        let key = reservation.key();
+        reservation.cancel();
+        assert_eq!(true, vault.add_with_key(1, &key));
+        let key = vault.reserve_key().key();
+        assert_eq!(true, vault.add_with_key(2, &key));
+        assert_eq!(Some(2), vault.get_clone(&key));
+    }
+
+    #[test]
+    fn fulfilled_reservations_wake_waiters() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::new());
+        let reservation = vault.reserve_key();
+        let key = reservation.key();
+        let consumer = {
+            let vault = Arc::clone(&vault);
+            thread::spawn(move || vault.take_blocking(&key, Some(Duration::from_secs(10))))
+        };
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(Ok(()), reservation.fulfill(1));
+        assert_eq!(Some(1), consumer.join().unwrap());
+    }
+
+    #[test]
+    fn presized_vaults_work_like_new_ones() {
+        let vault = Vault::with_capacity(1000);
+        let capacity = vault.capacity();
+        assert!(capacity >= 1000);
+        assert_eq!(0, Vault::<i32>::new().capacity());
+        let keys = vault.add_many(0..1000);
+        assert_eq!(1000, vault.len());
+        assert_eq!(Some(500), vault.get_clone(&keys[500]));
+        assert!(vault.capacity() >= capacity);
+        assert_eq!(Some(0), vault.remove(&keys[0]));
+        assert_eq!(true, Vault::<i32>::with_capacity(0).is_empty());
+    }
+
+    #[test]
+    fn bulk_loads_fit_in_reserved_room() {
+        let reserved = Vault::new();
+        let key = reserved.add(usize::MAX);
+        reserved.reserve(100_000);
+        assert!(reserved.capacity() >= 100_001);
+        let keys = reserved.add_many(0..100_000);
+        let unreserved = Vault::new();
+        let other_keys = unreserved.add_many(0..100_000);
+        assert_eq!(100_001, reserved.len());
+        assert_eq!(100_000, unreserved.len());
+        assert_eq!(Some(usize::MAX), reserved.get_clone(&key));
+        assert_eq!(Some(99_999), reserved.get_clone(&keys[99_999]));
+        assert_eq!(Some(99_999), unreserved.get_clone(&other_keys[99_999]));
+    }
+
+    #[test]
+    fn shrunk_vaults_keep_working() {
+        let vault = Vault::new();
+        let keys = vault.add_many(0..100_000);
+        let grown = vault.capacity();
+        vault.retain(|_, item| *item < 100);
+        vault.shrink_to(1000);
+        let shrunk = vault.capacity();
+        assert!(shrunk >= 1000);
+        assert!(shrunk < grown);
+        vault.shrink_to_fit();
+        assert!(vault.capacity() <= shrunk);
+        assert_eq!(100, vault.len());
+        assert_eq!(Some(99), vault.get_clone(&keys[99]));
+        assert_eq!(false, vault.has_item(&keys[100]));
+        let key = vault.add(100_000);
+        assert_eq!(Some(100_000), vault.remove(&key));
+        assert_eq!(Some(0), vault.remove(&keys[0]));
+        assert_eq!(99, vault.len());
+    }
+
+    #[cfg(feature = "spill")]
+    #[test]
+    fn large_items_spill_to_disk() {
+        let dir = std::env::temp_dir().join(format!("bank_vault-spill-test-{}", std::process::id()));
+        let vault = SpillVault::new(&dir, 1024).unwrap();
+        let large = (0..4096).map(|i| i as u8).collect::<Vec<u8>>();
+        let small = vault.add(vec![1, 2, 3]).unwrap();
+        let key = vault.add(large.clone()).unwrap();
+        assert_eq!(false, vault.is_spilled(&small));
+        assert_eq!(true, vault.is_spilled(&key));
+        let files = || std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(1, files());
+        assert_eq!(Some(large.clone()), vault.get_clone(&key).unwrap());
+        assert_eq!(Some(large), vault.remove(&key).unwrap());
+        assert_eq!(0, files());
+        assert_eq!(None, vault.remove(&key).unwrap());
+        assert_eq!(Some(vec![1, 2, 3]), vault.remove(&small).unwrap());
+        vault.add(vec![0; 2048]).unwrap();
+        vault.clear();
+        assert_eq!(0, files());
+        vault.add(vec![0; 2048]).unwrap();
+        drop(vault);
+        assert_eq!(0, files());
+        std::fs::write(dir.join("7.spill"), [0; 2048]).unwrap();
+        let vault = SpillVault::<Vec<u8>>::new(&dir, 1024).unwrap();
+        assert_eq!(0, files());
+        assert_eq!(true, vault.is_empty());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(feature = "spill")]
+    #[test]
+    fn spilling_vaults_take_builder_options() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let dir = std::env::temp_dir().join(format!("bank_vault-spill-builder-test-{}", std::process::id()));
+        let vault = Vault::builder()
+            .shards(2)
+            .max_items(2)
+            .key_generator(SequentialKeyGenerator::new())
+            .build_spilling(&dir, 16)
+            .unwrap();
+        assert_eq!(VaultKey::from_u128(1), vault.add(vec![0u8; 4]).unwrap());
+        let key = vault.add(vec![0u8; 64]).unwrap();
+        assert_eq!(VaultKey::from_u128(2), key);
+        assert_eq!(true, vault.is_spilled(&key));
+        let full = panic::catch_unwind(AssertUnwindSafe(|| vault.add(vec![0u8; 4])));
+        assert_eq!(true, full.is_err());
+        assert_eq!(2, vault.len());
+        drop(vault);
+        let weighed = panic::catch_unwind(AssertUnwindSafe(|| Vault::builder().max_weight(10, Vec::<u8>::len).build_spilling(&dir, 16)));
+        assert_eq!(true, weighed.is_err());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(feature = "spill")]
+    #[test]
+    fn unreadable_spilled_item_stays_in_vault() {
+        let dir = std::env::temp_dir().join(format!("bank_vault-spill-unreadable-test-{}", std::process::id()));
+        let vault = Vault::builder().max_items(1).build_spilling(&dir, 16).unwrap();
+        let key = vault.add(vec![0u8; 64]).unwrap();
+        let file = std::fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(true, vault.remove(&key).is_err());
+        assert_eq!(true, vault.is_spilled(&key));
+        assert_eq!(1, vault.len());
+        std::fs::write(&file, [1u8; 64]).unwrap();
+        assert_eq!(Some(vec![1u8; 64]), vault.remove(&key).unwrap());
+        assert_eq!(true, vault.is_empty());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn capped_vaults_refuse_new_items() {
+        let vault = Vault::with_max_items(2);
+        let key = vault.add(1);
+        assert_eq!(Err(VaultError::KeyInUse), vault.add_with_key_checked(2, &key));
+        vault.add(2);
+        assert_eq!(Err(VaultError::Full), vault.add_checked(3));
+        assert_eq!(Err(VaultError::Full), vault.try_add(3));
+        assert_eq!(Err(VaultError::Full), vault.add_timeout(3, Duration::from_millis(10)));
+        assert_eq!(Err(VaultError::Full), vault.add_many_checked(vec![3]));
+        assert_eq!(false, vault.add_with_key(3, &VaultKey::new()));
+        assert_eq!(false, vault.get_or_insert_with(&VaultKey::new(), || 3));
+        assert_eq!(false, vault.lock().add_with_key(3, &VaultKey::new()));
+        assert_eq!(false, vault.atomically(|txn| txn.add_with_key(3, &VaultKey::new())));
+        assert_eq!(Some(1), vault.replace(&key, 3));
+        assert_eq!(Some(3), vault.remove(&key));
+        assert_eq!(true, vault.atomically(|txn| txn.add_with_key(4, &key)));
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    #[should_panic(expected = "the vault is full")]
+    fn adding_to_full_vault_panics() {
+        let vault = Vault::with_max_items(1);
+        vault.add(1);
+        vault.add(2);
+    }
+
+    #[test]
+    fn cap_holds_against_racing_threads() {
+        use std::sync::Arc;
+        use std::thread;
+
+        let vault = Arc::new(Vault::with_max_items(100));
+        let threads = (0..8)
+            .map(|_| {
+                let vault = Arc::clone(&vault);
+                thread::spawn(move || (0..50).filter(|i| vault.add_checked(*i) == Err(VaultError::Full)).count())
+            })
+            .collect::<Vec<_>>();
+        let rejected: usize = threads.into_iter().map(|thread| thread.join().unwrap()).sum();
+        assert_eq!(100, vault.len());
+        assert_eq!(300, rejected);
+        assert_eq!(100, vault.keys().len());
+    }
+
+    #[test]
+    fn builder_combines_options() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&evicted);
+        let (vault, master) = Vault::builder()
+            .shards(3)
+            .capacity(30)
+            .fair()
+            .max_items(5)
+            .max_weight(6, |item: &String| item.len())
+            .eviction(Box::new(FifoPolicy::new()), move |key, _| sink.lock().unwrap().push(key))
+            .key_generator(SequentialKeyGenerator::new())
+            .build_with_master_key();
+        assert_eq!(3, vault.shards.len());
+        assert_eq!(true, vault.capacity() >= 30);
+        assert_eq!(None, vault.max_items());
+        assert_eq!(Some(6), vault.max_weight());
+        let first = vault.add(String::from("abc"));
+        let second = vault.add(String::from("def"));
+        assert_eq!(VaultKey::from_u128(1), first);
+        assert_eq!(VaultKey::from_u128(2), second);
+        assert_eq!(true, vault.get_clone(&first).is_some());
+        let third = vault.add(String::from("gh"));
+        assert_eq!(vec![first], *evicted.lock().unwrap());
+        assert_eq!(5, vault.weight());
+        assert_eq!(Ok(Some(String::from("gh"))), vault.master_remove(&master, &third));
+    }
+
+    #[test]
+    fn builder_defaults_match_new() {
+        let vault = Vault::<i32>::builder().shards(0).build();
+        assert_eq!(1, vault.shards.len());
+        assert_eq!(None, vault.max_items());
+        assert_eq!(None, vault.max_weight());
+        let keys: Vec<_> = (0..100).map(|i| vault.add(i)).collect();
+        assert_eq!(100, vault.len());
+        assert_eq!(Some(42), vault.get_clone(&keys[42]));
+    }
+
+    #[test]
+    #[should_panic(expected = "max_items or max_weight")]
+    fn builder_refuses_eviction_without_cap() {
+        Vault::<i32>::builder().eviction(Box::new(LruPolicy::new()), |_, _| {}).build();
+    }
+
+    #[test]
+    fn least_recently_used_items_are_evicted() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&evicted);
+        let vault = Vault::with_eviction(3, Box::new(LruPolicy::new()), move |key, item| sink.lock().unwrap().push((key, item)));
+        let keys = vault.add_many(vec![0, 1, 2]);
+        assert_eq!(true, evicted.lock().unwrap().is_empty());
+        assert_eq!(true, vault.has_item(&keys[0]));
+        assert_eq!(Some(1), vault.get_clone(&keys[1]));
+        let key = vault.add(3);
+        assert_eq!(vec![(keys[2], 2)], *evicted.lock().unwrap());
+        assert_eq!(true, vault.update_item(&keys[0], |i| *i += 10));
+        assert_eq!(true, vault.add_with_key(4, &VaultKey::new()));
+        assert_eq!((keys[1], 1), evicted.lock().unwrap()[1]);
+        assert_eq!(Some(10), vault.remove(&keys[0]));
+        vault.add(5);
+        assert_eq!(2, evicted.lock().unwrap().len());
+        vault.add(6);
+        assert_eq!((key, 3), evicted.lock().unwrap()[2]);
+        assert_eq!(3, vault.len());
+    }
+
+    #[test]
+    fn single_item_vaults_evict_on_every_add() {
+        let vault = Vault::with_eviction(1, Box::new(LruPolicy::new()), |_, _| {});
+        let first = vault.add(1);
+        assert_eq!(Some(1), vault.get_clone(&first));
+        let second = vault.add(2);
+        assert_eq!(false, vault.has_item(&first));
+        assert_eq!(Some(2), vault.get_clone(&second));
+        assert_eq!(Some(2), vault.replace(&second, 3));
+        assert_eq!(vec![(second, 3)], vault.drain());
+        let third = vault.add(4);
+        assert_eq!(1, vault.len());
+        assert_eq!(Some(4), vault.remove(&third));
+    }
+
+    #[test]
+    fn full_evicting_vaults_keep_items_that_cant_be_evicted() {
+        let vault = Vault::with_eviction(2, Box::new(LruPolicy::new()), |_, _| {});
+        let (first, second) = vault.add_dual(1);
+        let key = vault.add(2);
+        vault.add(3);
+        assert_eq!(false, vault.has_item(&key));
+        assert_eq!(Err(VaultError::Full), vault.try_add(4));
+        assert_eq!(Some(1), vault.remove_dual(&first, &second));
+        assert_eq!(2, vault.add_many(vec![4, 5]).len());
+        assert_eq!(2, vault.len());
+    }
+
+    #[test]
+    fn first_in_first_out_ignores_reads() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&evicted);
+        let vault = Vault::with_eviction(3, Box::new(FifoPolicy::new()), move |_, item| sink.lock().unwrap().push(item));
+        let keys = vault.add_many(vec![0, 1, 2]);
+        assert_eq!(true, vault.has_item(&keys[0]));
+        assert_eq!(Some(0), vault.get_clone(&keys[0]));
+        assert_eq!(true, vault.update_item(&keys[0], |i| *i += 10));
+        let key = vault.add(3);
+        assert_eq!(Some(1), vault.get_clone(&keys[1]));
+        assert_eq!(Some(1), vault.replace(&keys[1], 11));
+        vault.add(4);
+        assert_eq!(Some(2), vault.remove(&keys[2]));
+        assert_eq!(true, vault.add_with_key(12, &keys[2]));
+        assert_eq!(Some(3), vault.get_clone(&key));
+        vault.add(5);
+        vault.add(6);
+        assert_eq!(vec![10, 11, 3, 4], *evicted.lock().unwrap());
+        assert_eq!(Some(12), vault.get_clone(&keys[2]));
+    }
+
+    #[test]
+    fn custom_policies_choose_what_to_evict() {
+        use std::collections::HashMap;
+        use std::sync::{Arc, Mutex};
+
+        // Evicts the cheapest item first, as priced by its key.
+        struct Cheapest {
+            costs: HashMap<VaultKey, u32>,
+            keys: Vec<VaultKey>,
+        }
+
+        impl EvictionPolicy<i32> for Cheapest {
+            fn on_insert(&mut self, key: VaultKey) {
+                self.keys.push(key);
+            }
+
+            fn on_access(&mut self, _key: &VaultKey) {}
+
+            fn on_remove(&mut self, key: &VaultKey) {
+                self.keys.retain(|known| known != key);
+            }
+
+            fn choose_victim(&mut self) -> Option<VaultKey> {
+                let costs = &self.costs;
+                self.keys.iter().min_by_key(|key| costs.get(key).copied().unwrap_or(u32::MAX)).copied()
+            }
+        }
+
+        let keys = (0..4).map(|_| VaultKey::new()).collect::<Vec<_>>();
+        let costs = keys.iter().copied().zip(vec![3, 1, 2, 0]).collect();
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&evicted);
+        let vault = Vault::with_eviction(3, Box::new(Cheapest {costs, keys: Vec::new()}), move |_, item| sink.lock().unwrap().push(item));
+        for (item, key) in keys.iter().enumerate() {
+            assert_eq!(true, vault.add_with_key(item as i32, key));
+        }
+        assert_eq!(Some(0), vault.remove(&keys[0]));
+        assert_eq!(true, vault.add_with_key(10, &keys[1]));
+        vault.add(4);
+        assert_eq!(vec![1, 3], *evicted.lock().unwrap());
+        assert_eq!(Some(2), vault.get_clone(&keys[2]));
+    }
+
+    #[test]
+    fn weight_stays_exact_as_items_change() {
+        let vault = Vault::with_max_weight(10, |item: &String| item.len());
+        let key = vault.add(String::from("abc"));
+        let other = vault.add(String::from("de"));
+        assert_eq!(5, vault.weight());
+        assert_eq!(true, vault.update_item(&key, |item| item.push_str("fgh")));
+        assert_eq!(8, vault.weight());
+        assert_eq!(Some(String::from("de")), vault.replace(&other, String::from("d")));
+        assert_eq!(7, vault.weight());
+        vault.entry(&other).and_modify(|item| item.push('e'));
+        assert_eq!(8, vault.weight());
+        vault.atomically(|txn| txn.update_item(&key, |item| item.truncate(2)));
+        assert_eq!(4, vault.weight());
+        vault.retain_mut(|_, item| {
+            item.push('!');
+            true
+        });
+        assert_eq!(6, vault.weight());
+        assert_eq!(2, vault.drain().len());
+        assert_eq!(0, vault.weight());
+        vault.add_many(vec![String::from("abcde"), String::from("fghij")]);
+        vault.clear();
+        assert_eq!(0, vault.weight());
+    }
+
+    #[test]
+    fn items_growing_past_the_budget_shut_the_vault() {
+        let vault = Vault::with_max_weight(10, |item: &Vec<u8>| item.len());
+        let key = vault.add(vec![0; 4]);
+        vault.add(vec![0; 4]);
+        assert_eq!(true, vault.update_item(&key, |item| item.resize(8, 0)));
+        assert_eq!(12, vault.weight());
+        assert_eq!(Err(VaultError::Full), vault.add_checked(Vec::new()));
+        assert_eq!(false, vault.get_or_insert_with(&VaultKey::new(), Vec::new));
+        assert_eq!(Some(vec![0; 8]), vault.remove(&key));
+        assert_eq!(true, vault.add_checked(vec![0; 6]).is_ok());
+        assert_eq!(Err(VaultError::Full), vault.add_checked(vec![0]));
+    }
+
+    #[test]
+    fn items_growing_past_the_budget_evict_others() {
+        use std::sync::{Arc, Mutex};
+
+        let evicted = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&evicted);
+        let vault = Vault::with_weighted_eviction(10, |item: &Vec<u8>| item.len(), Box::new(LruPolicy::new()), move |_, item: Vec<u8>| sink.lock().unwrap().push(item.len()));
+        let keys = vault.add_many(vec![vec![0; 1], vec![0; 2], vec![0; 3]]);
+        assert_eq!(true, vault.update_item(&keys[0], |item| item.resize(7, 0)));
+        assert_eq!(12, vault.weight());
+        vault.add(vec![0; 1]);
+        assert_eq!(vec![2, 3], *evicted.lock().unwrap());
+        assert_eq!(8, vault.weight());
+        assert_eq!(Err(VaultError::Full), vault.add_checked(vec![0; 11]));
+        assert_eq!(2, vault.len());
     }
 }
@@ -0,0 +1,488 @@
+// A cap on the number of items in a vault, from Vault::with_max_items. A call
+// that adds to one shard can't see what other threads are adding to the
+// others, so each such call first locks the vault's room, and checks the
+// count for space with its shard locked; only calls that add items take it,
+// so while one holds it the count can only fall. Calls that lock every shard
+// see every item, and check against the cap without it. The room is always
+// locked before any shard, never while holding one.
+//
+// A vault made with Vault::with_max_weight caps the total weight of its items
+// instead, in the same way. Each item's weight is booked under its key as it
+// goes into its shard, in a table under a lock that, like the policy's below,
+// is only ever taken last, and the booking let go of as it leaves, so the total
+// always covers exactly the items in the shards. An item changed in place,
+// which holds only its slot, is weighed again with the slot still locked,
+// but only if its booking is still for that slot: an item removed meanwhile
+// keeps none, and one added under the key since keeps its own. An item that
+// grows in place is kept even if that takes the vault over its budget.
+//
+// A vault made with Vault::with_eviction makes space instead, by evicting the
+// item its policy chooses. The policy hears of every item added, used and
+// removed, under a lock of its own beside the shards, which is only ever
+// taken last, and dropped before anything else is locked, so calls into the
+// policy never overlap. The vault's ways of removing items
+// tell it, but what it knows can lag behind the shards, so an eviction checks
+// the victim still has an item, under its shard lock, before taking it; one
+// that has none is removed from the policy, and one that can't be evicted is
+// removed and added back once the eviction is over, so that the policy can
+// offer others. Evicted items are handed to the vault's callback once every
+// lock is let go of.
+
+use crate::{Slot, VaultError, VaultKey};
+use crate::lock::{Lock, WriteGuard};
+use crate::watch::Watches;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard, PoisonError, TryLockError, TryLockResult};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Chooses which item a vault made with `Vault::with_eviction` evicts to make space for a new one once it is full, from what it is told of the vault's items. `LruPolicy` and `FifoPolicy` are provided.
+/// 
+/// The vault calls these methods with its locks held, so they must not call back into the vault, directly or through another thread: such a call deadlocks or panics. Nor should they block for long, as the vault waits on them. The vault never calls them from two threads at once.
+/// 
+/// What the policy is told can lag behind the vault, so it may be told to remove keys it doesn't know, or to insert ones it already does, and it may choose a key that no longer has an item; the vault then removes it and asks again. An item moved to another key, by `rekey`, `swap` and the like, is removed under its old key and inserted under its new one, as are items the vault can't evict, such as those added with `add_locked`, once they have been chosen.
+pub trait EvictionPolicy<T>: Send {
+    /// Called when an item is added under the key.
+    fn on_insert(&mut self, key: VaultKey);
+
+    /// Called when the key's item is looked at or changed in place, by `has_item`, `with_item`, `get_clone`, `update_item`, `replace` and the like.
+    fn on_access(&mut self, key: &VaultKey);
+
+    /// Called when the key's item leaves the vault, by being removed or evicted, or by the vault being emptied.
+    fn on_remove(&mut self, key: &VaultKey);
+
+    /// Returns the key of the item to evict next, without forgetting it: the vault calls `on_remove` once it has evicted the item. Returns None if there is nothing to evict, and the vault then stays full.
+    fn choose_victim(&mut self) -> Option<VaultKey>;
+}
+
+// The keys in the order of a clock that ticks as they are stamped.
+#[derive(Default, Debug)]
+struct Stamps {
+    clock: u64,
+    stamps: HashMap<VaultKey, u64>,
+    order: BTreeMap<u64, VaultKey>,
+}
+
+impl Stamps {
+    fn stamp(&mut self, key: VaultKey) {
+        self.clock += 1;
+        if let Some(old) = self.stamps.insert(key, self.clock) {
+            self.order.remove(&old);
+        }
+        self.order.insert(self.clock, key);
+    }
+
+    fn release(&mut self, key: &VaultKey) {
+        if let Some(stamp) = self.stamps.remove(key) {
+            self.order.remove(&stamp);
+        }
+    }
+
+    fn oldest(&self) -> Option<VaultKey> {
+        self.order.values().next().copied()
+    }
+}
+
+/// An `EvictionPolicy` that evicts the item used least recently. Adding an item uses it, and so does looking at or changing it with `has_item`, `with_item`, `get_clone`, `update_item` and the like.
+#[derive(Default, Debug)]
+pub struct LruPolicy {
+    stamps: Stamps,
+}
+
+impl LruPolicy {
+    /// Creates a new LruPolicy, knowing of no items.
+    pub fn new() -> LruPolicy {
+        LruPolicy::default()
+    }
+}
+
+impl<T> EvictionPolicy<T> for LruPolicy {
+    fn on_insert(&mut self, key: VaultKey) {
+        self.stamps.stamp(key);
+    }
+
+    fn on_access(&mut self, key: &VaultKey) {
+        self.stamps.stamp(*key);
+    }
+
+    fn on_remove(&mut self, key: &VaultKey) {
+        self.stamps.release(key);
+    }
+
+    fn choose_victim(&mut self) -> Option<VaultKey> {
+        self.stamps.oldest()
+    }
+}
+
+/// An `EvictionPolicy` that evicts the item added longest ago, however it has been used since. An item removed and added again under the same key counts as added anew; one replaced in place by `replace` or `update_or_insert` doesn't.
+#[derive(Default, Debug)]
+pub struct FifoPolicy {
+    stamps: Stamps,
+}
+
+impl FifoPolicy {
+    /// Creates a new FifoPolicy, knowing of no items.
+    pub fn new() -> FifoPolicy {
+        FifoPolicy::default()
+    }
+}
+
+impl<T> EvictionPolicy<T> for FifoPolicy {
+    fn on_insert(&mut self, key: VaultKey) {
+        self.stamps.stamp(key);
+    }
+
+    fn on_access(&mut self, _key: &VaultKey) {}
+
+    fn on_remove(&mut self, key: &VaultKey) {
+        self.stamps.release(key);
+    }
+
+    fn choose_victim(&mut self) -> Option<VaultKey> {
+        self.stamps.oldest()
+    }
+}
+
+pub(crate) type OnEvict<T> = Box<dyn Fn(VaultKey, T) + Send + Sync>;
+type Weigher<T> = Box<dyn Fn(&T) -> usize + Send + Sync>;
+
+// The cap is on what the items count for, their load: one each, or their
+// weight if the vault has a weigher.
+pub(crate) struct Limit<T> {
+    max: usize,
+    room: Lock<()>,
+    weights: Option<Weights<T>>,
+    eviction: Option<Eviction<T>>,
+}
+
+struct Weights<T> {
+    weigher: Weigher<T>,
+    booked: Mutex<Booked>,
+}
+
+// Each key's weight, with the address of the slot it was weighed in.
+#[derive(Default)]
+struct Booked {
+    total: usize,
+    weights: HashMap<VaultKey, (usize, usize)>,
+}
+
+impl Booked {
+    fn book(&mut self, key: VaultKey, weight: usize, slot: usize) {
+        self.total += weight;
+        if let Some((old, _)) = self.weights.insert(key, (weight, slot)) {
+            self.total -= old;
+        }
+    }
+
+    fn release(&mut self, key: &VaultKey) -> Option<(usize, usize)> {
+        let booked = self.weights.remove(key)?;
+        self.total -= booked.0;
+        Some(booked)
+    }
+}
+
+fn address<T>(slot: &Slot<T>) -> usize {
+    std::sync::Arc::as_ptr(slot) as usize
+}
+
+struct Eviction<T> {
+    policy: Mutex<Box<dyn EvictionPolicy<T>>>,
+    on_evict: OnEvict<T>,
+}
+
+impl<T> Limit<T> {
+    pub(crate) fn new(max_items: usize) -> Limit<T> {
+        Limit {max: max_items, room: Lock::new(()), weights: None, eviction: None}
+    }
+
+    pub(crate) fn weighted<W>(max_weight: usize, weigher: W) -> Limit<T>
+            where W: Fn(&T) -> usize + Send + Sync + 'static {
+        let weights = Weights {weigher: Box::new(weigher), booked: Mutex::new(Booked::default())};
+        Limit {weights: Some(weights), ..Limit::new(max_weight)}
+    }
+
+    pub(crate) fn evicting(self, policy: Box<dyn EvictionPolicy<T>>, on_evict: OnEvict<T>) -> Limit<T> {
+        let eviction = Eviction {policy: Mutex::new(policy), on_evict};
+        Limit {eviction: Some(eviction), ..self}
+    }
+
+    pub(crate) fn max_items(&self) -> Option<usize> {
+        Some(self.max).filter(|_| self.weights.is_none())
+    }
+
+    pub(crate) fn max_weight(&self) -> Option<usize> {
+        Some(self.max).filter(|_| self.weights.is_some())
+    }
+
+    pub(crate) fn evicts(&self) -> bool {
+        self.eviction.is_some()
+    }
+
+    fn booked(&self) -> Option<MutexGuard<'_, Booked>> {
+        self.weights.as_ref().map(|weights| weights.booked.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    // What the item counts for against the cap.
+    pub(crate) fn load(&self, item: &T) -> usize {
+        self.weights.as_ref().map_or(1, |weights| (weights.weigher)(item))
+    }
+
+    // What the items in the shards count for, of which there are len.
+    pub(crate) fn loaded(&self, len: usize) -> usize {
+        self.booked().map_or(len, |booked| booked.total)
+    }
+
+    // What the key's item counts for, if it has one, as far as the cap knows.
+    pub(crate) fn load_of(&self, key: &VaultKey, present: bool) -> usize {
+        match self.booked() {
+            Some(booked) => booked.weights.get(key).map_or(0, |(weight, _)| *weight),
+            None => usize::from(present),
+        }
+    }
+
+    // Whether the vault, holding len items, has space for adding the load,
+    // which could never fit if it is over the cap by itself.
+    pub(crate) fn fits(&self, len: usize, adding: usize) -> bool {
+        self.fits_changed(len, 0, adding)
+    }
+
+    // Whether it has space for adding the load in place of items that count
+    // for the load replaced.
+    pub(crate) fn fits_changed(&self, len: usize, replacing: usize, adding: usize) -> bool {
+        self.loaded(len).saturating_sub(replacing).saturating_add(adding) <= self.max
+    }
+
+    pub(crate) fn could_fit(&self, adding: usize) -> bool {
+        adding <= self.max
+    }
+
+    pub(crate) fn weight(&self) -> Option<usize> {
+        self.booked().map(|booked| booked.total)
+    }
+
+    // Books the weight of the item going into the slot under the key. Called
+    // with the key's shard locked.
+    pub(crate) fn book(&self, key: &VaultKey, slot: &Slot<T>, load: usize) {
+        if let Some(mut booked) = self.booked() {
+            booked.book(*key, load, address(slot));
+        }
+    }
+
+    // Weighs the item changed in place in the slot again, with the slot
+    // locked, if the booking under the key is still for that slot.
+    pub(crate) fn rebook(&self, key: &VaultKey, slot: &Slot<T>, item: &T) {
+        if let Some(weights) = &self.weights {
+            let weight = (weights.weigher)(item);
+            let mut booked = self.booked().expect("the vault has a budget");
+            if booked.weights.get(key).is_some_and(|(_, booked)| *booked == address(slot)) {
+                booked.book(*key, weight, address(slot));
+            }
+        }
+    }
+
+    // A policy that panicked is carried on with; what it knows may be off,
+    // but the vault copes with that anyway.
+    fn policy(&self) -> Option<MutexGuard<'_, Box<dyn EvictionPolicy<T>>>> {
+        self.eviction.as_ref().map(|eviction| eviction.policy.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    pub(crate) fn inserted(&self, key: &VaultKey) {
+        if let Some(mut policy) = self.policy() {
+            policy.on_insert(*key);
+        }
+    }
+
+    pub(crate) fn used(&self, key: &VaultKey) {
+        if let Some(mut policy) = self.policy() {
+            policy.on_access(key);
+        }
+    }
+
+    // Lets go of the key's item as it leaves its shard, which is locked.
+    pub(crate) fn release(&self, key: &VaultKey) {
+        if let Some(mut booked) = self.booked() {
+            booked.release(key);
+        }
+        self.unlist(key);
+    }
+
+    fn unlist(&self, key: &VaultKey) {
+        if let Some(mut policy) = self.policy() {
+            policy.on_remove(key);
+        }
+    }
+
+    // Moves what is known of items that are moving from one key to another,
+    // all at once so that the moves may swap keys around.
+    pub(crate) fn rename(&self, moves: &[(VaultKey, VaultKey)]) {
+        if let Some(mut booked) = self.booked() {
+            let moved: Vec<(VaultKey, (usize, usize))> = moves.iter()
+                .filter_map(|(old, new)| booked.release(old).map(|weight| (*new, weight)))
+                .collect();
+            for (key, (weight, slot)) in moved {
+                booked.book(key, weight, slot);
+            }
+        }
+        if let Some(mut policy) = self.policy() {
+            for (old, _) in moves {
+                policy.on_remove(old);
+            }
+            for (_, new) in moves {
+                policy.on_insert(*new);
+            }
+        }
+    }
+
+    // Called with every shard locked, before the vault is emptied.
+    pub(crate) fn release_all<'k, I>(&self, keys: I)
+            where I: Iterator<Item = &'k VaultKey> {
+        if let Some(mut booked) = self.booked() {
+            *booked = Booked::default();
+        }
+        if let Some(mut policy) = self.policy() {
+            for key in keys {
+                policy.on_remove(key);
+            }
+        }
+    }
+
+    fn victim(&self) -> Option<VaultKey> {
+        self.policy()?.choose_victim()
+    }
+}
+
+// The victims a policy offers during one eviction, each offered only once,
+// so that a policy that keeps offering the same key can't stall the vault.
+// Those that couldn't be evicted are added back as the walk is dropped.
+pub(crate) struct Victims<'a, T> {
+    limit: &'a Limit<T>,
+    offered: HashSet<VaultKey>,
+    kept: Vec<VaultKey>,
+}
+
+impl<'a, T> Victims<'a, T> {
+    pub(crate) fn new(limit: &'a Limit<T>) -> Victims<'a, T> {
+        Victims {limit, offered: HashSet::new(), kept: Vec::new()}
+    }
+
+    pub(crate) fn next(&mut self) -> Option<VaultKey> {
+        self.limit.victim().filter(|key| self.offered.insert(*key))
+    }
+
+    // The victim has no item any more.
+    pub(crate) fn gone(&mut self, key: &VaultKey) {
+        self.limit.unlist(key);
+    }
+
+    // The victim's item can't be evicted.
+    pub(crate) fn kept(&mut self, key: &VaultKey) {
+        self.limit.unlist(key);
+        self.kept.push(*key);
+    }
+}
+
+impl<'a, T> Drop for Victims<'a, T> {
+    fn drop(&mut self) {
+        for key in &self.kept {
+            self.limit.inserted(key);
+        }
+    }
+}
+
+// Whether a vault holding len items has space for adding the load.
+pub(crate) fn fits<T>(limit: Option<&Limit<T>>, len: usize, adding: usize) -> bool {
+    limit.is_none_or(|limit| limit.fits(len, adding))
+}
+
+// Items taken out of the vault to make space, handed to its callback, with
+// their watchers told, once the holder is dropped after the vault's locks.
+pub(crate) struct Evicted<'a, T> {
+    limit: Option<&'a Limit<T>>,
+    watches: &'a Watches,
+    slots: Vec<(VaultKey, Slot<T>)>,
+}
+
+impl<'a, T> Evicted<'a, T> {
+    pub(crate) fn new(limit: Option<&'a Limit<T>>, watches: &'a Watches) -> Evicted<'a, T> {
+        Evicted {limit, watches, slots: Vec::new()}
+    }
+
+    pub(crate) fn push(&mut self, key: VaultKey, slot: Slot<T>) {
+        self.slots.push((key, slot));
+    }
+}
+
+impl<'a, T> Drop for Evicted<'a, T> {
+    fn drop(&mut self) {
+        for (key, slot) in self.slots.drain(..) {
+            self.watches.changed(&key);
+            let item = slot.write().unwrap_or_else(PoisonError::into_inner).take();
+            if let (Some(item), Some(eviction)) = (item, self.limit.and_then(|limit| limit.eviction.as_ref())) {
+                (eviction.on_evict)(key, item);
+            }
+        }
+    }
+}
+
+// The room of a vault, locked if the vault has a cap. It must outlive the
+// guard of the shard it is checked under, so that the count has caught up
+// with the shard before another call checks it. The items it evicted are
+// handed over after it is unlocked.
+pub(crate) struct Room<'a, T> {
+    count: &'a AtomicUsize,
+    held: Option<(&'a Limit<T>, WriteGuard<'a, ()>)>,
+    pub(crate) evicted: Evicted<'a, T>,
+}
+
+impl<'a, T> Room<'a, T> {
+    // The room holds nothing but its lock, so a panic while it was held
+    // leaves nothing to clear up.
+    pub(crate) fn lock(limit: Option<&'a Limit<T>>, count: &'a AtomicUsize, watches: &'a Watches) -> Room<'a, T> {
+        let held = limit.map(|limit| (limit, limit.room.write().unwrap_or_else(PoisonError::into_inner)));
+        Room {count, held, evicted: Evicted::new(limit, watches)}
+    }
+
+    pub(crate) fn try_lock(limit: Option<&'a Limit<T>>, count: &'a AtomicUsize, watches: &'a Watches) -> Result<Room<'a, T>, VaultError> {
+        Room::attempt(limit, count, watches, |room| room.try_write(), VaultError::WouldBlock)
+    }
+
+    pub(crate) fn lock_for(limit: Option<&'a Limit<T>>, count: &'a AtomicUsize, watches: &'a Watches, timeout: Duration) -> Result<Room<'a, T>, VaultError> {
+        Room::attempt(limit, count, watches, |room| room.try_write_for(timeout), VaultError::Timeout)
+    }
+
+    fn attempt<F>(limit: Option<&'a Limit<T>>, count: &'a AtomicUsize, watches: &'a Watches, attempt: F, busy: VaultError) -> Result<Room<'a, T>, VaultError>
+            where F: FnOnce(&'a Lock<()>) -> TryLockResult<WriteGuard<'a, ()>> {
+        let held = match limit {
+            Some(limit) => match attempt(&limit.room) {
+                Ok(guard) => Some((limit, guard)),
+                Err(TryLockError::Poisoned(poisoned)) => Some((limit, poisoned.into_inner())),
+                Err(TryLockError::WouldBlock) => return Err(busy),
+            },
+            None => None,
+        };
+        Ok(Room {count, held, evicted: Evicted::new(limit, watches)})
+    }
+
+    pub(crate) fn limit(&self) -> Option<&'a Limit<T>> {
+        self.held.as_ref().map(|(limit, _)| *limit)
+    }
+
+    // Whether the vault has space for adding the load to the shard locked
+    // under the room, before anything has been added to it.
+    pub(crate) fn fits(&self, adding: usize) -> bool {
+        fits(self.limit(), self.count.load(Ordering::Relaxed), adding)
+    }
+
+    // Whether the vault has space for an item of the load under the key,
+    // which it then counts as added, as the item is about to be.
+    pub(crate) fn admit(&self, key: &VaultKey, load: usize) -> bool {
+        if !self.fits(load) {
+            return false;
+        }
+        if let Some((limit, _)) = &self.held {
+            limit.inserted(key);
+        }
+        true
+    }
+}
@@ -0,0 +1,304 @@
+// The read-write lock behind a vault. With the `parking_lot` feature this is
+// parking_lot's RwLock, which has smaller guards and is never poisoned;
+// otherwise it's the standard library's. Both are exposed through std's
+// LockResult and TryLockResult so the vault handles them the same way.
+//
+// The vault also needs to give up on a lock after a while. parking_lot can
+// do that itself; std can't, so there every lock keeps a condvar that its
+// guards signal on release whenever a thread is waiting with a timeout.
+//
+// Neither lock promises to hand itself out in the order it was asked for,
+// so a fair lock puts a queue in front: each blocking read or write takes a
+// ticket and waits its turn before locking, and passes the turn on once it
+// has the lock. Attempts that don't block, or only block for a while, skip
+// the queue.
+
+use std::sync::{Condvar, LockResult, Mutex, PoisonError, TryLockResult};
+use std::time::Duration;
+
+struct Queue {
+    tickets: Mutex<Tickets>,
+    turn: Condvar,
+}
+
+struct Tickets {
+    next: u64,
+    serving: u64,
+}
+
+impl Queue {
+    fn new() -> Queue {
+        Queue {tickets: Mutex::new(Tickets {next: 0, serving: 0}), turn: Condvar::new()}
+    }
+
+    // Locking can't panic, both locks reporting poison through the result,
+    // so the turn is always passed on.
+    fn in_turn<G, F>(&self, lock: F) -> G
+            where F: FnOnce() -> G {
+        let mut tickets = self.tickets.lock().unwrap_or_else(PoisonError::into_inner);
+        let ticket = tickets.next;
+        tickets.next += 1;
+        while tickets.serving != ticket {
+            tickets = self.turn.wait(tickets).unwrap_or_else(PoisonError::into_inner);
+        }
+        drop(tickets);
+        let guard = lock();
+        self.tickets.lock().unwrap_or_else(PoisonError::into_inner).serving += 1;
+        self.turn.notify_all();
+        guard
+    }
+}
+
+// Runs the blocking lock through the queue, if there is one.
+fn queued<G, F>(queue: &Option<Box<Queue>>, lock: F) -> G
+        where F: FnOnce() -> G {
+    match queue {
+        Some(queue) => queue.in_turn(lock),
+        None => lock(),
+    }
+}
+
+#[cfg(not(feature = "parking_lot"))]
+mod imp {
+    use super::{queued, Condvar, Duration, LockResult, Mutex, PoisonError, Queue, TryLockResult};
+    use std::ops::{Deref, DerefMut};
+    use std::sync::atomic::{self, AtomicUsize, Ordering};
+    use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError};
+    use std::time::Instant;
+
+    pub(crate) struct Lock<T> {
+        inner: RwLock<T>,
+        // Threads waiting in try_read_for or try_write_for.
+        waiting: AtomicUsize,
+        released: Mutex<()>,
+        waiters: Condvar,
+        queue: Option<Box<Queue>>,
+    }
+
+    // The guards hold std's guard in an Option so that they can let go of
+    // the lock before signalling its waiters.
+    pub(crate) struct ReadGuard<'a, T> {
+        guard: Option<RwLockReadGuard<'a, T>>,
+        lock: &'a Lock<T>,
+    }
+
+    pub(crate) struct WriteGuard<'a, T> {
+        guard: Option<RwLockWriteGuard<'a, T>>,
+        lock: &'a Lock<T>,
+    }
+
+    impl<'a, T> Deref for ReadGuard<'a, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            self.guard.as_ref().expect("guard is held until dropped")
+        }
+    }
+
+    impl<'a, T> Drop for ReadGuard<'a, T> {
+        fn drop(&mut self) {
+            self.guard = None;
+            self.lock.released();
+        }
+    }
+
+    impl<'a, T> Deref for WriteGuard<'a, T> {
+        type Target = T;
+
+        fn deref(&self) -> &T {
+            self.guard.as_ref().expect("guard is held until dropped")
+        }
+    }
+
+    impl<'a, T> DerefMut for WriteGuard<'a, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            self.guard.as_mut().expect("guard is held until dropped")
+        }
+    }
+
+    impl<'a, T> Drop for WriteGuard<'a, T> {
+        fn drop(&mut self) {
+            self.guard = None;
+            self.lock.released();
+        }
+    }
+
+    fn wrap<G, W, F>(result: LockResult<G>, wrap_guard: F) -> LockResult<W>
+            where F: FnOnce(G) -> W {
+        match result {
+            Ok(guard) => Ok(wrap_guard(guard)),
+            Err(poisoned) => Err(PoisonError::new(wrap_guard(poisoned.into_inner()))),
+        }
+    }
+
+    fn try_wrap<G, W, F>(result: TryLockResult<G>, wrap_guard: F) -> TryLockResult<W>
+            where F: FnOnce(G) -> W {
+        match result {
+            Ok(guard) => Ok(wrap_guard(guard)),
+            Err(TryLockError::Poisoned(poisoned)) => Err(TryLockError::Poisoned(PoisonError::new(wrap_guard(poisoned.into_inner())))),
+            Err(TryLockError::WouldBlock) => Err(TryLockError::WouldBlock),
+        }
+    }
+
+    impl<T> Lock<T> {
+        pub(crate) fn new(value: T) -> Lock<T> {
+            Lock {
+                inner: RwLock::new(value),
+                waiting: AtomicUsize::new(0),
+                released: Mutex::new(()),
+                waiters: Condvar::new(),
+                queue: None,
+            }
+        }
+
+        pub(crate) fn fair(value: T) -> Lock<T> {
+            Lock {queue: Some(Box::new(Queue::new())), ..Lock::new(value)}
+        }
+
+        fn read_guard<'a>(&'a self, guard: RwLockReadGuard<'a, T>) -> ReadGuard<'a, T> {
+            ReadGuard {guard: Some(guard), lock: self}
+        }
+
+        fn write_guard<'a>(&'a self, guard: RwLockWriteGuard<'a, T>) -> WriteGuard<'a, T> {
+            WriteGuard {guard: Some(guard), lock: self}
+        }
+
+        pub(crate) fn read(&self) -> LockResult<ReadGuard<'_, T>> {
+            wrap(queued(&self.queue, || self.inner.read()), |guard| self.read_guard(guard))
+        }
+
+        pub(crate) fn write(&self) -> LockResult<WriteGuard<'_, T>> {
+            wrap(queued(&self.queue, || self.inner.write()), |guard| self.write_guard(guard))
+        }
+
+        pub(crate) fn try_read(&self) -> TryLockResult<ReadGuard<'_, T>> {
+            try_wrap(self.inner.try_read(), |guard| self.read_guard(guard))
+        }
+
+        pub(crate) fn try_write(&self) -> TryLockResult<WriteGuard<'_, T>> {
+            try_wrap(self.inner.try_write(), |guard| self.write_guard(guard))
+        }
+
+        pub(crate) fn try_read_for(&self, timeout: Duration) -> TryLockResult<ReadGuard<'_, T>> {
+            self.wait_for(timeout, || self.try_read())
+        }
+
+        pub(crate) fn try_write_for(&self, timeout: Duration) -> TryLockResult<WriteGuard<'_, T>> {
+            self.wait_for(timeout, || self.try_write())
+        }
+
+        // Retries the attempt each time a guard is released, until the
+        // timeout. Attempts are made holding the released mutex, which a
+        // releasing guard must take to signal, so no release is missed
+        // between a failed attempt and the wait.
+        fn wait_for<G, F>(&self, timeout: Duration, attempt: F) -> TryLockResult<G>
+                where F: Fn() -> TryLockResult<G> {
+            match attempt() {
+                Err(TryLockError::WouldBlock) => {}
+                result => return result,
+            }
+            let start = Instant::now();
+            self.waiting.fetch_add(1, Ordering::SeqCst);
+            let mut released = self.released.lock().unwrap_or_else(PoisonError::into_inner);
+            let result = loop {
+                match attempt() {
+                    Err(TryLockError::WouldBlock) => {}
+                    result => break result,
+                }
+                let left = timeout.saturating_sub(start.elapsed());
+                if left.is_zero() {
+                    break Err(TryLockError::WouldBlock);
+                }
+                released = self.waiters.wait_timeout(released, left)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            };
+            drop(released);
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            result
+        }
+
+        // Called by a guard once it has let go of the lock. The fence pairs
+        // with the waiter's count so that either the waiter's next attempt
+        // sees the lock free or this sees the waiter.
+        fn released(&self) {
+            atomic::fence(Ordering::SeqCst);
+            if self.waiting.load(Ordering::SeqCst) > 0 {
+                drop(self.released.lock().unwrap_or_else(PoisonError::into_inner));
+                self.waiters.notify_all();
+            }
+        }
+
+        pub(crate) fn is_poisoned(&self) -> bool {
+            self.inner.is_poisoned()
+        }
+
+        pub(crate) fn clear_poison(&self) {
+            self.inner.clear_poison()
+        }
+
+        pub(crate) fn into_inner(self) -> LockResult<T> {
+            self.inner.into_inner()
+        }
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+mod imp {
+    use super::{queued, Duration, LockResult, Queue, TryLockResult};
+    use std::sync::TryLockError;
+
+    pub(crate) type ReadGuard<'a, T> = parking_lot::RwLockReadGuard<'a, T>;
+    pub(crate) type WriteGuard<'a, T> = parking_lot::RwLockWriteGuard<'a, T>;
+
+    pub(crate) struct Lock<T> {
+        inner: parking_lot::RwLock<T>,
+        queue: Option<Box<Queue>>,
+    }
+
+    impl<T> Lock<T> {
+        pub(crate) fn new(value: T) -> Lock<T> {
+            Lock {inner: parking_lot::RwLock::new(value), queue: None}
+        }
+
+        pub(crate) fn fair(value: T) -> Lock<T> {
+            Lock {inner: parking_lot::RwLock::new(value), queue: Some(Box::new(Queue::new()))}
+        }
+
+        pub(crate) fn read(&self) -> LockResult<ReadGuard<'_, T>> {
+            Ok(queued(&self.queue, || self.inner.read()))
+        }
+
+        pub(crate) fn write(&self) -> LockResult<WriteGuard<'_, T>> {
+            Ok(queued(&self.queue, || self.inner.write()))
+        }
+
+        pub(crate) fn try_read(&self) -> TryLockResult<ReadGuard<'_, T>> {
+            self.inner.try_read().ok_or(TryLockError::WouldBlock)
+        }
+
+        pub(crate) fn try_write(&self) -> TryLockResult<WriteGuard<'_, T>> {
+            self.inner.try_write().ok_or(TryLockError::WouldBlock)
+        }
+
+        pub(crate) fn try_read_for(&self, timeout: Duration) -> TryLockResult<ReadGuard<'_, T>> {
+            self.inner.try_read_for(timeout).ok_or(TryLockError::WouldBlock)
+        }
+
+        pub(crate) fn try_write_for(&self, timeout: Duration) -> TryLockResult<WriteGuard<'_, T>> {
+            self.inner.try_write_for(timeout).ok_or(TryLockError::WouldBlock)
+        }
+
+        pub(crate) fn is_poisoned(&self) -> bool {
+            false
+        }
+
+        pub(crate) fn clear_poison(&self) {}
+
+        pub(crate) fn into_inner(self) -> LockResult<T> {
+            Ok(self.inner.into_inner())
+        }
+    }
+}
+
+pub(crate) use self::imp::{Lock, ReadGuard, WriteGuard};
@@ -0,0 +1,29 @@
+// The master key of a vault made by Vault::with_master_key. The vault keeps a
+// random nonce and its master key carries the same one; a vault only takes
+// the master key carrying its own nonce, so a master key opens no other
+// vault, and there is no way to make one except with_master_key.
+
+use crate::keygen;
+use std::fmt;
+
+/// The key to a vault made by `Vault::with_master_key`, which lets its holder remove or look at any item in that vault, with `master_remove`, `master_inspect` and `master_drain`. It opens no other vault. It can't be cloned or made any other way, so only its holder has master access; dropping it gives that up and changes nothing else about the vault.
+pub struct MasterKey {
+    nonce: u128,
+}
+
+impl MasterKey {
+    pub(crate) fn new() -> MasterKey {
+        MasterKey {nonce: keygen::random_bits()}
+    }
+
+    pub(crate) fn nonce(&self) -> u128 {
+        self.nonce
+    }
+}
+
+// Doesn't show the nonce.
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MasterKey(..)")
+    }
+}
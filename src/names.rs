@@ -0,0 +1,106 @@
+// Names for items, from Vault::add_named. The index sits beside the shards
+// like the access table, under a lock of its own that is only taken with the
+// item's shard locked or no shard locked at all, and each name holds a weak
+// pointer to the slot of the item it names. A name only resolves while that
+// slot is the one stored under its key, so it stops resolving the moment its
+// item is removed, however that happens; the vault's own ways of removing
+// items drop the name straight away, and the rest leave it to be tidied when
+// next looked up. A count of the names lets vaults with none skip the lock.
+
+use crate::{Slot, VaultKey, WeakSlot};
+use crate::lock::Lock;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+struct Index<T> {
+    keys: HashMap<String, (VaultKey, WeakSlot<T>)>,
+    names: HashMap<VaultKey, String>,
+}
+
+impl<T> Index<T> {
+    fn forget(&mut self, key: &VaultKey) {
+        if let Some(name) = self.names.remove(key) {
+            self.keys.remove(&name);
+        }
+    }
+}
+
+pub(crate) struct Names<T> {
+    index: Mutex<Index<T>>,
+    count: AtomicUsize,
+}
+
+impl<T> Names<T> {
+    pub(crate) fn new() -> Names<T> {
+        Names {index: Mutex::new(Index {keys: HashMap::new(), names: HashMap::new()}), count: AtomicUsize::new(0)}
+    }
+
+    // Gives the item in the slot under the key the name, taking it from any
+    // item that had it. Called with the key's shard locked.
+    pub(crate) fn name(&self, name: &str, key: VaultKey, slot: &Slot<T>) {
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((named, _)) = index.keys.remove(name) {
+            index.names.remove(&named);
+        }
+        index.forget(&key);
+        index.keys.insert(name.to_owned(), (key, Arc::downgrade(slot)));
+        index.names.insert(key, name.to_owned());
+        self.count.store(index.names.len(), Ordering::Relaxed);
+    }
+
+    // The key the name was given to, and a pointer to its item's slot. The
+    // caller checks with the key's shard locked that the slot is still the
+    // one stored under it. A name whose item is gone for good is dropped.
+    pub(crate) fn lookup(&self, name: &str) -> Option<(VaultKey, *const Lock<Option<T>>)> {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let (key, slot) = index.keys.get(name)?;
+        if slot.strong_count() > 0 {
+            return Some((*key, slot.as_ptr()));
+        }
+        let key = *key;
+        index.forget(&key);
+        self.count.store(index.names.len(), Ordering::Relaxed);
+        None
+    }
+
+    // Drops the name of the key's item, as it is removed. Called with the
+    // key's shard locked.
+    pub(crate) fn release(&self, key: &VaultKey) {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        index.forget(key);
+        self.count.store(index.names.len(), Ordering::Relaxed);
+    }
+
+    // Moves the names of items that are moving from one key to another, all
+    // at once so that the moves may swap keys around.
+    pub(crate) fn rename(&self, moves: &[(VaultKey, VaultKey)]) {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        let moved: Vec<(VaultKey, String)> = moves.iter()
+            .filter_map(|(old, new)| index.names.remove(old).map(|name| (*new, name)))
+            .collect();
+        for (key, name) in moved {
+            if let Some((named, _)) = index.keys.get_mut(&name) {
+                *named = key;
+            }
+            index.names.insert(key, name);
+        }
+    }
+
+    // Called with every shard locked, as the vault is emptied.
+    pub(crate) fn release_all(&self) {
+        let mut index = self.index.lock().unwrap_or_else(PoisonError::into_inner);
+        index.keys.clear();
+        index.names.clear();
+        self.count.store(0, Ordering::Relaxed);
+    }
+}
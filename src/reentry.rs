@@ -0,0 +1,108 @@
+// Catches a thread using a vault from inside an operation it passed to that
+// same vault, while the part of the vault the operation runs under is still
+// locked. Such a call would deadlock, or panic with a poisoning message that
+// says nothing about the cause, so in debug builds each thread keeps a list
+// of the locks it is running operations under and checks it before locking
+// the vault again. Release builds keep no list and check nothing.
+
+use crate::VaultKey;
+
+// What an operation runs under: the whole vault, the shard with the given
+// index, or the slot of a single item.
+#[derive(Clone, Copy)]
+#[cfg_attr(not(debug_assertions), allow(dead_code))]
+pub(crate) enum Scope {
+    Vault,
+    Shard(usize),
+    Item(VaultKey),
+}
+
+#[cfg(debug_assertions)]
+mod imp {
+    use super::Scope;
+    use crate::VaultKey;
+    use std::cell::RefCell;
+
+    struct Hold {
+        id: u64,
+        vault: usize,
+        scope: Scope,
+        operation: &'static str,
+    }
+
+    thread_local! {
+        static HOLDS: RefCell<(u64, Vec<Hold>)> = const { RefCell::new((0, Vec::new())) };
+    }
+
+    // Leaves the list when the operation returns or panics. Holds needn't
+    // end in the order they began, since a guard can outlive a later one.
+    pub(crate) struct Held {
+        id: u64,
+    }
+
+    impl Drop for Held {
+        fn drop(&mut self) {
+            HOLDS.with(|holds| holds.borrow_mut().1.retain(|hold| hold.id != self.id));
+        }
+    }
+
+    pub(crate) fn enter(vault: usize, scope: Scope, operation: &'static str) -> Held {
+        HOLDS.with(|holds| {
+            let (next, holds) = &mut *holds.borrow_mut();
+            *next += 1;
+            holds.push(Hold {id: *next, vault, scope, operation});
+            Held {id: *next}
+        })
+    }
+
+    // The list is let go of before panicking, so that the holds unwound
+    // through can drop themselves from it.
+    pub(crate) fn check(vault: usize, needs: Option<(usize, &VaultKey)>) {
+        let outer = HOLDS.with(|holds| {
+            holds.borrow().1.iter()
+                .find(|hold| hold.vault == vault && match (hold.scope, needs) {
+                    (Scope::Vault, _) | (_, None) => true,
+                    (Scope::Shard(held), Some((shard, _))) => held == shard,
+                    (Scope::Item(held), Some((_, key))) => held == *key,
+                })
+                .map(|hold| hold.operation)
+        });
+        if let Some(operation) = outer {
+            panic!("vault used again while `{}` holds it locked on this thread, which would deadlock", operation);
+        }
+    }
+
+    // Before locking a capped vault's room, which is never waited on with a
+    // shard locked; holding a single item is fine.
+    pub(crate) fn check_room(vault: usize) {
+        let outer = HOLDS.with(|holds| {
+            holds.borrow().1.iter()
+                .find(|hold| hold.vault == vault && !matches!(hold.scope, Scope::Item(_)))
+                .map(|hold| hold.operation)
+        });
+        if let Some(operation) = outer {
+            panic!("vault added to while `{}` holds it locked on this thread, which could deadlock with a cap on its items", operation);
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+mod imp {
+    use super::Scope;
+    use crate::VaultKey;
+
+    pub(crate) struct Held;
+
+    #[inline(always)]
+    pub(crate) fn enter(_vault: usize, _scope: Scope, _operation: &'static str) -> Held {
+        Held
+    }
+
+    #[inline(always)]
+    pub(crate) fn check(_vault: usize, _needs: Option<(usize, &VaultKey)>) {}
+
+    #[inline(always)]
+    pub(crate) fn check_room(_vault: usize) {}
+}
+
+pub(crate) use self::imp::{check, check_room, enter, Held};
@@ -0,0 +1,45 @@
+// A key handed out before its item exists, by Vault::reserve_key. The vault
+// keeps the key reserved in its access table, so that nothing else can add
+// an item under it, until the Reservation is fulfilled or let go of; the
+// Reservation itself is the token, and dropping it lets go of the key, so a
+// reservation that is abandoned, even by a panic, doesn't keep its key.
+
+use crate::{Vault, VaultKey};
+use std::fmt;
+
+/// A key reserved with `Vault::reserve_key`, and the right to add the item under it. Until the reservation is fulfilled, `add_with_key` and the other ways of adding an item under the key refuse it, and `has_item` reports no item. Dropping the reservation, like `cancel`, lets go of the key.
+pub struct Reservation<'a, T> {
+    vault: &'a Vault<T>,
+    key: VaultKey,
+}
+
+impl<'a, T> Reservation<'a, T> {
+    pub(crate) fn new(vault: &'a Vault<T>, key: VaultKey) -> Reservation<'a, T> {
+        Reservation {vault, key}
+    }
+
+    /// Returns the reserved key, to hand to whoever waits for the item.
+    pub fn key(&self) -> VaultKey {
+        self.key
+    }
+
+    /// Adds the item under the reserved key, waking anyone waiting on it. If the key was taken anyway, by `extend_entries` or a transaction, which don't look at reservations, the item is handed back.
+    pub fn fulfill(self, to_add: T) -> Result<(), T> {
+        self.vault.fulfill_reservation(&self.key, to_add)
+    }
+
+    /// Lets go of the reserved key without adding an item, so that it can be used like any other key.
+    pub fn cancel(self) {}
+}
+
+impl<'a, T> Drop for Reservation<'a, T> {
+    fn drop(&mut self) {
+        self.vault.cancel_reservation(&self.key);
+    }
+}
+
+impl<'a, T> fmt::Debug for Reservation<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Reservation").field(&self.key).finish()
+    }
+}
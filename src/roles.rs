@@ -0,0 +1,58 @@
+// The two keys of an item added with Vault::add_with_roles. The owner key is
+// the item's own key, and derefs to it, so it works with every method of the
+// vault. The read key is a second key that the vault only resolves when
+// reading; it doesn't deref, so it can't be handed to a method that changes
+// items by mistake, and its untyped form is refused by them at run time.
+
+use crate::VaultKey;
+use std::ops::Deref;
+
+/// The key of an item added with `Vault::add_with_roles` that has every right to it. It derefs to the item's `VaultKey`, so it can be passed to any method of the vault.
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct OwnerKey {
+    key: VaultKey,
+}
+
+impl OwnerKey {
+    pub(crate) fn new(key: VaultKey) -> OwnerKey {
+        OwnerKey {key}
+    }
+
+    /// Returns the item's key.
+    pub fn untyped(&self) -> VaultKey {
+        self.key
+    }
+}
+
+impl Deref for OwnerKey {
+    type Target = VaultKey;
+
+    fn deref(&self) -> &VaultKey {
+        &self.key
+    }
+}
+
+/// The key of an item added with `Vault::add_with_roles` that can only look at it, with `has_item_read`, `get_clone_read` and `with_item_read`. It can't be passed to the methods that take a `VaultKey`, so it can't change or remove the item:
+/// 
+/// ```rust,compile_fail
+/// # use bank_vault::Vault;
+/// let vault = Vault::<i32>::new();
+/// let (_, reader) = vault.add_with_roles(1);
+/// 
+/// vault.remove(&reader);
+/// ```
+#[derive(Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ReadKey {
+    key: VaultKey,
+}
+
+impl ReadKey {
+    pub(crate) fn new(key: VaultKey) -> ReadKey {
+        ReadKey {key}
+    }
+
+    /// Returns the key as a plain `VaultKey`, for sending elsewhere. The plain key still only reads: `has_item`, `get_clone` and `with_item` find the item with it, and the methods that change or remove items find nothing under it.
+    pub fn untyped(&self) -> VaultKey {
+        self.key
+    }
+}
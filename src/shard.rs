@@ -0,0 +1,134 @@
+// A vault splits its items across shards, each behind its own lock, so that
+// operations on different keys needn't wait for each other. Shards holds the
+// guards of every shard of one vault, always locked in shard order, and
+// answers the map operations the whole-vault methods need by routing each
+// key to its shard.
+
+use crate::VaultKey;
+use std::collections::{hash_map, HashMap};
+use std::ops::{Deref, DerefMut};
+use std::thread;
+
+pub(crate) fn default_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+// Keys are usually random already, but not necessarily (VaultKey::zero, or
+// keys built from known ids), so fold and mix all of the key's bits.
+pub(crate) fn index(key: &VaultKey, shards: usize) -> usize {
+    let bits = key.as_u128();
+    let folded = (bits as u64) ^ ((bits >> 64) as u64);
+    let mixed = folded.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    ((mixed >> 32) % shards as u64) as usize
+}
+
+pub(crate) struct Shards<G> {
+    guards: Vec<G>,
+}
+
+impl<G, T> Shards<G>
+        where G: Deref<Target = HashMap<VaultKey, T>> {
+    pub(crate) fn new(guards: Vec<G>) -> Shards<G> {
+        Shards {guards}
+    }
+
+    pub(crate) fn shard_count(&self) -> usize {
+        self.guards.len()
+    }
+
+    fn shard(&self, key: &VaultKey) -> &HashMap<VaultKey, T> {
+        &self.guards[index(key, self.guards.len())]
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.guards.iter().map(|shard| shard.len()).sum()
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.guards.iter().map(|shard| shard.capacity()).sum()
+    }
+
+    pub(crate) fn contains_key(&self, key: &VaultKey) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    pub(crate) fn get(&self, key: &VaultKey) -> Option<&T> {
+        self.shard(key).get(key)
+    }
+
+    pub(crate) fn iter<'s>(&'s self) -> impl Iterator<Item = (&'s VaultKey, &'s T)>
+            where T: 's {
+        self.guards.iter().flat_map(|shard| shard.iter())
+    }
+
+    pub(crate) fn keys<'s>(&'s self) -> impl Iterator<Item = &'s VaultKey>
+            where T: 's {
+        self.guards.iter().flat_map(|shard| shard.keys())
+    }
+
+    pub(crate) fn values<'s>(&'s self) -> impl Iterator<Item = &'s T>
+            where T: 's {
+        self.guards.iter().flat_map(|shard| shard.values())
+    }
+}
+
+impl<G, T> Shards<G>
+        where G: DerefMut<Target = HashMap<VaultKey, T>> {
+    pub(crate) fn shards_mut<'s>(&'s mut self) -> impl Iterator<Item = &'s mut HashMap<VaultKey, T>>
+            where T: 's {
+        self.guards.iter_mut().map(|shard| &mut **shard)
+    }
+
+    fn shard_mut(&mut self, key: &VaultKey) -> &mut HashMap<VaultKey, T> {
+        let index = index(key, self.guards.len());
+        &mut self.guards[index]
+    }
+
+    pub(crate) fn insert(&mut self, key: VaultKey, item: T) -> Option<T> {
+        self.shard_mut(&key).insert(key, item)
+    }
+
+    pub(crate) fn remove(&mut self, key: &VaultKey) -> Option<T> {
+        self.shard_mut(key).remove(key)
+    }
+
+    pub(crate) fn entry(&mut self, key: VaultKey) -> hash_map::Entry<'_, VaultKey, T> {
+        self.shard_mut(&key).entry(key)
+    }
+
+    pub(crate) fn retain<F>(&mut self, mut predicate: F)
+            where F: FnMut(&VaultKey, &mut T) -> bool {
+        for shard in self.shards_mut() {
+            shard.retain(|key, item| predicate(key, item));
+        }
+    }
+
+    pub(crate) fn drain<'s>(&'s mut self) -> impl Iterator<Item = (VaultKey, T)> + 's
+            where T: 's {
+        self.shards_mut().flat_map(|shard| shard.drain())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for shard in self.shards_mut() {
+            shard.clear();
+        }
+    }
+
+    // Spreads the reservation evenly, which is where random keys land.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        let shards = self.guards.len();
+        for shard in self.shards_mut() {
+            shard.reserve(additional / shards + 1);
+        }
+    }
+}
+
+impl<G, T> Extend<(VaultKey, T)> for Shards<G>
+        where G: DerefMut<Target = HashMap<VaultKey, T>> {
+    fn extend<I>(&mut self, iter: I)
+            where I: IntoIterator<Item = (VaultKey, T)> {
+        for (key, item) in iter {
+            self.insert(key, item);
+        }
+    }
+}
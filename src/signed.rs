@@ -0,0 +1,73 @@
+// Signed key tokens: the key's 16 bytes, then the time the token expires, as
+// 8 big-endian bytes of milliseconds since the Unix epoch, then an
+// HMAC-SHA256 of those 24 bytes under the caller's secret, all in unpadded
+// base64url. The MAC is checked, in constant time, before anything else in
+// the token is looked at, so a token that was tampered with is reported as
+// such even if it has also expired, and none of its contents is trusted
+// until then.
+
+use crate::{token, VaultKey};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// The key and the expiry, then the MAC.
+const SIGNED: usize = 16 + 8;
+const LENGTH: usize = SIGNED + 32;
+
+/// The ways a signed key token can fail `VaultKey::verify_token`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenError {
+    /// The text isn't a signed key token at all: it has the wrong length, or characters outside the URL-safe base64 alphabet.
+    Malformed,
+    /// The token's signature doesn't match its contents, so it was altered, or signed with another secret.
+    BadSignature,
+    /// The token is genuine, but the time it expires at has passed.
+    Expired,
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Malformed => write!(f, "not a signed key token"),
+            TokenError::BadSignature => write!(f, "the key token's signature doesn't match"),
+            TokenError::Expired => write!(f, "the key token has expired"),
+        }
+    }
+}
+
+impl Error for TokenError {}
+
+fn mac(secret: &[u8], signed: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC takes secrets of any length");
+    mac.update(signed);
+    mac
+}
+
+pub(crate) fn sign(key: &VaultKey, secret: &[u8], expires_at: SystemTime) -> String {
+    let millis = expires_at.duration_since(UNIX_EPOCH).map_or(0, |since| since.as_millis().try_into().unwrap_or(u64::MAX));
+    let mut bytes = Vec::with_capacity(LENGTH);
+    bytes.extend_from_slice(&key.to_bytes());
+    bytes.extend_from_slice(&millis.to_be_bytes());
+    let tag = mac(secret, &bytes).finalize().into_bytes();
+    bytes.extend_from_slice(&tag);
+    token::encode_bytes(&bytes)
+}
+
+pub(crate) fn verify(text: &str, secret: &[u8]) -> Result<VaultKey, TokenError> {
+    let bytes = token::decode_bytes(text)
+        .filter(|bytes| bytes.len() == LENGTH)
+        .ok_or(TokenError::Malformed)?;
+    let (signed, tag) = bytes.split_at(SIGNED);
+    mac(secret, signed).verify_slice(tag).map_err(|_| TokenError::BadSignature)?;
+    let millis = u64::from_be_bytes(signed[16..].try_into().expect("the expiry is 8 bytes"));
+    if SystemTime::now() >= UNIX_EPOCH + Duration::from_millis(millis) {
+        return Err(TokenError::Expired);
+    }
+    // Only fails for a token signed for a full UUID and verified with compact
+    // keys.
+    VaultKey::try_from_slice(&signed[..16]).map_err(|_| TokenError::Malformed)
+}
@@ -0,0 +1,225 @@
+// Items too big to keep in memory, for SpillVault. An item whose size is over
+// the vault's threshold is written to a file of its own in the vault's
+// directory before it is added, and the vault holds only the file's path; the
+// file belongs to that entry, and is deleted when the entry is dropped, by
+// whatever means. Files are named by a counter and never reused, so writing
+// one needs no lock. A process that crashes leaves its files behind, and the
+// next vault made on the directory deletes them, so a directory must only be
+// used by one vault at a time.
+
+use crate::{Vault, VaultBuilder, VaultKey};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const EXTENSION: &str = "spill";
+
+/// Values that a `SpillVault` can write out to disk and read back.
+pub trait Spillable: Sized {
+    /// Returns roughly how many bytes the value takes up in memory, to compare against the vault's threshold.
+    fn spill_size(&self) -> usize;
+
+    /// Returns the value as bytes, to be written to disk.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Makes the value back from the bytes `to_bytes` gave. Returns an error if they aren't such bytes.
+    fn from_bytes(bytes: Vec<u8>) -> io::Result<Self>;
+}
+
+impl Spillable for Vec<u8> {
+    fn spill_size(&self) -> usize {
+        self.len()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.clone()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> io::Result<Vec<u8>> {
+        Ok(bytes)
+    }
+}
+
+impl Spillable for String {
+    fn spill_size(&self) -> usize {
+        self.len()
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn from_bytes(bytes: Vec<u8>) -> io::Result<String> {
+        String::from_utf8(bytes).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+    fn read<T: Spillable>(&self) -> io::Result<T> {
+        T::from_bytes(fs::read(&self.path)?)
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+enum Entry<T> {
+    Held(T),
+    Spilled(SpillFile),
+}
+
+/// A `Vault` that keeps items bigger than a threshold on disk instead of in memory, each in a file of its own in the vault's directory, and reads them back when they are asked for. Removing an item, or dropping it with the vault, deletes its file. The directory should be given to one vault only: making a vault deletes the files left in it by an earlier one, such as one in a process that crashed.
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::SpillVault;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// let dir = std::env::temp_dir().join("bank_vault-spill-example");
+/// let vault = SpillVault::new(&dir, 16)?;
+/// 
+/// let small = vault.add(vec![1u8; 4])?;
+/// let large = vault.add(vec![2u8; 1024])?;
+/// assert_eq!(false, vault.is_spilled(&small));
+/// assert_eq!(true, vault.is_spilled(&large));
+/// assert_eq!(Some(vec![2u8; 1024]), vault.remove(&large)?);
+/// #     Ok(())
+/// # }
+/// ```
+pub struct SpillVault<T> {
+    vault: Vault<Entry<T>>,
+    dir: PathBuf,
+    threshold: usize,
+    files: AtomicU64,
+}
+
+impl<T: Spillable> VaultBuilder<T> {
+    /// Creates a `SpillVault` with the builder's options, writing items whose `spill_size` is over the threshold to files in the directory, as for `SpillVault::new`. The shards, capacity, fair locks, key generator and `max_items` cap carry over. Returns an error if the directory can't be created or cleaned up. Panics if `max_weight` or `eviction` was set, as a vault that spills can't weigh or evict items kept on disk.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::Vault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let dir = std::env::temp_dir().join("bank_vault-spill-builder-example");
+    /// let vault = Vault::<Vec<u8>>::builder().shards(2).max_items(10).build_spilling(&dir, 16)?;
+    /// 
+    /// let large = vault.add(vec![2u8; 1024])?;
+    /// assert_eq!(true, vault.is_spilled(&large));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn build_spilling<P: AsRef<Path>>(self, dir: P, threshold: usize) -> io::Result<SpillVault<T>> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == EXTENSION) {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(SpillVault {vault: self.retyped().build(), dir, threshold, files: AtomicU64::new(0)})
+    }
+}
+
+impl<T: Spillable> SpillVault<T> {
+    /// Creates a new, empty SpillVault instance that writes items whose `spill_size` is over the threshold to files in the directory, creating it if need be. Any spill files already in the directory are deleted. Returns an error if the directory can't be created or cleaned up.
+    pub fn new<P: AsRef<Path>>(dir: P, threshold: usize) -> io::Result<SpillVault<T>> {
+        Vault::builder().build_spilling(dir, threshold)
+    }
+
+    /// Returns the directory the vault writes items to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the size over which items are written to disk.
+    pub fn threshold(&self) -> usize {
+        self.threshold
+    }
+
+    /// Adds an object to the vault and returns its key, like `Vault::add`, first writing it to disk if it is over the threshold. Returns an error, without adding it, if it can't be written.
+    pub fn add(&self, to_add: T) -> io::Result<VaultKey> {
+        Ok(self.vault.add(self.spill(to_add)?))
+    }
+
+    /// Adds an object to the vault under the provided key, like `Vault::add_with_key`, first writing it to disk if it is over the threshold. Returns false, without adding it, if the key is already in use, and an error if it can't be written.
+    pub fn add_with_key(&self, to_add: T, key: &VaultKey) -> io::Result<bool> {
+        Ok(self.vault.add_with_key(self.spill(to_add)?, key))
+    }
+
+    /// Removes and returns the stored object with a matching key, like `Vault::remove`, reading it back and deleting its file if it was written to disk. The file is read while the item is still in the vault, and the item is only removed once the read succeeds; if it can't be read back, an error is returned and the item is left as it was.
+    pub fn remove(&self, key: &VaultKey) -> io::Result<Option<T>> {
+        let mut removed = None;
+        self.vault.update_filter(key, |entry| match entry {
+            Entry::Held(item) => {
+                removed = Some(Ok(item));
+                None
+            }
+            Entry::Spilled(file) => match file.read() {
+                Ok(item) => {
+                    removed = Some(Ok(item));
+                    None
+                }
+                Err(error) => {
+                    removed = Some(Err(error));
+                    Some(Entry::Spilled(file))
+                }
+            },
+        });
+        removed.transpose()
+    }
+
+    /// Returns a clone of the stored object with a matching key, like `Vault::get_clone`, reading it back if it was written to disk. Returns an error if it can't be read back.
+    pub fn get_clone(&self, key: &VaultKey) -> io::Result<Option<T>>
+            where T: Clone {
+        self.vault.with_item(key, |entry| match entry {
+            Entry::Held(item) => Ok(item.clone()),
+            Entry::Spilled(file) => file.read(),
+        }).transpose()
+    }
+
+    /// Returns whether there exists an item in the vault with the provided key, like `Vault::has_item`.
+    pub fn has_item(&self, key: &VaultKey) -> bool {
+        self.vault.has_item(key)
+    }
+
+    /// Returns whether the item with the provided key is kept on disk. Returns false if there is no such item.
+    pub fn is_spilled(&self, key: &VaultKey) -> bool {
+        self.vault.with_item(key, |entry| matches!(entry, Entry::Spilled(_))).unwrap_or(false)
+    }
+
+    /// Removes every item from the vault, deleting the files of those on disk.
+    pub fn clear(&self) {
+        self.vault.clear()
+    }
+
+    /// Returns the number of items in the vault, on disk or not.
+    pub fn len(&self) -> usize {
+        self.vault.len()
+    }
+
+    /// Returns whether the vault holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.vault.is_empty()
+    }
+
+    // Writes the item to a new file if it is over the threshold.
+    fn spill(&self, item: T) -> io::Result<Entry<T>> {
+        if item.spill_size() <= self.threshold {
+            return Ok(Entry::Held(item));
+        }
+        let number = self.files.fetch_add(1, Ordering::Relaxed);
+        let path = self.dir.join(format!("{}.{}", number, EXTENSION));
+        let mut file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        let spilled = SpillFile {path};
+        file.write_all(&item.to_bytes())?;
+        Ok(Entry::Spilled(spilled))
+    }
+}
@@ -0,0 +1,91 @@
+// The compact form of a key: its 128 bits in unpadded base64url, six bits to
+// a character, most significant first. 22 characters hold 132 bits, so the
+// last one carries only the key's lowest two bits, and its other four must
+// be zero for a token to be accepted; every key has exactly one token.
+
+use crate::Invalid;
+
+pub(crate) const LENGTH: usize = 22;
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+pub(crate) fn encode(value: u128) -> String {
+    let mut token = String::with_capacity(LENGTH);
+    for shift in (0..LENGTH - 1).map(|index| 122 - 6 * index) {
+        token.push(ALPHABET[(value >> shift) as usize & 0x3f] as char);
+    }
+    token.push(ALPHABET[(value as usize & 0x3) << 4] as char);
+    token
+}
+
+pub(crate) fn decode(token: &str) -> Result<u128, Invalid> {
+    if token.len() != LENGTH {
+        return Err(Invalid::TokenLength(token.len()));
+    }
+    let mut value = 0;
+    for (index, character) in token.chars().enumerate() {
+        let digit = digit(character).map(u128::from).ok_or(Invalid::TokenCharacter(character, index))?;
+        if index < LENGTH - 1 {
+            value = value << 6 | digit;
+        } else if digit & 0xf == 0 {
+            value = value << 2 | digit >> 4;
+        } else {
+            return Err(Invalid::TokenCharacter(character, index));
+        }
+    }
+    Ok(value)
+}
+
+// The value of a character of the alphabet.
+fn digit(character: char) -> Option<u8> {
+    match character {
+        'A'..='Z' => Some(character as u8 - b'A'),
+        'a'..='z' => Some(character as u8 - b'a' + 26),
+        '0'..='9' => Some(character as u8 - b'0' + 52),
+        '-' => Some(62),
+        '_' => Some(63),
+        _ => None,
+    }
+}
+
+// Any number of bytes in the same alphabet, for signed tokens. The bits are
+// taken in order, most significant first, and the last character is padded
+// out with zero bits.
+#[cfg(feature = "signed-tokens")]
+pub(crate) fn encode_bytes(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity((bytes.len() * 8).div_ceil(6));
+    let (mut bits, mut count) = (0u32, 0);
+    for &byte in bytes {
+        bits = bits << 8 | u32::from(byte);
+        count += 8;
+        while count >= 6 {
+            count -= 6;
+            text.push(ALPHABET[(bits >> count) as usize & 0x3f] as char);
+        }
+    }
+    if count > 0 {
+        text.push(ALPHABET[(bits << (6 - count)) as usize & 0x3f] as char);
+    }
+    text
+}
+
+// The padding bits must be zero, and a lone character left over can't make a
+// byte, so that each run of bytes has exactly one encoding.
+#[cfg(feature = "signed-tokens")]
+pub(crate) fn decode_bytes(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len() * 6 / 8);
+    let (mut bits, mut count) = (0u32, 0);
+    for character in text.chars() {
+        bits = bits << 6 | u32::from(digit(character)?);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    if count < 6 && bits & ((1 << count) - 1) == 0 {
+        Some(bytes)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,216 @@
+// Keys branded with the type of the vault they belong to. TypedVault is a
+// thin wrapper around a Vault that hands out and takes only TypedKeys of its
+// item type, so mixing up keys of vaults of different types is caught at
+// compile time. TypedKey holds a PhantomData of fn() -> T, which keeps it
+// Copy, Send and Sync whatever T is; the brand costs nothing at run time.
+
+use crate::{Vault, VaultKey};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+
+/// A `VaultKey` for an item of type `T`, as handed out by a `TypedVault<T>`. A `TypedVault` only takes keys of its own item type, so a key for one type of item can't be used with a vault of another by mistake:
+/// 
+/// ```rust,compile_fail
+/// # use bank_vault::TypedVault;
+/// struct Session;
+/// struct Order;
+/// 
+/// let sessions = TypedVault::<Session>::new();
+/// let orders = TypedVault::<Order>::new();
+/// let session = sessions.add(Session);
+/// 
+/// orders.remove(&session);
+/// ```
+pub struct TypedKey<T> {
+    key: VaultKey,
+    item: PhantomData<fn() -> T>,
+}
+
+impl<T> TypedKey<T> {
+    /// Brands an untyped key as the key of an item of type `T`. Nothing checks that there is such an item under the key.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::{TypedKey, TypedVault, VaultKey};
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = TypedVault::<i32>::new();
+    /// let key = vault.add(1).untyped();
+    /// 
+    /// assert_eq!(Some(1), vault.get_clone(&TypedKey::from_untyped(key)));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn from_untyped(key: VaultKey) -> TypedKey<T> {
+        TypedKey {key, item: PhantomData}
+    }
+
+    /// Returns the untyped key, for use with a plain `Vault`.
+    /// # Example
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use bank_vault::TypedVault;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// let vault = TypedVault::<i32>::new();
+    /// let key = vault.add(1);
+    /// 
+    /// assert_eq!(Some(1), vault.as_untyped().get_clone(&key.untyped()));
+    /// #     Ok(())
+    /// # }
+    /// ```
+    pub fn untyped(&self) -> VaultKey {
+        self.key
+    }
+}
+
+// Implemented by hand, as deriving would require T to implement the traits
+// too.
+impl<T> Clone for TypedKey<T> {
+    fn clone(&self) -> TypedKey<T> {
+        *self
+    }
+}
+
+impl<T> Copy for TypedKey<T> {}
+
+impl<T> PartialEq for TypedKey<T> {
+    fn eq(&self, other: &TypedKey<T>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<T> Eq for TypedKey<T> {}
+
+impl<T> PartialOrd for TypedKey<T> {
+    fn partial_cmp(&self, other: &TypedKey<T>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for TypedKey<T> {
+    fn cmp(&self, other: &TypedKey<T>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+impl<T> Hash for TypedKey<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state)
+    }
+}
+
+impl<T> fmt::Debug for TypedKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TypedKey").field(&self.key).finish()
+    }
+}
+
+/// A `Vault` whose keys carry the type of its items: it hands out and takes only `TypedKey<T>`s. The untyped vault is available through `as_untyped`, for the methods this wrapper doesn't offer.
+/// # Example
+/// ```rust
+/// # use std::error::Error;
+/// # use bank_vault::TypedVault;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// struct Order {
+///     total: u32,
+/// }
+/// 
+/// let orders = TypedVault::new();
+/// let key = orders.add(Order {total: 10});
+/// 
+/// assert_eq!(Some(10), orders.with_item(&key, |order| order.total));
+/// #     Ok(())
+/// # }
+/// ```
+pub struct TypedVault<T> {
+    vault: Vault<T>,
+}
+
+impl<T> TypedVault<T> {
+    /// Creates a new, empty TypedVault instance.
+    pub fn new() -> TypedVault<T> {
+        TypedVault {vault: Vault::new()}
+    }
+
+    /// Returns the untyped vault behind this one.
+    pub fn as_untyped(&self) -> &Vault<T> {
+        &self.vault
+    }
+
+    /// Consumes this vault and returns the untyped vault behind it.
+    pub fn into_untyped(self) -> Vault<T> {
+        self.vault
+    }
+
+    /// Adds an object to the vault and returns its key, like `Vault::add`.
+    pub fn add(&self, to_add: T) -> TypedKey<T> {
+        TypedKey::from_untyped(self.vault.add(to_add))
+    }
+
+    /// Adds an object to the vault under the provided key, like `Vault::add_with_key`. Returns false, without adding it, if the key is already in use.
+    pub fn add_with_key(&self, to_add: T, key: &TypedKey<T>) -> bool {
+        self.vault.add_with_key(to_add, &key.key)
+    }
+
+    /// Removes and returns the stored object with a matching key, like `Vault::remove`.
+    pub fn remove(&self, key: &TypedKey<T>) -> Option<T> {
+        self.vault.remove(&key.key)
+    }
+
+    /// Returns whether there exists an item in the vault with the provided key, like `Vault::has_item`.
+    pub fn has_item(&self, key: &TypedKey<T>) -> bool {
+        self.vault.has_item(&key.key)
+    }
+
+    /// Returns a clone of the stored object with a matching key, like `Vault::get_clone`.
+    pub fn get_clone(&self, key: &TypedKey<T>) -> Option<T>
+            where T: Clone {
+        self.vault.get_clone(&key.key)
+    }
+
+    /// Applies the operation to a reference to the stored object with a matching key, like `Vault::with_item`.
+    pub fn with_item<R, F>(&self, key: &TypedKey<T>, operation: F) -> Option<R>
+            where F: FnOnce(&T) -> R {
+        self.vault.with_item(&key.key, operation)
+    }
+
+    /// Applies the operation to a mutable reference to the stored object with a matching key, like `Vault::with_item_mut`.
+    pub fn with_item_mut<R, F>(&self, key: &TypedKey<T>, operation: F) -> Option<R>
+            where F: FnOnce(&mut T) -> R {
+        self.vault.with_item_mut(&key.key, operation)
+    }
+
+    /// Updates an item in the vault with the specified key by applying the operation to it, like `Vault::update_item`.
+    pub fn update_item<F>(&self, key: &TypedKey<T>, operation: F) -> bool
+            where F: FnOnce(&mut T) {
+        self.vault.update_item(&key.key, operation)
+    }
+
+    /// Returns the keys of every item in the vault, in no particular order.
+    pub fn keys(&self) -> Vec<TypedKey<T>> {
+        self.vault.keys().into_iter().map(TypedKey::from_untyped).collect()
+    }
+
+    /// Returns the number of items in the vault.
+    pub fn len(&self) -> usize {
+        self.vault.len()
+    }
+
+    /// Returns whether the vault holds no items.
+    pub fn is_empty(&self) -> bool {
+        self.vault.is_empty()
+    }
+}
+
+impl<T> Default for TypedVault<T> {
+    fn default() -> TypedVault<T> {
+        TypedVault::new()
+    }
+}
+
+impl<T> From<Vault<T>> for TypedVault<T> {
+    fn from(vault: Vault<T>) -> TypedVault<T> {
+        TypedVault {vault}
+    }
+}
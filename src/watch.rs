@@ -0,0 +1,120 @@
+// Lets threads wait for the item under a key to change. Methods that change
+// an item tell the vault's Watches, which wakes the threads waiting on that
+// key; a woken thread looks at the vault again itself, so a wakeup only means
+// the key may have changed. Keys nobody waits on aren't tracked, and while
+// nobody waits at all, telling costs a single atomic load.
+
+use crate::VaultKey;
+use std::collections::HashMap;
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+pub(crate) struct Watches {
+    waiting: AtomicUsize,
+    versions: Mutex<HashMap<VaultKey, Watched>>,
+    changed: Condvar,
+}
+
+// How many threads wait on a key, and how many times it has changed since
+// the first of them began.
+struct Watched {
+    waiters: usize,
+    version: u64,
+}
+
+// Registers the waiting thread for as long as it's alive, so that a wait
+// left by a timeout or a panic in its check is always unregistered.
+struct Waiting<'a> {
+    watches: &'a Watches,
+    key: VaultKey,
+}
+
+impl<'a> Drop for Waiting<'a> {
+    fn drop(&mut self) {
+        let mut versions = self.watches.versions();
+        let watched = versions.get_mut(&self.key).expect("waiting key is watched");
+        watched.waiters -= 1;
+        if watched.waiters == 0 {
+            versions.remove(&self.key);
+        }
+        self.watches.waiting.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Watches {
+    pub(crate) fn new() -> Watches {
+        Watches {
+            waiting: AtomicUsize::new(0),
+            versions: Mutex::new(HashMap::new()),
+            changed: Condvar::new(),
+        }
+    }
+
+    fn versions(&self) -> MutexGuard<'_, HashMap<VaultKey, Watched>> {
+        self.versions.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Called once the key's change is made. The fence pairs with a waiter's
+    // registration so that either the waiter's check sees the change or this
+    // sees the waiter.
+    pub(crate) fn changed(&self, key: &VaultKey) {
+        atomic::fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        if let Some(watched) = self.versions().get_mut(key) {
+            watched.version += 1;
+            self.changed.notify_all();
+        }
+    }
+
+    pub(crate) fn changed_all(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.waiting.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        for watched in self.versions().values_mut() {
+            watched.version += 1;
+        }
+        self.changed.notify_all();
+    }
+
+    // Calls check, and again each time the key changes, until it returns
+    // Some or the timeout runs out. check runs without the watches locked,
+    // so it's free to lock the vault.
+    pub(crate) fn wait<R, F>(&self, key: &VaultKey, timeout: Option<Duration>, mut check: F) -> Option<R>
+            where F: FnMut() -> Option<R> {
+        let start = Instant::now();
+        let mut seen = {
+            let mut versions = self.versions();
+            self.waiting.fetch_add(1, Ordering::SeqCst);
+            let watched = versions.entry(*key).or_insert(Watched {waiters: 0, version: 0});
+            watched.waiters += 1;
+            watched.version
+        };
+        let _waiting = Waiting {watches: self, key: *key};
+        atomic::fence(Ordering::SeqCst);
+        loop {
+            if let Some(result) = check() {
+                return Some(result);
+            }
+            let mut versions = self.versions();
+            // Spurious wakeups leave the version as it was, and go back to
+            // waiting.
+            while versions[key].version == seen {
+                versions = match timeout {
+                    None => self.changed.wait(versions).unwrap_or_else(PoisonError::into_inner),
+                    Some(timeout) => {
+                        let left = timeout.saturating_sub(start.elapsed());
+                        if left.is_zero() {
+                            return None;
+                        }
+                        self.changed.wait_timeout(versions, left).unwrap_or_else(PoisonError::into_inner).0
+                    }
+                };
+            }
+            seen = versions[key].version;
+        }
+    }
+}